@@ -0,0 +1,7 @@
+#!/bin/bash
+set -e
+
+# builds every contract of the workspace into ../out
+for contract in masterchef-staking mock-dao mock-ft mock-ref; do
+    (cd "$contract" && ./build.sh)
+done
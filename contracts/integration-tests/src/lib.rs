@@ -0,0 +1,5 @@
+//! Sandbox tests for the staking contract against the mock DAO, token and exchange contracts,
+//! see `tests/`. Build the contracts with `contracts/build.sh` first, the tests deploy the wasm
+//! files from `out/` at the repository root. They only compile with the `sandbox` feature:
+//! `cargo test -p integration_tests --features sandbox`. The feature downloads `near-sandbox` at
+//! build time unless `NEAR_SANDBOX_BIN_PATH` points at a local binary, see `README.md`.
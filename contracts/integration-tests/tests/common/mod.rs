@@ -0,0 +1,200 @@
+//! Deployment helpers shared by the sandbox tests.
+#![allow(dead_code)]
+
+use near_workspaces::network::Sandbox;
+use near_workspaces::result::ExecutionFinalResult;
+use near_workspaces::types::NearToken;
+use near_workspaces::{Account, AccountId, Contract, Worker};
+use serde_json::json;
+
+pub const STAKING_WASM: &str = "../../out/masterchef.wasm";
+pub const DAO_WASM: &str = "../../out/mock_dao.wasm";
+pub const FT_WASM: &str = "../../out/mock_ft.wasm";
+pub const REF_WASM: &str = "../../out/mock_ref.wasm";
+
+/// the LP shares staked in the test farms
+pub const POOL_TOKEN_ID: &str = ":0";
+
+pub async fn deploy(worker: &Worker<Sandbox>, path: &str) -> anyhow::Result<Contract> {
+    let wasm = std::fs::read(path)
+        .map_err(|err| anyhow::anyhow!("{}: {}, run contracts/build.sh first", path, err))?;
+    Ok(worker.dev_deploy(&wasm).await?)
+}
+
+/// Calls `method_name` with the one yocto every state-changing staking method asks for.
+pub async fn call(
+    account: &Account,
+    contract_id: &AccountId,
+    method_name: &str,
+    args: serde_json::Value,
+) -> anyhow::Result<ExecutionFinalResult> {
+    Ok(account
+        .call(contract_id, method_name)
+        .args_json(args)
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?)
+}
+
+pub async fn ft_balance_of(token: &Contract, account_id: &AccountId) -> anyhow::Result<u128> {
+    let balance: String = token
+        .view("ft_balance_of")
+        .args_json(json!({ "account_id": account_id }))
+        .await?
+        .json()?;
+    Ok(balance.parse()?)
+}
+
+pub async fn mft_balance_of(exchange: &Contract, account_id: &AccountId) -> anyhow::Result<u128> {
+    let balance: String = exchange
+        .view("mft_balance_of")
+        .args_json(json!({ "token_id": POOL_TOKEN_ID, "account_id": account_id }))
+        .await?
+        .json()?;
+    Ok(balance.parse()?)
+}
+
+/// A staking contract wired to a mock UNET token, a mock swap token and a mock Ref exchange,
+/// with two farm managers.
+pub struct Env {
+    pub worker: Worker<Sandbox>,
+    pub staking: Contract,
+    pub unet: Contract,
+    pub usdc: Contract,
+    pub exchange: Contract,
+    pub owner: Account,
+    pub managers: [Account; 2],
+}
+
+impl Env {
+    pub async fn new() -> anyhow::Result<Self> {
+        let worker = near_workspaces::sandbox().await?;
+        let staking = deploy(&worker, STAKING_WASM).await?;
+        let unet = deploy(&worker, FT_WASM).await?;
+        let usdc = deploy(&worker, FT_WASM).await?;
+        let exchange = deploy(&worker, REF_WASM).await?;
+        let owner = worker.dev_create_account().await?;
+        let managers = [worker.dev_create_account().await?, worker.dev_create_account().await?];
+
+        staking
+            .call("new")
+            .args_json(json!({ "owner_id": owner.id() }))
+            .transact()
+            .await?
+            .into_result()?;
+        for contract in [&unet, &usdc, &exchange] {
+            contract.call("new").transact().await?.into_result()?;
+        }
+        call(
+            &owner,
+            staking.id(),
+            "set_external_contracts",
+            json!({ "unet_token_id": unet.id(), "ref_exchange_id": exchange.id() }),
+        )
+        .await?
+        .into_result()?;
+        for manager in &managers {
+            call(
+                &owner,
+                staking.id(),
+                "grant_role",
+                json!({ "role": "FarmManager", "account_id": manager.id() }),
+            )
+            .await?
+            .into_result()?;
+        }
+
+        Ok(Self {
+            worker,
+            staking,
+            unet,
+            usdc,
+            exchange,
+            owner,
+            managers,
+        })
+    }
+
+    /// Saves the farm with the first manager and confirms it with the second.
+    pub async fn create_farm(&self, farm: serde_json::Value) -> anyhow::Result<()> {
+        call(&self.managers[0], self.staking.id(), "save_editing_farm_info", farm)
+            .await?
+            .into_result()?;
+        call(&self.managers[1], self.staking.id(), "confirm_new_farm", json!({}))
+            .await?
+            .into_result()?;
+        Ok(())
+    }
+
+    /// A UNET token farm paying one UNET per second, with rewards unlocked right away.
+    pub fn token_farm(&self) -> serde_json::Value {
+        json!({
+            "farm_type": 0,
+            "token_id": self.unet.id(),
+            "token_decimal": 18,
+            "pool_id": 0,
+            "token_reward_rate": "1000000000000000000",
+            "pool_reward_rate": "0",
+            "reward_reward_rate": "0",
+            "token_weight_rate": 1000,
+            "pool_weight_rate": 0,
+            "max_token_vesting_duration": 1000000,
+            "max_reward_vesting_duration": 0,
+            "starting_at": 0,
+            "ending_at": 100000000000000u64,
+        })
+    }
+
+    /// A farm of the `POOL_TOKEN_ID` shares paying its rewards in UNET.
+    pub fn lp_farm(&self) -> serde_json::Value {
+        json!({
+            "farm_type": 1,
+            "token_id": self.unet.id(),
+            "token_decimal": 18,
+            "pool_id": 0,
+            "token_reward_rate": "0",
+            "pool_reward_rate": "1000000000000000000",
+            "reward_reward_rate": "0",
+            "token_weight_rate": 0,
+            "pool_weight_rate": 1000,
+            "max_token_vesting_duration": 1000000,
+            "max_reward_vesting_duration": 0,
+            "starting_at": 0,
+            "ending_at": 100000000000000u64,
+        })
+    }
+
+    /// Creates a staker holding `amount` UNET and `amount` LP shares.
+    pub async fn staker(&self, amount: u128) -> anyhow::Result<Account> {
+        let account = self.worker.dev_create_account().await?;
+        self.unet
+            .call("mint")
+            .args_json(json!({ "account_id": account.id(), "amount": amount.to_string() }))
+            .transact()
+            .await?
+            .into_result()?;
+        self.exchange
+            .call("mint")
+            .args_json(json!({
+                "token_id": POOL_TOKEN_ID,
+                "account_id": account.id(),
+                "amount": amount.to_string(),
+            }))
+            .transact()
+            .await?
+            .into_result()?;
+        Ok(account)
+    }
+
+    /// Mints UNET to the staking contract to pay rewards and swaps from.
+    pub async fn fund_rewards(&self, amount: u128) -> anyhow::Result<()> {
+        self.unet
+            .call("mint")
+            .args_json(json!({ "account_id": self.staking.id(), "amount": amount.to_string() }))
+            .transact()
+            .await?
+            .into_result()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+#![cfg(feature = "sandbox")]
+
+mod common;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use common::{deploy, DAO_WASM, STAKING_WASM};
+use near_workspaces::types::NearToken;
+use near_workspaces::{Account, Contract};
+use serde_json::json;
+
+/// Adds a function-call proposal batching `actions` to the staking contract and approves it
+/// with every given council member.
+async fn pass_proposal(
+    dao: &Contract,
+    council: &[&Account],
+    staking: &Contract,
+    actions: serde_json::Value,
+) -> anyhow::Result<()> {
+    let id: u64 = council[0]
+        .call(dao.id(), "add_proposal")
+        .args_json(json!({
+            "proposal": {
+                "description": "staking admin batch",
+                "kind": { "FunctionCall": { "receiver_id": staking.id(), "actions": actions } },
+            }
+        }))
+        .transact()
+        .await?
+        .json()?;
+    for member in council {
+        member
+            .call(dao.id(), "act_proposal")
+            .args_json(json!({ "id": id, "action": "VoteApprove" }))
+            .max_gas()
+            .transact()
+            .await?
+            .into_result()?;
+    }
+    Ok(())
+}
+
+fn action(method_name: &str, args: serde_json::Value) -> serde_json::Value {
+    json!({
+        "method_name": method_name,
+        "args": STANDARD.encode(args.to_string()),
+        "deposit": "1",
+        "gas": "30000000000000",
+    })
+}
+
+#[tokio::test]
+async fn dao_takes_ownership_and_creates_a_farm_in_one_proposal() -> anyhow::Result<()> {
+    let worker = near_workspaces::sandbox().await?;
+    let staking = deploy(&worker, STAKING_WASM).await?;
+    let dao = deploy(&worker, DAO_WASM).await?;
+    let owner = worker.dev_create_account().await?;
+    let alice = worker.dev_create_account().await?;
+    let bob = worker.dev_create_account().await?;
+    let manager = worker.dev_create_account().await?;
+
+    staking
+        .call("new")
+        .args_json(json!({ "owner_id": owner.id() }))
+        .transact()
+        .await?
+        .into_result()?;
+    dao.call("new")
+        .args_json(json!({ "council": [alice.id(), bob.id()], "threshold": 2 }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    // a second farm manager would otherwise have to confirm every new farm
+    owner
+        .call(staking.id(), "grant_role")
+        .args_json(json!({ "role": "FarmManager", "account_id": manager.id() }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+    owner
+        .call(staking.id(), "propose_owner")
+        .args_json(json!({ "new_owner_id": dao.id() }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?
+        .into_result()?;
+
+    pass_proposal(
+        &dao,
+        &[&alice, &bob],
+        &staking,
+        json!([
+            action("accept_ownership", json!({})),
+            action("set_dao_mode", json!({ "enabled": true })),
+            action(
+                "save_editing_farm_info",
+                json!({
+                    "farm_type": 0,
+                    "token_id": "unet.testnet",
+                    "token_decimal": 18,
+                    "pool_id": 0,
+                    "token_reward_rate": "1000000000000000000",
+                    "pool_reward_rate": "0",
+                    "reward_reward_rate": "0",
+                    "token_weight_rate": 1000,
+                    "pool_weight_rate": 0,
+                    "max_token_vesting_duration": 0,
+                    "max_reward_vesting_duration": 0,
+                    "starting_at": 0,
+                    "ending_at": 100000000,
+                }),
+            ),
+        ]),
+    )
+    .await?;
+
+    let owner_id: Option<String> = staking.view("get_owner").await?.json()?;
+    assert_eq!(owner_id.as_deref(), Some(dao.id().as_str()));
+    let dao_mode: bool = staking.view("is_dao_mode").await?.json()?;
+    assert!(dao_mode);
+    let farm_length: String = staking.view("get_farm_length").await?.json()?;
+    assert_eq!(farm_length, "1");
+
+    // the previous owner lost every owner-only action
+    let result = owner
+        .call(staking.id(), "set_dao_mode")
+        .args_json(json!({ "enabled": false }))
+        .deposit(NearToken::from_yoctonear(1))
+        .transact()
+        .await?;
+    assert!(result.is_failure());
+    Ok(())
+}
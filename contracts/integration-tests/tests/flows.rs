@@ -0,0 +1,230 @@
+#![cfg(feature = "sandbox")]
+
+mod common;
+
+use common::*;
+use near_workspaces::types::NearToken;
+use serde_json::json;
+
+const STAKE: u128 = 100_000_000_000_000_000_000;
+const REWARDS: u128 = 1_000_000_000_000_000_000_000_000;
+
+fn stake_msg(stake_type: u8) -> String {
+    json!({ "stake_type": stake_type, "farm_id": 0, "lock_duration": 0 }).to_string()
+}
+
+/// amount of the unlocked reward position created by the last claim
+async fn claimed_reward(env: &Env, account: &near_workspaces::Account) -> anyhow::Result<u128> {
+    let positions: Vec<serde_json::Value> = env
+        .staking
+        .view("get_positions")
+        .args_json(json!({ "farm_id": 0, "account_id": account.id() }))
+        .await?
+        .json()?;
+    let reward = positions
+        .iter()
+        .find(|position| position["kind"] == "Reward")
+        .ok_or_else(|| anyhow::anyhow!("no reward position"))?;
+    Ok(reward["amount"].as_str().unwrap().parse()?)
+}
+
+#[tokio::test]
+async fn token_stake_is_claimed_and_unstaked() -> anyhow::Result<()> {
+    let env = Env::new().await?;
+    env.create_farm(env.token_farm()).await?;
+    env.fund_rewards(REWARDS).await?;
+    let alice = env.staker(STAKE).await?;
+
+    alice
+        .call(env.unet.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": env.staking.id(),
+            "amount": STAKE.to_string(),
+            "msg": stake_msg(0),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(ft_balance_of(&env.unet, alice.id()).await?, 0);
+    assert_eq!(ft_balance_of(&env.unet, env.staking.id()).await?, REWARDS + STAKE);
+
+    env.worker.fast_forward(20).await?;
+    call(&alice, env.staking.id(), "claim_reward", json!({ "farm_id": 0, "token_type": 0 }))
+        .await?
+        .into_result()?;
+    let reward = claimed_reward(&env, &alice).await?;
+    assert!(reward > 0);
+
+    call(
+        &alice,
+        env.staking.id(),
+        "unstake",
+        json!({ "farm_id": 0, "token_type": 2, "amount": reward.to_string() }),
+    )
+    .await?
+    .into_result()?;
+    assert_eq!(ft_balance_of(&env.unet, alice.id()).await?, reward);
+
+    call(
+        &alice,
+        env.staking.id(),
+        "unstake",
+        json!({ "farm_id": 0, "token_type": 0, "amount": STAKE.to_string() }),
+    )
+    .await?
+    .into_result()?;
+    assert_eq!(ft_balance_of(&env.unet, alice.id()).await?, reward + STAKE);
+    assert_eq!(ft_balance_of(&env.unet, env.staking.id()).await?, REWARDS - reward);
+    Ok(())
+}
+
+#[tokio::test]
+async fn lp_shares_are_staked_through_the_exchange_and_returned() -> anyhow::Result<()> {
+    let env = Env::new().await?;
+    env.create_farm(env.lp_farm()).await?;
+    env.fund_rewards(REWARDS).await?;
+    let alice = env.staker(STAKE).await?;
+
+    alice
+        .call(env.exchange.id(), "mft_transfer_call")
+        .args_json(json!({
+            "token_id": POOL_TOKEN_ID,
+            "receiver_id": env.staking.id(),
+            "amount": STAKE.to_string(),
+            "msg": stake_msg(1),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(mft_balance_of(&env.exchange, alice.id()).await?, 0);
+    assert_eq!(mft_balance_of(&env.exchange, env.staking.id()).await?, STAKE);
+
+    env.worker.fast_forward(20).await?;
+    call(&alice, env.staking.id(), "claim_reward", json!({ "farm_id": 0, "token_type": 1 }))
+        .await?
+        .into_result()?;
+    let reward = claimed_reward(&env, &alice).await?;
+    assert!(reward > 0);
+
+    call(
+        &alice,
+        env.staking.id(),
+        "unstake",
+        json!({ "farm_id": 0, "token_type": 1, "amount": STAKE.to_string() }),
+    )
+    .await?
+    .into_result()?;
+    call(
+        &alice,
+        env.staking.id(),
+        "unstake",
+        json!({ "farm_id": 0, "token_type": 2, "amount": reward.to_string() }),
+    )
+    .await?
+    .into_result()?;
+    assert_eq!(mft_balance_of(&env.exchange, alice.id()).await?, STAKE);
+    assert_eq!(mft_balance_of(&env.exchange, env.staking.id()).await?, 0);
+    // the staker's own UNET was never staked
+    assert_eq!(ft_balance_of(&env.unet, alice.id()).await?, STAKE + reward);
+    Ok(())
+}
+
+#[tokio::test]
+async fn swapped_tokens_vest_into_unet() -> anyhow::Result<()> {
+    let env = Env::new().await?;
+    env.fund_rewards(REWARDS).await?;
+    call(
+        &env.owner,
+        env.staking.id(),
+        "save_swap_farm",
+        json!({
+            "token_id": env.usdc.id(),
+            "swap_rate": "100000000",
+            "decimal": 18,
+            "min_lock_time": 0,
+            "max_lock_time": 1000000000,
+        }),
+    )
+    .await?
+    .into_result()?;
+    let alice = env.worker.dev_create_account().await?;
+    env.usdc
+        .call("mint")
+        .args_json(json!({ "account_id": alice.id(), "amount": STAKE.to_string() }))
+        .transact()
+        .await?
+        .into_result()?;
+
+    alice
+        .call(env.usdc.id(), "ft_transfer_call")
+        .args_json(json!({
+            "receiver_id": env.staking.id(),
+            "amount": STAKE.to_string(),
+            "msg": stake_msg(1),
+        }))
+        .deposit(NearToken::from_yoctonear(1))
+        .max_gas()
+        .transact()
+        .await?
+        .into_result()?;
+    assert_eq!(ft_balance_of(&env.usdc, alice.id()).await?, 0);
+    assert_eq!(ft_balance_of(&env.usdc, env.staking.id()).await?, STAKE);
+
+    env.worker.fast_forward(20).await?;
+    let vested: String = env
+        .staking
+        .view("get_swap_unstake_amount_by_id")
+        .args_json(json!({ "token_id": env.usdc.id(), "account_id": alice.id(), "id": 0 }))
+        .await?
+        .json()?;
+    let vested: u128 = vested.parse()?;
+    assert!(vested > 0 && vested < STAKE, "vested {} of {}", vested, STAKE);
+
+    call(
+        &alice,
+        env.staking.id(),
+        "unstake_swap",
+        json!({
+            "token_id": env.usdc.id(),
+            "account_id": alice.id(),
+            "id": 0,
+            "amount_2_unstake": vested.to_string(),
+        }),
+    )
+    .await?
+    .into_result()?;
+    assert_eq!(ft_balance_of(&env.unet, alice.id()).await?, vested);
+    assert_eq!(ft_balance_of(&env.unet, env.staking.id()).await?, REWARDS - vested);
+    Ok(())
+}
+
+#[tokio::test]
+async fn new_farm_needs_every_farm_manager() -> anyhow::Result<()> {
+    let env = Env::new().await?;
+    call(&env.managers[0], env.staking.id(), "save_editing_farm_info", env.token_farm())
+        .await?
+        .into_result()?;
+    let farm_length: String = env.staking.view("get_farm_length").await?.json()?;
+    assert_eq!(farm_length, "0");
+
+    let outsider = env.worker.dev_create_account().await?;
+    let result = call(&outsider, env.staking.id(), "confirm_new_farm", json!({})).await?;
+    assert!(result.is_failure());
+
+    call(&env.managers[1], env.staking.id(), "confirm_new_farm", json!({}))
+        .await?
+        .into_result()?;
+    let farm_length: String = env.staking.view("get_farm_length").await?.json()?;
+    assert_eq!(farm_length, "1");
+    let confirmed: Vec<String> = env
+        .staking
+        .view("get_confirmed_admins_for_new_farm")
+        .await?
+        .json()?;
+    assert!(confirmed.is_empty());
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use crate::*;
+
+/// Who may stake into a farm. `Merkle` farms take a proof that sha256 of the account id is a
+/// leaf of the tree, with each pair of nodes hashed in sorted order.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub enum FarmAccess {
+    Open,
+    Allowlist,
+    Merkle { root: [u8; 32] },
+}
+
+/// JSON form of `FarmAccess`, hashes are hex encoded
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum FarmAccessView {
+    Open,
+    Allowlist,
+    Merkle { root: String },
+}
+
+fn decode_hash(hex: &str) -> [u8; 32] {
+    assert!(
+        hex.len() == 64 && hex.is_ascii(),
+        "Hash must be 32 hex encoded bytes"
+    );
+    let mut hash = [0u8; 32];
+    for (index, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).expect("Invalid hex in hash");
+    }
+    hash
+}
+
+fn encode_hash(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn merkle_verify(root: &[u8; 32], account_id: &AccountId, proof: &[String]) -> bool {
+    let mut node = env::sha256(account_id.as_bytes());
+    for sibling in proof {
+        let sibling = decode_hash(sibling).to_vec();
+        node = if node <= sibling {
+            env::sha256(&[node, sibling].concat())
+        } else {
+            env::sha256(&[sibling, node].concat())
+        };
+    }
+    node[..] == root[..]
+}
+
+impl Contract {
+    pub(crate) fn internal_is_eligible(
+        &self,
+        farm_info: &FarmInfo,
+        account_id: &AccountId,
+        proof: Option<&[String]>,
+    ) -> bool {
+        match &farm_info.access {
+            FarmAccess::Open => true,
+            FarmAccess::Allowlist => farm_info.allowlist.contains(account_id),
+            FarmAccess::Merkle { root } => {
+                matches!(proof, Some(proof) if merkle_verify(root, account_id, proof))
+            }
+        }
+    }
+
+    pub(crate) fn internal_set_farm_access(&mut self, farm_id: u16, access: FarmAccessView) {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        farm_info.access = match access {
+            FarmAccessView::Open => FarmAccess::Open,
+            FarmAccessView::Allowlist => FarmAccess::Allowlist,
+            FarmAccessView::Merkle { root } => FarmAccess::Merkle { root: decode_hash(&root) },
+        };
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+
+    /// Whether the account may be handed a position of the farm. Transfers carry no proof, so on
+    /// Merkle farms only accounts that already proved themselves with a stake can receive one.
+    pub(crate) fn internal_may_receive(&self, farm_info: &FarmInfo, account_id: &AccountId) -> bool {
+        match &farm_info.access {
+            FarmAccess::Merkle { .. } => farm_info.stake_infos.get(account_id).is_some(),
+            _ => self.internal_is_eligible(farm_info, account_id, None),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires the `FarmManager` role. Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_farm_access(&mut self, farm_id: u16, access: FarmAccessView) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.internal_set_farm_access(farm_id, access);
+    }
+
+    /// Requires the `FarmManager` role.
+    #[payable]
+    pub fn add_to_farm_allowlist(&mut self, farm_id: u16, account_ids: Vec<AccountId>) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        for account_id in account_ids {
+            farm_info.allowlist.insert(&account_id);
+        }
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+
+    /// Requires the `FarmManager` role.
+    #[payable]
+    pub fn remove_from_farm_allowlist(&mut self, farm_id: u16, account_ids: Vec<AccountId>) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        for account_id in account_ids {
+            farm_info.allowlist.remove(&account_id);
+        }
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+
+    pub fn get_farm_access(&self, farm_id: u16) -> FarmAccessView {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        match &farm_info.access {
+            FarmAccess::Open => FarmAccessView::Open,
+            FarmAccess::Allowlist => FarmAccessView::Allowlist,
+            FarmAccess::Merkle { root } => FarmAccessView::Merkle { root: encode_hash(root) },
+        }
+    }
+
+    pub fn get_farm_allowlist(&self, farm_id: u16, from_index: u64, limit: u64) -> Vec<AccountId> {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let account_ids = farm_info.allowlist.as_vector();
+        (from_index..min(from_index + limit, account_ids.len()))
+            .map(|index| account_ids.get(index).unwrap())
+            .collect()
+    }
+
+    /// Whether the account may stake into the farm, `proof` is only used by Merkle farms.
+    pub fn is_eligible(&self, farm_id: u16, account_id: AccountId, proof: Option<Vec<String>>) -> bool {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        self.internal_is_eligible(&farm_info, &account_id, proof.as_deref())
+    }
+}
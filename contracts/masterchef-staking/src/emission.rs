@@ -0,0 +1,207 @@
+use crate::*;
+
+/// rate multipliers of the emission schedules are expressed in basis points, 10000 keeps the farm's rate
+pub const EMISSION_MULTIPLIER_DENOMINATOR: u128 = 10000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EmissionPoint {
+    pub timestamp: u64,
+    pub rate_multiplier: u32,
+}
+
+/// Shape of a farm's emission over time, applied to its token, pool and reward-on-reward rates.
+/// `Halving` halves the rates every `period` ms from the farm start. `Piecewise` interpolates
+/// linearly between its points and holds the first and last multiplier outside of them.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum EmissionSchedule {
+    Constant,
+    Halving { period: u64 },
+    Piecewise { points: Vec<EmissionPoint> },
+}
+
+impl EmissionSchedule {
+    pub fn assert_valid(&self) {
+        match self {
+            EmissionSchedule::Constant => {}
+            EmissionSchedule::Halving { period } => {
+                assert!(
+                    *period >= 1000 && period % 1000 == 0,
+                    "Halving period must be a whole number of seconds"
+                );
+            }
+            EmissionSchedule::Piecewise { points } => {
+                assert!(!points.is_empty(), "Schedule needs at least one point");
+                assert!(
+                    points.windows(2).all(|pair| pair[0].timestamp / 1000 < pair[1].timestamp / 1000),
+                    "Schedule points must be at least a second apart and in order"
+                );
+            }
+        }
+    }
+
+    /// emission per second of a farm with `rate` and started at `starting_at`, at `timestamp`,
+    /// counted in whole seconds like `emitted`
+    pub fn rate_at(&self, rate: u128, starting_at: u64, timestamp: u64) -> u128 {
+        match self {
+            EmissionSchedule::Constant => rate,
+            EmissionSchedule::Halving { period } => {
+                let halvings = (timestamp / 1000).saturating_sub(starting_at / 1000) / (period / 1000);
+                if halvings >= 128 {
+                    0
+                } else {
+                    rate >> halvings
+                }
+            }
+            EmissionSchedule::Piecewise { points } => {
+                let time = timestamp / 1000;
+                let first = &points[0];
+                let last = &points[points.len() - 1];
+                let multiplier = if time <= first.timestamp / 1000 {
+                    u128::from(first.rate_multiplier)
+                } else if time >= last.timestamp / 1000 {
+                    u128::from(last.rate_multiplier)
+                } else {
+                    let index = points.iter().position(|point| point.timestamp / 1000 > time).unwrap();
+                    Self::interpolate(&points[index - 1], &points[index], time)
+                };
+                mul_div(rate, multiplier, EMISSION_MULTIPLIER_DENOMINATOR)
+            }
+        }
+    }
+
+    /// multiplier on the line between two points at `time` (seconds)
+    fn interpolate(start: &EmissionPoint, end: &EmissionPoint, time: u64) -> u128 {
+        let length = u128::from(end.timestamp / 1000 - start.timestamp / 1000);
+        let offset = u128::from(time - start.timestamp / 1000);
+        let (start_multiplier, end_multiplier) = (u128::from(start.rate_multiplier), u128::from(end.rate_multiplier));
+        if end_multiplier >= start_multiplier {
+            start_multiplier + (end_multiplier - start_multiplier) * offset / length
+        } else {
+            start_multiplier - (start_multiplier - end_multiplier) * offset / length
+        }
+    }
+
+    /// Total emission of a farm with `rate` over the whole seconds from `from` to `to` (ms).
+    /// Integrated exactly over every halving or breakpoint inside the range.
+    pub fn emitted(&self, rate: u128, starting_at: u64, from: u64, to: u64) -> u128 {
+        let (from, to) = (from / 1000, to / 1000);
+        if to <= from {
+            return 0;
+        }
+        match self {
+            EmissionSchedule::Constant => rate.checked_mul(u128::from(to - from)).unwrap(),
+            EmissionSchedule::Halving { period } => {
+                let start = starting_at / 1000;
+                let period = period / 1000;
+                let mut emitted = 0u128;
+                let mut time = from;
+                while time < to {
+                    let halvings = time.saturating_sub(start) / period;
+                    if halvings >= 128 {
+                        break;
+                    }
+                    let halving_end = if time < start { start } else { start + (halvings + 1) * period };
+                    let end = min(to, halving_end);
+                    emitted = emitted.checked_add((rate >> halvings).checked_mul(u128::from(end - time)).unwrap()).unwrap();
+                    time = end;
+                }
+                emitted
+            }
+            EmissionSchedule::Piecewise { points } => {
+                let first = &points[0];
+                let last = &points[points.len() - 1];
+                let mut emitted = 0u128;
+                // flat before the first point
+                let flat_end = min(to, first.timestamp / 1000);
+                if from < flat_end {
+                    emitted += mul_div(rate, u128::from(first.rate_multiplier) * u128::from(flat_end - from), EMISSION_MULTIPLIER_DENOMINATOR);
+                }
+                for pair in points.windows(2) {
+                    let (start, end) = (pair[0].timestamp / 1000, pair[1].timestamp / 1000);
+                    let (x, y) = (from.max(start), to.min(end));
+                    if x >= y {
+                        continue;
+                    }
+                    let area = Self::segment_area(&pair[0], &pair[1], x, y);
+                    emitted += mul_div(rate, area, EMISSION_MULTIPLIER_DENOMINATOR * 2 * u128::from(end - start));
+                }
+                // flat after the last point
+                let flat_start = from.max(last.timestamp / 1000);
+                if flat_start < to {
+                    emitted += mul_div(rate, u128::from(last.rate_multiplier) * u128::from(to - flat_start), EMISSION_MULTIPLIER_DENOMINATOR);
+                }
+                emitted
+            }
+        }
+    }
+
+    /// Area below the line between two points over the seconds `x..y`, times twice the segment length.
+    fn segment_area(start: &EmissionPoint, end: &EmissionPoint, x: u64, y: u64) -> u128 {
+        let length = u128::from(end.timestamp / 1000 - start.timestamp / 1000);
+        let start_time = start.timestamp / 1000;
+        let (start_multiplier, end_multiplier) = (u128::from(start.rate_multiplier), u128::from(end.rate_multiplier));
+        // (f(x) + f(y)) * (y - x) / 2 with f(t) = f0 + (f1 - f0) * (t - t0) / length
+        let offsets = u128::from(x - start_time) + u128::from(y - start_time);
+        let sum = if end_multiplier >= start_multiplier {
+            2 * start_multiplier * length + (end_multiplier - start_multiplier) * offsets
+        } else {
+            2 * start_multiplier * length - (start_multiplier - end_multiplier) * offsets
+        };
+        sum * u128::from(y - x)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EmissionRateView {
+    pub token_reward_rate: U128,
+    pub pool_reward_rate: U128,
+    pub reward_reward_rate: U128,
+}
+
+impl FarmInfo {
+    /// total emission of `rate` from `from` to `to` under the farm's emission schedule
+    pub fn emitted(&self, rate: u128, from: u64, to: u64) -> u128 {
+        self.emission_schedule.emitted(rate, self.starting_at, from, to)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_set_emission_schedule(&mut self, farm_id: u16, emission_schedule: EmissionSchedule) {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        emission_schedule.assert_valid();
+        let now = env::block_timestamp() / 1000000;
+        self.update_claim_amounts(farm_id, 0, now);
+        self.update_claim_amounts(farm_id, 1, now);
+        self.update_claim_amounts(farm_id, 2, now);
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        farm_info.emission_schedule = emission_schedule;
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Settles every staker with the current schedule before switching to the new one.
+    /// Requires the `FarmManager` role. Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_emission_schedule(&mut self, farm_id: u16, emission_schedule: EmissionSchedule) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.internal_set_emission_schedule(farm_id, emission_schedule);
+    }
+
+    /// Emission per second of each pool of the farm at `timestamp` (ms).
+    pub fn get_emission_rate(&self, farm_id: u16, timestamp: u64) -> EmissionRateView {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let schedule = &farm_info.emission_schedule;
+        EmissionRateView {
+            token_reward_rate: U128(schedule.rate_at(farm_info.token_reward_rate, farm_info.starting_at, timestamp)),
+            pool_reward_rate: U128(schedule.rate_at(farm_info.pool_reward_rate, farm_info.starting_at, timestamp)),
+            reward_reward_rate: U128(schedule.rate_at(farm_info.reward_reward_rate, farm_info.starting_at, timestamp)),
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::*;
+
+/// Splits a farm into back to back epochs paying a fixed reward each. The farm keeps running
+/// epoch after epoch for `total_epochs`, or until cancelled when there is no limit. Stakes are
+/// not touched between epochs, so they carry over.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct EpochConfig {
+    pub epoch_length: u64,
+    pub token_reward_per_epoch: u128,
+    pub pool_reward_per_epoch: u128,
+    pub total_epochs: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EpochInfoView {
+    pub epoch_length: u64,
+    pub token_reward_per_epoch: U128,
+    pub pool_reward_per_epoch: U128,
+    pub total_epochs: Option<u32>,
+    pub current_epoch: Option<u32>,
+    pub current_epoch_ends_at: Option<u64>,
+    /// epochs still to start after the current one, `None` while the farm renews until cancelled
+    pub remaining_epochs: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EpochView {
+    pub epoch: u32,
+    pub starts_at: u64,
+    pub ends_at: u64,
+}
+
+impl FarmInfo {
+    /// index of the epoch running at `now`, `None` before the farm starts or after its last epoch
+    pub fn current_epoch(&self, now: u64) -> Option<u32> {
+        let config = self.epochs.as_ref()?;
+        if now < self.starting_at || now >= self.ending_at {
+            return None;
+        }
+        Some(((now - self.starting_at) / config.epoch_length) as u32)
+    }
+
+    /// start of `epoch`, `u64::MAX` for epochs too far out to ever start
+    pub fn epoch_starts_at(&self, epoch: u32) -> u64 {
+        let config = self.epochs.as_ref().unwrap();
+        u64::from(epoch)
+            .checked_mul(config.epoch_length)
+            .and_then(|offset| self.starting_at.checked_add(offset))
+            .unwrap_or(u64::MAX)
+    }
+}
+
+impl Contract {
+    // `is_multiple_of` needs Rust 1.87, the modulo keeps older toolchains building
+    #[allow(clippy::manual_is_multiple_of)]
+    pub(crate) fn internal_set_farm_epochs(
+        &mut self,
+        farm_id: u16,
+        epoch_length: u64,
+        token_reward_per_epoch: U128,
+        pool_reward_per_epoch: U128,
+        total_epochs: Option<u32>,
+    ) {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let now = env::block_timestamp() / 1000000;
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        assert!(now < farm_info.starting_at, "Farm has already started");
+        // rewards accrue per whole second
+        assert!(
+            epoch_length >= 1000 && epoch_length % 1000 == 0,
+            "Epoch must last a whole number of seconds"
+        );
+        assert!(total_epochs != Some(0), "Farm needs at least one epoch");
+
+        let epoch_seconds = u128::from(epoch_length / 1000);
+        assert!(
+            token_reward_per_epoch.0 % epoch_seconds == 0 && pool_reward_per_epoch.0 % epoch_seconds == 0,
+            "Rewards per epoch must split evenly over its {} seconds",
+            epoch_seconds
+        );
+        farm_info.token_reward_rate = token_reward_per_epoch.0 / epoch_seconds;
+        farm_info.pool_reward_rate = pool_reward_per_epoch.0 / epoch_seconds;
+        farm_info.ending_at = match total_epochs {
+            Some(total_epochs) => u64::from(total_epochs)
+                .checked_mul(epoch_length)
+                .and_then(|duration| farm_info.starting_at.checked_add(duration))
+                .expect("Epochs run past the last timestamp"),
+            None => u64::MAX,
+        };
+        farm_info.epochs = Some(EpochConfig {
+            epoch_length,
+            token_reward_per_epoch: token_reward_per_epoch.0,
+            pool_reward_per_epoch: pool_reward_per_epoch.0,
+            total_epochs,
+        });
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Turns a farm that has not started yet into a recurring one. The reward rates are set to
+    /// pay the per-epoch amounts over each epoch, `total_epochs: None` renews until cancelled.
+    /// Epochs last whole seconds and the amounts must split evenly over them, so every epoch
+    /// pays exactly its amount.
+    /// Requires the `FarmManager` role. Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_farm_epochs(
+        &mut self,
+        farm_id: u16,
+        epoch_length: u64,
+        token_reward_per_epoch: U128,
+        pool_reward_per_epoch: U128,
+        total_epochs: Option<u32>,
+    ) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.internal_set_farm_epochs(farm_id, epoch_length, token_reward_per_epoch, pool_reward_per_epoch, total_epochs);
+    }
+
+    /// Stops renewing a recurring farm, it ends with the epoch currently running
+    /// (or before its first epoch if it has not started yet).
+    /// Requires the `FarmManager` role.
+    #[payable]
+    pub fn cancel_farm_epochs(&mut self, farm_id: u16) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let now = env::block_timestamp() / 1000000;
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        assert!(farm_info.epochs.is_some(), "Farm is not recurring");
+        assert!(now < farm_info.ending_at, "Farm has ended");
+
+        let last_epochs = if now < farm_info.starting_at {
+            0
+        } else {
+            farm_info.current_epoch(now).unwrap() + 1
+        };
+        farm_info.ending_at = farm_info.epoch_starts_at(last_epochs);
+        farm_info.epochs.as_mut().unwrap().total_epochs = Some(last_epochs);
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+
+    pub fn get_farm_epochs(&self, farm_id: u16) -> Option<EpochInfoView> {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let now = env::block_timestamp() / 1000000;
+        let current_epoch = farm_info.current_epoch(now);
+        let config = farm_info.epochs.as_ref()?;
+        let remaining_epochs = config.total_epochs.map(|total_epochs| match current_epoch {
+            Some(epoch) => total_epochs - epoch - 1,
+            None if now < farm_info.starting_at => total_epochs,
+            None => 0,
+        });
+        Some(EpochInfoView {
+            epoch_length: config.epoch_length,
+            token_reward_per_epoch: U128(config.token_reward_per_epoch),
+            pool_reward_per_epoch: U128(config.pool_reward_per_epoch),
+            total_epochs: config.total_epochs,
+            current_epoch,
+            current_epoch_ends_at: current_epoch.map(|epoch| farm_info.epoch_starts_at(epoch + 1)),
+            remaining_epochs,
+        })
+    }
+
+    /// Start and end of `limit` epochs from `from_epoch`, stopping at the last epoch of a capped farm.
+    pub fn get_epoch_schedule(&self, farm_id: u16, from_epoch: u32, limit: u32) -> Vec<EpochView> {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let config = farm_info.epochs.as_ref().expect("Farm is not recurring");
+        let to_epoch = match config.total_epochs {
+            Some(total_epochs) => min(from_epoch.saturating_add(limit), total_epochs),
+            None => from_epoch.saturating_add(limit),
+        };
+        (from_epoch..to_epoch)
+            .map(|epoch| EpochView {
+                epoch,
+                starts_at: farm_info.epoch_starts_at(epoch),
+                ends_at: farm_info.epoch_starts_at(epoch + 1),
+            })
+            .collect()
+    }
+}
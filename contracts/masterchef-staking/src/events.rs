@@ -0,0 +1,129 @@
+use crate::*;
+
+use near_sdk::serde_json::json;
+
+/// events logged in the NEP-297 format so indexers can follow stake movements
+const EVENT_STANDARD: &str = "uon_staking";
+const EVENT_VERSION: &str = "1.0.0";
+
+pub(crate) fn emit_event<T: Serialize>(event: &str, data: T) {
+    env::log_str(&format!(
+        "EVENT_JSON:{}",
+        json!({
+            "standard": EVENT_STANDARD,
+            "version": EVENT_VERSION,
+            "event": event,
+            "data": [data],
+        })
+    ));
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakeForData<'a> {
+    pub sender_id: &'a AccountId,
+    pub beneficiary_id: &'a AccountId,
+    pub farm_id: u16,
+    pub token_type: u8,
+    pub amount: U128,
+    pub lock_duration: u64,
+}
+
+pub(crate) fn emit_stake_for(
+    sender_id: &AccountId,
+    beneficiary_id: &AccountId,
+    farm_id: u16,
+    token_type: u8,
+    amount: U128,
+    lock_duration: u64,
+) {
+    emit_event(
+        "stake_for",
+        StakeForData {
+            sender_id,
+            beneficiary_id,
+            farm_id,
+            token_type,
+            amount,
+            lock_duration,
+        },
+    );
+}
+
+/// lock tokens are logged with the NEP-171 event standard so wallets and indexers pick them up
+pub(crate) fn emit_nft_event<T: Serialize>(event: &str, data: T) {
+    env::log_str(&format!(
+        "EVENT_JSON:{}",
+        json!({
+            "standard": "nep171",
+            "version": "1.0.0",
+            "event": event,
+            "data": [data],
+        })
+    ));
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftMintData<'a> {
+    pub owner_id: &'a AccountId,
+    pub token_ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftTransferData<'a> {
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+    pub token_ids: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PositionMigratedData<'a> {
+    pub owner_id: &'a AccountId,
+    pub position_id: u64,
+    pub from_farm_id: u16,
+    pub to_farm_id: u16,
+    pub weight: U128,
+}
+
+pub(crate) fn emit_position_migrated(
+    owner_id: &AccountId,
+    position_id: u64,
+    from_farm_id: u16,
+    to_farm_id: u16,
+    weight: u128,
+) {
+    emit_event(
+        "position_migrated",
+        PositionMigratedData {
+            owner_id,
+            position_id,
+            from_farm_id,
+            to_farm_id,
+            weight: U128(weight),
+        },
+    );
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoleData<'a> {
+    pub role: Role,
+    pub account_id: &'a AccountId,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OwnershipData<'a> {
+    pub owner_id: &'a AccountId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_owner_id: Option<&'a AccountId>,
+}
+
+pub(crate) fn emit_ownership_event(event: &str, owner_id: &AccountId, new_owner_id: Option<&AccountId>) {
+    emit_event(event, OwnershipData { owner_id, new_owner_id });
+}
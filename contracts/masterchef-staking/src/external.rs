@@ -23,4 +23,48 @@ trait ExtTransfer {
         amount: U128,
         memo: Option<String>,
     );
+    fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    );
+}
+
+#[ext_contract(ext_balance)]
+trait ExtBalance {
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
+    fn mft_balance_of(&self, token_id: String, account_id: AccountId) -> U128;
+}
+
+#[ext_contract(ext_nft_receiver)]
+trait ExtNftReceiver {
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: String,
+        msg: String,
+    ) -> PromiseOrValue<bool>;
+}
+
+#[ext_contract(ext_self)]
+trait ExtSelf {
+    fn nft_resolve_transfer(
+        &mut self,
+        previous_owner_id: AccountId,
+        receiver_id: AccountId,
+        token_id: String,
+    ) -> bool;
+    fn on_rescue_balance(&mut self, token_id: String, receiver_id: AccountId, amount: U128) -> U128;
+    fn on_solvency_balance(&mut self, token_id: String, pause_on_deficit: bool) -> SolvencyReport;
+    fn on_nft_withdraw(
+        &mut self,
+        farm_id: u16,
+        account_id: AccountId,
+        nft_contract_id: AccountId,
+        token_id: String,
+        multiplier: u32,
+    ) -> bool;
 }
\ No newline at end of file
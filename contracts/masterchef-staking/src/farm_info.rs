@@ -1,6 +1,9 @@
 use crate::*;
 
 extern crate chrono;
+
+/// weight of a stake that makes up the whole pool, before the lock adds to it
+pub const POOL_SHARE_WEIGHT: u128 = 10_000_000_000_000_000_000_000_000_000;
 // use chrono::prelude::*;
 // use chrono::{DateTime, Local, NaiveDateTime, Utc};
 // use std::time::SystemTime;
@@ -19,6 +22,8 @@ pub struct FarmInfo {
     pub total_lp_share_weight: u128,
     pub total_reward_amount: u128,
     pub total_reward_weight: u128,
+    /// rewards of every pool credited to stakers so far, fees included
+    pub distributed_reward_amount: u128,
     pub token_reward_rate: u128,
     pub pool_reward_rate: u128,
     pub reward_reward_rate: u128,
@@ -28,9 +33,88 @@ pub struct FarmInfo {
     pub max_reward_vesting_duration: u64,
     pub starting_at: u64,
     pub ending_at: u64,
+    pub unbonding_period: u64,
+    pub unbonding_token_amount: u128,
+    pub unbonding_lp_share_amount: u128,
+    pub unbonding_reward_amount: u128,
+    pub successor_farm_id: Option<u16>,
+    pub epochs: Option<EpochConfig>,
+    pub emission_schedule: EmissionSchedule,
+    pub token_limits: StakeLimits,
+    pub lp_share_limits: StakeLimits,
+    pub access: FarmAccess,
+    pub allowlist: UnorderedSet<AccountId>,
+    pub fees: Option<FeeConfig>,
     pub stake_infos: UnorderedMap<AccountId, StakeInfo>,
 }
 
+/// Layout of `FarmInfo` before positions, read by `migrate`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct FarmInfoV0 {
+    pub farm_id: u16,
+    pub farm_type: u8,
+    pub token_id: AccountId,
+    pub token_decimal: u8,
+    pub pool_id: u64,
+    pub total_token_amount: u128,
+    pub total_token_weight: u128,
+    pub total_lp_share_amount: u128,
+    pub total_lp_share_weight: u128,
+    pub total_reward_amount: u128,
+    pub total_reward_weight: u128,
+    pub token_reward_rate: u128,
+    pub pool_reward_rate: u128,
+    pub reward_reward_rate: u128,
+    pub token_weight_rate: u16,
+    pub pool_weight_rate: u16,
+    pub max_token_vesting_duration: u64,
+    pub max_reward_vesting_duration: u64,
+    pub starting_at: u64,
+    pub ending_at: u64,
+    pub stake_infos: UnorderedMap<AccountId, LegacyStakeInfo>,
+}
+
+impl From<FarmInfoV0> for FarmInfo {
+    /// The stake infos are still in the legacy layout until `migrate_stake_infos` converts them.
+    /// Rewards paid before were not tracked, so `distributed_reward_amount` starts at zero and
+    /// the rescuable balance errs on the safe side.
+    fn from(prev: FarmInfoV0) -> Self {
+        let mut farm_info = FarmInfo::new(
+            prev.farm_id,
+            prev.farm_type,
+            prev.token_id,
+            prev.token_decimal,
+            prev.pool_id,
+            U128(prev.token_reward_rate),
+            U128(prev.pool_reward_rate),
+            U128(prev.reward_reward_rate),
+            prev.token_weight_rate,
+            prev.pool_weight_rate,
+            prev.max_token_vesting_duration,
+            prev.max_reward_vesting_duration,
+            prev.starting_at,
+            prev.ending_at,
+        );
+        farm_info.total_token_amount = prev.total_token_amount;
+        farm_info.total_token_weight = prev.total_token_weight;
+        farm_info.total_lp_share_amount = prev.total_lp_share_amount;
+        farm_info.total_lp_share_weight = prev.total_lp_share_weight;
+        farm_info.total_reward_amount = prev.total_reward_amount;
+        farm_info.total_reward_weight = prev.total_reward_weight;
+        farm_info.stake_infos = with_layout(&prev.stake_infos);
+        farm_info
+    }
+}
+
+/// What a stake adds to a farm: the part its limits accept, the deposit fee on it, the
+/// amount staked after the fee and the weight it gets.
+pub struct StakeQuote {
+    pub accepted: u128,
+    pub fee: u128,
+    pub amount: u128,
+    pub weight: u128,
+}
+
 #[derive(BorshDeserialize, BorshSerialize, Serialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct EditingFarmInfo {
@@ -80,6 +164,7 @@ impl FarmInfo {
             total_lp_share_weight: 0,
             total_reward_amount: 0,
             total_reward_weight: 0,
+            distributed_reward_amount: 0,
             token_reward_rate: u128::from(token_reward_rate),
             pool_reward_rate: u128::from(pool_reward_rate),
             reward_reward_rate: u128::from(reward_reward_rate),
@@ -89,13 +174,103 @@ impl FarmInfo {
             max_reward_vesting_duration,
             starting_at,
             ending_at,
+            unbonding_period: 0,
+            unbonding_token_amount: 0,
+            unbonding_lp_share_amount: 0,
+            unbonding_reward_amount: 0,
+            successor_farm_id: None,
+            epochs: None,
+            emission_schedule: EmissionSchedule::Constant,
+            token_limits: StakeLimits::default(),
+            lp_share_limits: StakeLimits::default(),
+            access: FarmAccess::Open,
+            allowlist: UnorderedSet::new(StorageKey::FarmAllowlist { farm_id }),
+            fees: None,
             stake_infos: UnorderedMap::new(StorageKey::StakeInfos { farm_id }),
         }
     }
+
+    /// weight of `amount` tokens locked for `lock_duration`, once `amount` is part of `total_token_amount`
+    pub fn token_weight_for(&self, amount: u128, lock_duration: u64) -> u128 {
+        let decimal_scale = 10u128.pow((24 - self.token_decimal).into());
+        mul_div(amount, POOL_SHARE_WEIGHT, self.total_token_amount)
+            .checked_add(lock_weight(
+                amount,
+                self.token_weight_rate,
+                lock_duration,
+                self.max_token_vesting_duration,
+                decimal_scale,
+            ))
+            .unwrap()
+    }
+
+    /// weight of `amount` LP shares locked for `lock_duration`, once `amount` is part of `total_lp_share_amount`
+    pub fn lp_share_weight_for(&self, amount: u128, lock_duration: u64) -> u128 {
+        mul_div(amount, POOL_SHARE_WEIGHT, self.total_lp_share_amount)
+            .checked_add(lock_weight(
+                amount,
+                self.pool_weight_rate,
+                lock_duration,
+                self.max_token_vesting_duration,
+                1,
+            ))
+            .unwrap()
+    }
+
+    /// Adds a stake of `amount` tokens or LP shares locked for `lock_duration` to the account and
+    /// pool totals, after the pool's limits and the deposit fee. Shared by the transfer callbacks
+    /// and `preview_stake`, charging the fee is left to the caller.
+    pub fn apply_stake(
+        &mut self,
+        stake_info: &mut StakeInfo,
+        kind: PositionKind,
+        amount: u128,
+        lock_duration: u64,
+        stake_fee_bps: u16,
+    ) -> StakeQuote {
+        let accepted = match kind {
+            PositionKind::Token => {
+                self.token_limits
+                    .accepted_amount(amount, self.total_token_amount, stake_info.token_amount)
+            }
+            PositionKind::LpShare => {
+                self.lp_share_limits
+                    .accepted_amount(amount, self.total_lp_share_amount, stake_info.lp_share_amount)
+            }
+            PositionKind::Reward => env::panic_str("Only tokens and LP shares can be staked"),
+        };
+        let fee = fee_amount(accepted, stake_fee_bps);
+        assert!(accepted > fee, "Stake does not cover the deposit fee");
+        let amount = accepted - fee;
+
+        let weight = if kind == PositionKind::Token {
+            stake_info.token_amount = stake_info.token_amount.checked_add(amount).unwrap();
+            self.total_token_amount = self.total_token_amount.checked_add(amount).unwrap();
+            let weight = stake_info.boosted_weight(self.token_weight_for(amount, lock_duration));
+            stake_info.token_weight = stake_info.token_weight.checked_add(weight).unwrap();
+            self.total_token_weight = self.total_token_weight.checked_add(weight).unwrap();
+            weight
+        } else {
+            stake_info.lp_share_amount = stake_info.lp_share_amount.checked_add(amount).unwrap();
+            self.total_lp_share_amount = self.total_lp_share_amount.checked_add(amount).unwrap();
+            let weight = stake_info.boosted_weight(self.lp_share_weight_for(amount, lock_duration));
+            stake_info.lp_share_weight = stake_info.lp_share_weight.checked_add(weight).unwrap();
+            self.total_lp_share_weight = self.total_lp_share_weight.checked_add(weight).unwrap();
+            weight
+        };
+        StakeQuote {
+            accepted,
+            fee,
+            amount,
+            weight,
+        }
+    }
 }
 
 #[near_bindgen]
 impl Contract {
+    /// Requires the owner, farm managers propose farms with `save_editing_farm_info`. Goes
+    /// through `queue_operation` while the timelock is on.
     #[payable]
     pub fn add_farm(
         &mut self,
@@ -115,6 +290,7 @@ impl Contract {
     ) {
         self.assert_owner();
         assert_one_yocto();
+        self.assert_not_timelocked();
         self.farm_infos.push(&FarmInfo::new(
             self.farm_infos.len() as u16,
             farm_type,
@@ -155,6 +331,9 @@ impl Contract {
         // data
     }
 
+    /// Requires the `FarmManager` role. The farm is created once every holder confirmed it,
+    /// or right away when the owner DAO saves it in DAO mode. While the timelock is on farms
+    /// are only added through `queue_operation`.
     #[payable]
     pub fn save_editing_farm_info(
         &mut self,
@@ -172,8 +351,9 @@ impl Contract {
         starting_at: u64,
         ending_at: u64,
     ) {
-        self.assert_admin();
+        self.assert_role(Role::FarmManager);
         assert_one_yocto();
+        self.assert_not_timelocked();
         for elem in self.farm_infos.to_vec() {
             if farm_type == 0 {
                 if (elem.starting_at < starting_at && starting_at < elem.ending_at
@@ -224,7 +404,7 @@ impl Contract {
         self.confirmed_admins_for_new_farm
             .insert(&env::predecessor_account_id());
 
-        if self.confirmed_admins_for_new_farm.len() == self.admin_ids.len() {
+        if self.is_dao_call() || self.confirmed_admins_for_new_farm.len() == self.role_holder_count(&Role::FarmManager) {
             self.farm_infos.push(&FarmInfo::new(
                 self.farm_infos.len() as u16,
                 self.editing_new_farm.farm_type,
@@ -261,14 +441,16 @@ impl Contract {
         }
     }
 
+    /// Requires the `FarmManager` role. Only while the timelock is off, see `save_editing_farm_info`.
     #[payable]
     pub fn confirm_new_farm(&mut self) {
-        self.assert_admin();
+        self.assert_role(Role::FarmManager);
         assert_one_yocto();
+        self.assert_not_timelocked();
         self.confirmed_admins_for_new_farm
             .insert(&env::predecessor_account_id());
 
-        if self.confirmed_admins_for_new_farm.len() == self.admin_ids.len() {
+        if self.is_dao_call() || self.confirmed_admins_for_new_farm.len() == self.role_holder_count(&Role::FarmManager) {
             self.farm_infos.push(&FarmInfo::new(
                 self.farm_infos.len() as u16,
                 self.editing_new_farm.farm_type,
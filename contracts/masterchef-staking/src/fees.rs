@@ -0,0 +1,182 @@
+use crate::*;
+
+/// fees are expressed in basis points of the charged amount
+pub const FEE_DENOMINATOR: u128 = 10000;
+/// upper bound of every fee, 10%
+pub const MAX_FEE_BPS: u16 = 1000;
+
+/// `reward_fee_bps` is taken from claimed rewards, `stake_fee_bps` from deposits and from withdrawals.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeConfig {
+    pub reward_fee_bps: u16,
+    pub stake_fee_bps: u16,
+}
+
+impl FeeConfig {
+    fn assert_valid(&self) {
+        assert!(
+            self.reward_fee_bps <= MAX_FEE_BPS && self.stake_fee_bps <= MAX_FEE_BPS,
+            "Fees must be at most {} bps",
+            MAX_FEE_BPS
+        );
+    }
+}
+
+/// `amount * bps / 10000` rounded up, so rounding always goes to the protocol
+pub fn fee_amount(amount: u128, bps: u16) -> u128 {
+    let bps = u128::from(bps);
+    let whole = (amount / FEE_DENOMINATOR) * bps;
+    let rest = (amount % FEE_DENOMINATOR) * bps;
+    whole + rest.div_ceil(FEE_DENOMINATOR)
+}
+
+/// treasury key of Ref LP shares, the multi token id Ref uses for the pool
+pub fn lp_share_token_id(pool_id: u64) -> String {
+    ":".to_owned() + &pool_id.to_string()
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeChargedData<'a> {
+    pub account_id: &'a AccountId,
+    pub farm_id: u16,
+    pub token_id: &'a str,
+    pub fee_type: &'a str,
+    pub amount: U128,
+    pub fee: U128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TreasuryBalanceView {
+    pub token_id: String,
+    pub amount: U128,
+}
+
+impl Contract {
+    pub(crate) fn fees_of(&self, farm_info: &FarmInfo) -> FeeConfig {
+        farm_info.fees.unwrap_or(self.default_fees)
+    }
+
+    /// Moves the fee on `amount` to the treasury and logs it. Returns the fee.
+    pub(crate) fn internal_charge_fee(
+        &mut self,
+        farm_id: u16,
+        account_id: &AccountId,
+        token_id: &str,
+        fee_type: &str,
+        amount: u128,
+        bps: u16,
+    ) -> u128 {
+        let fee = fee_amount(amount, bps);
+        if fee > 0 {
+            let balance = self.treasury.get(&token_id.to_string()).unwrap_or(0);
+            self.treasury.insert(&token_id.to_string(), &(balance + fee));
+            emit_event(
+                "fee_charged",
+                FeeChargedData {
+                    account_id,
+                    farm_id,
+                    token_id,
+                    fee_type,
+                    amount: U128(amount),
+                    fee: U128(fee),
+                },
+            );
+        }
+        fee
+    }
+
+    pub(crate) fn internal_set_default_fees(&mut self, fees: FeeConfig) {
+        fees.assert_valid();
+        self.default_fees = fees;
+    }
+
+    pub(crate) fn internal_set_farm_fees(&mut self, farm_id: u16, fees: Option<FeeConfig>) {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        if let Some(fees) = &fees {
+            fees.assert_valid();
+        }
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        farm_info.fees = fees;
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires the `TreasuryManager` role. Applies to farms without their own fees.
+    /// Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_default_fees(&mut self, fees: FeeConfig) {
+        self.assert_role(Role::TreasuryManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.internal_set_default_fees(fees);
+    }
+
+    /// Requires the `TreasuryManager` role. `None` falls back to the default fees.
+    /// Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_farm_fees(&mut self, farm_id: u16, fees: Option<FeeConfig>) {
+        self.assert_role(Role::TreasuryManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.internal_set_farm_fees(farm_id, fees);
+    }
+
+    /// Requires the `TreasuryManager` role. Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_treasury_account(&mut self, account_id: AccountId) {
+        self.assert_role(Role::TreasuryManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.treasury_id = account_id;
+    }
+
+    /// Requires the `TreasuryManager` role. Sends the whole fee balance of a token, or of
+    /// Ref LP shares for `:<pool_id>`, to the treasury account.
+    #[payable]
+    pub fn withdraw_treasury(&mut self, token_id: String) -> U128 {
+        self.assert_role(Role::TreasuryManager);
+        assert_one_yocto();
+        let amount = self.treasury.remove(&token_id).expect("No treasury balance for this token");
+        if token_id.starts_with(':') {
+            ext_transfer::ext(self.ref_exchange_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .mft_transfer(token_id, self.treasury_id.clone(), U128(amount), None);
+        } else {
+            ext_transfer::ext(AccountId::new_unchecked(token_id))
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(self.treasury_id.clone(), U128(amount), None);
+        }
+        U128(amount)
+    }
+
+    pub fn get_default_fees(&self) -> FeeConfig {
+        self.default_fees
+    }
+
+    /// fees charged by the farm, its own or the default ones
+    pub fn get_farm_fees(&self, farm_id: u16) -> FeeConfig {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        self.fees_of(&farm_info)
+    }
+
+    pub fn get_treasury_account(&self) -> AccountId {
+        self.treasury_id.clone()
+    }
+
+    pub fn get_treasury_balances(&self) -> Vec<TreasuryBalanceView> {
+        self.treasury
+            .iter()
+            .map(|(token_id, amount)| TreasuryBalanceView {
+                token_id,
+                amount: U128(amount),
+            })
+            .collect()
+    }
+}
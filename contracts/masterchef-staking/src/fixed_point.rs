@@ -0,0 +1,50 @@
+//! Reward and weight math with 256-bit intermediates.
+//!
+//! A staker's share of a pool is a fixed-point fraction scaled by `PRECISION` (1e24), rounded
+//! down. Rewards are `share * emitted / PRECISION`, also rounded down, so the rewards of all
+//! stakers of a pool never add up to more than the pool emitted. Each settlement of a staker
+//! loses less than `emitted / PRECISION + 1` yocto to rounding.
+
+pub use u256::U256;
+
+/// the code generated by `construct_uint!` trips clippy lints
+#[allow(clippy::all)]
+mod u256 {
+    uint::construct_uint! {
+        pub struct U256(4);
+    }
+}
+
+/// scale of a share, a share of `PRECISION` is the whole pool
+pub const PRECISION: u128 = 1_000_000_000_000_000_000_000_000;
+
+/// `a * b / c` rounded down, panics if the result does not fit in u128 or `c` is 0
+pub fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    assert!(c > 0, "Division by zero");
+    let result = U256::from(a) * U256::from(b) / U256::from(c);
+    assert!(result <= U256::from(u128::MAX), "Overflow");
+    result.as_u128()
+}
+
+/// `weight / total_weight` scaled by `PRECISION`, 0 for an empty pool
+pub fn share_of(weight: u128, total_weight: u128) -> u128 {
+    if total_weight == 0 {
+        return 0;
+    }
+    mul_div(weight, PRECISION, total_weight)
+}
+
+/// part of `emitted` that goes to a staker holding `share`
+pub fn reward_for(share: u128, emitted: u128) -> u128 {
+    mul_div(share, emitted, PRECISION)
+}
+
+/// Weight a lock adds to a stake, `amount * weight_rate / 10000 * lock_duration / max_duration * scale`
+/// rounded down once at the end.
+pub fn lock_weight(amount: u128, weight_rate: u16, lock_duration: u64, max_duration: u64, scale: u128) -> u128 {
+    assert!(max_duration > 0, "Division by zero");
+    let result = U256::from(amount) * U256::from(weight_rate) * U256::from(lock_duration) * U256::from(scale)
+        / (U256::from(10000u64) * U256::from(max_duration));
+    assert!(result <= U256::from(u128::MAX), "Overflow");
+    result.as_u128()
+}
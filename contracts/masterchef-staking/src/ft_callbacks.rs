@@ -7,9 +7,13 @@ pub struct StakingArgs {
     pub stake_type: u8,
     pub farm_id: u16,
     pub lock_duration: u64,
+    /// account credited with the stake, defaults to the sender
+    pub beneficiary: Option<AccountId>,
+    /// Merkle proof of the credited account, for farms gated by a Merkle root
+    pub proof: Option<Vec<String>>,
 }
 
-trait FungibleTokenReceiver {
+pub trait FungibleTokenReceiver {
     fn ft_on_transfer(
         &mut self,
         sender_id: AccountId,
@@ -18,7 +22,7 @@ trait FungibleTokenReceiver {
     ) -> PromiseOrValue<U128>;
 }
 
-trait MFTTokenReceiver {
+pub trait MFTTokenReceiver {
     fn mft_on_transfer(
         &mut self,
         token_id: String,
@@ -37,13 +41,22 @@ impl MFTTokenReceiver for Contract {
         amount: U128,
         msg: String,
     ) -> PromiseOrValue<U128> {
+        self.assert_not_paused();
+        assert_eq!(
+            env::predecessor_account_id(),
+            self.ref_exchange_id,
+            "Only LP shares of the Ref exchange can be staked"
+        );
         let StakingArgs {
             stake_type,
             farm_id,
             lock_duration,
+            beneficiary,
+            proof,
         } = near_sdk::serde_json::from_str(&msg).expect("Invalid PurchaseArgs");
+        let account_id = beneficiary.unwrap_or_else(|| sender_id.clone());
+        let initial_storage_usage = env::storage_usage();
 
-        // let pool_id = env::predecessor_account_id();
         assert!(amount.0 > 0, "Amount must be greater than 0");
 
         assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
@@ -64,80 +77,54 @@ impl MFTTokenReceiver for Contract {
         let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
 
         assert!(farm_info.farm_type == 1 || farm_info.farm_type == 2, "LP Pool does not exist in this Farm");
+        if !self.internal_is_eligible(&farm_info, &account_id, proof.as_deref()) {
+            env::log_str(&format!("{} is not eligible for farm {}, refunding", account_id, farm_id));
+            return PromiseOrValue::Value(amount);
+        }
 
 
+        let mut unused_amount = 0;
         if pool_id == farm_info.pool_id {
 
-            let info: Option<StakeInfo> = farm_info.stake_infos.get(&sender_id);
+            let info: Option<StakeInfo> = farm_info.stake_infos.get(&account_id);
 
             let mut stake_info = if let Some(info) = info {
                 info
             } else {
-                let empty_value = StakeInfo {
-                    owner_id: sender_id.clone(),
-                    token_amount: 0,
-                    lp_share_amount: 0,
-                    reward_amount: 0,
-                    reward_token_to_claim: 0,
-                    reward_lp_to_claim: 0,
-                    token_locked: Vector::new(StorageKey::LockInfos {
-                        farm_id,
-                        account_id: sender_id.clone(),
-                        lock_info_type: 0,
-                    }),
-                    lp_share_locked: Vector::new(StorageKey::LockInfos {
-                        farm_id,
-                        account_id: sender_id.clone(),
-                        lock_info_type: 1,
-                    }),
-                    reward_locked: Vector::new(StorageKey::LockInfos {
-                        farm_id,
-                        account_id: sender_id.clone(),
-                        lock_info_type: 2,
-                    }),
-                    unlocked_at: Vector::new(StorageKey::LockInfos {
-                        farm_id,
-                        account_id: sender_id.clone(),
-                        lock_info_type: 3,
-                    }),
-                    staking_duration: Vector::new(StorageKey::LockInfos {
-                        farm_id,
-                        account_id: sender_id.clone(),
-                        lock_info_type: 4,
-                    }),
-                    lp_share_weight: 0,
-                    token_weight: 0,
-                    created_at: now,
-                    claimed_token_at: now,
-                    claimed_lp_at: now,
-                    claimed_reward_at: now,
-                };
-                empty_value
+                StakeInfo::new(farm_id, account_id.clone(), now)
             };
+            let stake_fee_bps = self.fees_of(&farm_info).stake_fee_bps;
+            let quote = farm_info.apply_stake(&mut stake_info, PositionKind::LpShare, amount.0, lock_duration, stake_fee_bps);
+            unused_amount = amount.0 - quote.accepted;
+            self.internal_charge_fee(farm_id, &account_id, &lp_share_token_id(pool_id), "deposit", quote.accepted, stake_fee_bps);
+            let amount = U128(quote.amount);
 
-            stake_info.token_locked.push(&U128(0));
-            stake_info.lp_share_locked.push(&amount);
-            stake_info.reward_locked.push(&U128(0));
-            stake_info
-                .unlocked_at
-                .push(&(now + lock_duration));
-            stake_info.staking_duration.push(&lock_duration);
             stake_info.claimed_lp_at = now;
-            stake_info.lp_share_amount = stake_info.lp_share_amount.checked_add(u128::from(amount)).unwrap();
-            farm_info.total_lp_share_amount = farm_info.total_lp_share_amount
-                    .checked_add(u128::from(amount))
-                    .unwrap();
-            let reward_weight = u128::from(amount).checked_mul(100000000).unwrap().checked_div(farm_info.total_lp_share_amount).unwrap().checked_mul(100000000000000000000).unwrap().checked_add(u128::from(amount).checked_mul(farm_info.pool_weight_rate.into()).unwrap().checked_div(10000).unwrap().checked_div(u128::from(farm_info.max_token_vesting_duration)).unwrap().checked_mul(u128::from(lock_duration)).unwrap()).unwrap();
-            stake_info.lp_share_weight = stake_info.lp_share_weight.checked_add(reward_weight).unwrap();
-            farm_info.total_lp_share_weight = farm_info.total_lp_share_weight.checked_add(u128::from(reward_weight)).unwrap();
+            self.internal_add_position(
+                farm_id,
+                &mut stake_info,
+                NewPosition {
+                    kind: PositionKind::LpShare,
+                    amount: amount.0,
+                    weight: quote.weight,
+                    created_at: now,
+                    lock_duration,
+                    storage_payer: (account_id != sender_id).then(|| sender_id.clone()),
+                },
+            );
 
-            farm_info.stake_infos.insert(&sender_id, &stake_info);
+            farm_info.stake_infos.insert(&account_id, &stake_info);
+            self.farm_infos.replace(farm_id.into(), &farm_info);
 
             let lp_staked = self.lp_stake_info.get(&pool_id).unwrap_or(0) + u128::from(amount);
             self.lp_stake_info.insert(&pool_id, &lp_staked);
+
+            if account_id != sender_id {
+                self.internal_charge_storage(&sender_id, initial_storage_usage);
+                emit_stake_for(&sender_id, &account_id, farm_id, 1, amount, lock_duration);
+            }
         }
-        self.farm_infos.replace(farm_id.into(), &farm_info);
-        PromiseOrValue::Value(U128(0))
+        PromiseOrValue::Value(U128(unused_amount))
     }
 }
 
@@ -149,11 +136,16 @@ impl FungibleTokenReceiver for Contract {
         amount: U128,
         msg: String,
     ) -> PromiseOrValue<U128> {
+        self.assert_not_paused();
         let StakingArgs {
             stake_type,
             farm_id,
             lock_duration,
+            beneficiary,
+            proof,
         } = near_sdk::serde_json::from_str(&msg).expect("Invalid PurchaseArgs");
+        let account_id = beneficiary.unwrap_or_else(|| sender_id.clone());
+        let initial_storage_usage = env::storage_usage();
 
         let ft_token_id = env::predecessor_account_id();
         assert!(amount.0 > 0, "Amount must be greater than 0");
@@ -225,6 +217,7 @@ impl FungibleTokenReceiver for Contract {
             swap_stake_info
                 .token_locked
                 .push(&U128::from(swaped_amount));
+            self.swap_owed = self.swap_owed.saturating_add(swaped_amount);
             swap_stake_info
                 .unlocked_at
                 .push(&(now + swap_farm_info.max_lock_time));
@@ -242,72 +235,47 @@ impl FungibleTokenReceiver for Contract {
             let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
 
             assert!(farm_info.farm_type == 0 || farm_info.farm_type == 2, "Token Pool does not exist in this Farm");
+            if !self.internal_is_eligible(&farm_info, &account_id, proof.as_deref()) {
+                env::log_str(&format!("{} is not eligible for farm {}, refunding", account_id, farm_id));
+                return PromiseOrValue::Value(amount);
+            }
 
             if ft_token_id == farm_info.token_id {
-                let info: Option<StakeInfo> = farm_info.stake_infos.get(&sender_id);
+                let info: Option<StakeInfo> = farm_info.stake_infos.get(&account_id);
                 let mut stake_info = if let Some(info) = info {
                     info
                 } else {
-                    let empty_value = StakeInfo {
-                        owner_id: sender_id.clone(),
-                        token_amount: 0,
-                        lp_share_amount: 0,
-                        reward_amount: 0,
-                        token_weight: 0,
-                        lp_share_weight: 0,
-                        reward_token_to_claim: 0,
-                        reward_lp_to_claim: 0,
-                        token_locked: Vector::new(StorageKey::LockInfos {
-                            farm_id,
-                            account_id: sender_id.clone(),
-                            lock_info_type: 0,
-                        }),
-                        lp_share_locked: Vector::new(StorageKey::LockInfos {
-                            farm_id,
-                            account_id: sender_id.clone(),
-                            lock_info_type: 1,
-                        }),
-                        reward_locked: Vector::new(StorageKey::LockInfos {
-                            farm_id,
-                            account_id: sender_id.clone(),
-                            lock_info_type: 2,
-                        }),
-                        unlocked_at: Vector::new(StorageKey::LockInfos {
-                            farm_id,
-                            account_id: sender_id.clone(),
-                            lock_info_type: 3,
-                        }),
-                        staking_duration: Vector::new(StorageKey::LockInfos {
-                            farm_id,
-                            account_id: sender_id.clone(),
-                            lock_info_type: 4,
-                        }),
-                        created_at: now,
-                        claimed_token_at: now,
-                        claimed_lp_at: now,
-                        claimed_reward_at: now,
-                    };
-                    empty_value
+                    StakeInfo::new(farm_id, account_id.clone(), now)
                 };
-                stake_info.token_locked.push(&amount);
-                stake_info.lp_share_locked.push(&U128(0));
-                stake_info.reward_locked.push(&U128(0));
-                stake_info.staking_duration.push(&lock_duration);
-                stake_info
-                    .unlocked_at
-                    .push(&(now + lock_duration));
+                let stake_fee_bps = self.fees_of(&farm_info).stake_fee_bps;
+                let quote = farm_info.apply_stake(&mut stake_info, PositionKind::Token, amount.0, lock_duration, stake_fee_bps);
+                let unused_amount = amount.0 - quote.accepted;
+                self.internal_charge_fee(farm_id, &account_id, farm_info.token_id.as_str(), "deposit", quote.accepted, stake_fee_bps);
+                let amount = U128(quote.amount);
                 stake_info.claimed_token_at = now;
-                stake_info.token_amount = stake_info.token_amount.checked_add(u128::from(amount)).unwrap();
-                farm_info.total_token_amount = farm_info.total_token_amount.checked_add(u128::from(amount)).unwrap();
-                let multiplier: u128 = 10;
-                let reward_weight = u128::from(amount).checked_mul(100000000).unwrap().checked_div(farm_info.total_token_amount).unwrap().checked_mul(100000000000000000000).unwrap().checked_add(u128::from(amount).checked_mul(farm_info.token_weight_rate.into()).unwrap().checked_div(10000).unwrap().checked_div(u128::from(farm_info.max_token_vesting_duration)).unwrap().checked_mul(u128::from(lock_duration)).unwrap().checked_mul(multiplier.pow((24-farm_info.token_decimal).try_into().unwrap())).unwrap()).unwrap();
-                stake_info.token_weight = stake_info.token_weight.checked_add(reward_weight).unwrap();
-                farm_info.total_token_weight = farm_info.total_token_weight.checked_add(u128::from(reward_weight)).unwrap();
-                farm_info.stake_infos.insert(&sender_id, &stake_info);
+                self.internal_add_position(
+                    farm_id,
+                    &mut stake_info,
+                    NewPosition {
+                        kind: PositionKind::Token,
+                        amount: amount.0,
+                        weight: quote.weight,
+                        created_at: now,
+                        lock_duration,
+                        storage_payer: (account_id != sender_id).then(|| sender_id.clone()),
+                    },
+                );
+                farm_info.stake_infos.insert(&account_id, &stake_info);
                 self.farm_infos.replace(farm_id.into(), &farm_info);
 
                 let token_staked = self.token_stake_info.get(&ft_token_id).unwrap_or(0) + u128::from(amount);
                 self.token_stake_info.insert(&ft_token_id, &token_staked);
+
+                if account_id != sender_id {
+                    self.internal_charge_storage(&sender_id, initial_storage_usage);
+                    emit_stake_for(&sender_id, &account_id, farm_id, 0, amount, lock_duration);
+                }
+                return PromiseOrValue::Value(U128(unused_amount));
             }
         }
         PromiseOrValue::Value(U128(0))
@@ -4,6 +4,7 @@ use crate::*;
 
 impl Contract {
     pub(crate) fn assert_owner(&self) {
+        assert!(!self.owner_renounced, "Ownership was renounced");
         assert_eq!(
             &env::predecessor_account_id(),
             &self.owner_id,
@@ -11,11 +12,53 @@ impl Contract {
         );
     }
 
-    pub(crate) fn assert_admin(&self) {
+
+    pub(crate) fn internal_charge_storage(&mut self, account_id: &AccountId, initial_storage_usage: u64) {
+        let storage_used = env::storage_usage().saturating_sub(initial_storage_usage);
+        let storage_cost = Balance::from(storage_used) * env::storage_byte_cost();
+        let balance = self.storage_deposits.get(account_id).unwrap_or(0);
         assert!(
-            self.admin_ids.contains(&env::predecessor_account_id()),
-            "Admin's method"
+            balance >= storage_cost,
+            "Insufficient storage deposit, requires {} more",
+            storage_cost - balance
         );
+        self.storage_deposits.insert(account_id, &(balance - storage_cost));
+        if storage_used > 0 {
+            let charged = self.storage_charged.get(account_id).unwrap_or(0);
+            self.storage_charged.insert(account_id, &(charged + storage_used));
+        }
+    }
+
+    /// Credits the storage freed since `initial_storage_usage` back to the account's storage
+    /// balance, up to the bytes the account was charged for. Storage of plain stakes is paid
+    /// by the contract and stays with it. Returns the bytes refunded.
+    pub(crate) fn internal_refund_storage(&mut self, account_id: &AccountId, initial_storage_usage: u64) -> u64 {
+        let storage_freed = initial_storage_usage.saturating_sub(env::storage_usage());
+        let charged = self.storage_charged.get(account_id).unwrap_or(0);
+        let refunded = min(storage_freed, charged);
+        if refunded > 0 {
+            if refunded == charged {
+                self.storage_charged.remove(account_id);
+            } else {
+                self.storage_charged.insert(account_id, &(charged - refunded));
+            }
+            let balance = self.storage_deposits.get(account_id).unwrap_or(0);
+            self.storage_deposits.insert(
+                account_id,
+                &(balance + Balance::from(refunded) * env::storage_byte_cost()),
+            );
+        }
+        refunded
+    }
+
+    /// Charges the storage added since `initial_storage_usage`, or refunds what was freed. For
+    /// unstakes that may queue an unbonding entry while emptying positions.
+    pub(crate) fn internal_settle_storage(&mut self, account_id: &AccountId, initial_storage_usage: u64) {
+        if env::storage_usage() > initial_storage_usage {
+            self.internal_charge_storage(account_id, initial_storage_usage);
+        } else {
+            self.internal_refund_storage(account_id, initial_storage_usage);
+        }
     }
 
     pub(crate) fn staking_informations_by_owner_id(
@@ -37,17 +80,7 @@ impl Contract {
         let stake_info = self.staking_informations_by_owner_id(farm_id, account_id);
         let now = env::block_timestamp() / 1000000;
 
-        let mut locked_amount: u128 = 0;
-
-        let start: u64 = 0;
-        let end = stake_info.unlocked_at.len();
-        for i in start..end {
-            if stake_info.unlocked_at.get(i).unwrap() > now {
-                locked_amount += u128::from(stake_info.token_locked.get(i).unwrap());
-            }
-        }
-
-        locked_amount
+        stake_info.locked_amount(PositionKind::Token, now)
     }
 
     pub(crate) fn lp_locked_amount(&self, farm_id: u16, account_id: AccountId) -> u128 {
@@ -59,224 +92,238 @@ impl Contract {
         let stake_info = self.staking_informations_by_owner_id(farm_id, account_id);
         let now = env::block_timestamp() / 1000000;
 
-        let mut locked_amount: u128 = 0;
-
-        let start: u64 = 0;
-        let end = stake_info.unlocked_at.len();
-        for i in start..end {
-            if stake_info.unlocked_at.get(i).unwrap() > now {
-                locked_amount += u128::from(stake_info.lp_share_locked.get(i).unwrap());
-            }
-        }
-
-        locked_amount
+        stake_info.locked_amount(PositionKind::LpShare, now)
     }
 
     pub(crate) fn reward_locked_amount(&self, farm_id: u16, account_id: AccountId) -> u128 {
         assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
-        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
         let stake_info = self.staking_informations_by_owner_id(farm_id, account_id);
         let now = env::block_timestamp() / 1000000;
 
-        let mut locked_amount: u128 = 0;
-
-        let start: u64 = 0;
-        let end = stake_info.unlocked_at.len();
-        for i in start..end {
-            if stake_info.unlocked_at.get(i).unwrap() > now {
-                locked_amount += u128::from(stake_info.reward_locked.get(i).unwrap());
-            }
-        }
-
-        locked_amount
+        stake_info.locked_amount(PositionKind::Reward, now)
     }
 
     pub(crate) fn claim_amount(&self, farm_id: u16, account_id: AccountId, token_type: u8, time: u64) -> u128 {
         assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
         let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
         let stake_info = self.staking_informations_by_owner_id(farm_id, account_id);
-        let mut now = time;
 
-        if now < farm_info.starting_at {
+        if token_type == 0 && farm_info.farm_type == 1 {
+            return 0;
+        } else if token_type == 1 && farm_info.farm_type == 0 {
             return 0;
-        } else if now > farm_info.ending_at {
-            now = farm_info.ending_at;
         }
+        let emission = farm_info.pool_emission(token_type);
+        let now = match emission.pool_settle_time(token_type, time) {
+            Some(now) => now,
+            None => return 0,
+        };
+
+        if token_type == 0 {
+            stake_info.reward_token_to_claim
+                + accrued_reward(&emission, stake_info.token_weight, farm_info.total_token_weight, stake_info.claimed_token_at, now)
+        } else if token_type == 1 {
+            stake_info.reward_lp_to_claim
+                + accrued_reward(&emission, stake_info.lp_share_weight, farm_info.total_lp_share_weight, stake_info.claimed_lp_at, now)
+        } else {
+            stake_info.reward_reward_to_claim
+                + reward_on_reward(
+                    &emission,
+                    stake_info.reward_positions(),
+                    farm_info.total_reward_amount,
+                    stake_info.claimed_reward_at,
+                    now,
+                )
+        }
+    }
 
+    pub(crate) fn update_claim_amounts(&mut self, farm_id: u16, token_type: u8, now: u64) {
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
         if token_type == 0 && farm_info.farm_type == 1 {
-            return 0;
+            return;
         } else if token_type == 1 && farm_info.farm_type == 0 {
-            return 0;
+            return;
+        }
+        let emission = farm_info.pool_emission(token_type);
+        let now = match emission.pool_settle_time(token_type, now) {
+            Some(now) => now,
+            None => return,
+        };
+        let keys_vec = farm_info.stake_infos.keys_as_vector().to_vec();
+        for key in keys_vec.iter() {
+            let mut stake_info: StakeInfo = farm_info.stake_infos.get(key).unwrap();
+            farm_info.accrue(&emission, &mut stake_info, token_type, now);
+            farm_info.stake_infos.insert(key, &stake_info);
         }
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+
+    /// Settles the account's rewards of the given pool and locks them for the reward vesting duration.
+    /// Reward-on-reward (`token_type == 2`) is settled apart from the vesting rewards it accrues on
+    /// and gets a reward position of its own, the positions it accrued on keep their amounts.
+    pub(crate) fn internal_claim_reward(&mut self, farm_id: u16, account_id: &AccountId, token_type: u8, now: u64) -> u128 {
         let mut claim_amount = 0;
-        if  token_type == 0 {
-            if (now / 1000) <= (stake_info.claimed_token_at / 1000) || stake_info.token_amount == 0 {
-                claim_amount = stake_info.reward_token_to_claim;
-            } else {
-                claim_amount = stake_info.reward_token_to_claim.checked_add(
-                    stake_info.token_weight
-                        .checked_mul(100000000)
-                        .unwrap()
-                        .checked_div(farm_info.total_token_weight)
-                        .unwrap()
-                        .checked_mul(farm_info.token_reward_rate)
-                        .unwrap()
-                        .checked_mul(((now / 1000) - (stake_info.claimed_token_at / 1000)).try_into().unwrap())
-                        .unwrap()
-                        .checked_div(100000000)
-                        .unwrap(),
-                )
-                .unwrap();
-            }
+
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        if token_type == 0 && farm_info.farm_type == 1 {
+            env::panic_str("Token Pool does not exist in this Farm");
+        } else if token_type == 1 && farm_info.farm_type == 0 {
+            env::panic_str("LP Pool does not exist in this Farm");
         }
 
-        if  token_type == 1 {
-            if (now / 1000) <= (stake_info.claimed_lp_at / 1000) || stake_info.lp_share_amount == 0{
-                claim_amount = stake_info.reward_lp_to_claim;
-            } else {
-                claim_amount = stake_info.reward_lp_to_claim.checked_add(
-                    stake_info.lp_share_weight
-                        .checked_mul(100000000)
-                        .unwrap()
-                        .checked_div(farm_info.total_lp_share_weight)
-                        .unwrap()
-                        .checked_mul(farm_info.pool_reward_rate)
-                        .unwrap()
-                        .checked_mul(((now / 1000) - (stake_info.claimed_lp_at / 1000)).try_into().unwrap())
-                        .unwrap()
-                        .checked_div(100000000)
-                        .unwrap(),
-                )
-                .unwrap();
-            }
+        if token_type == 0 {
+            self.update_claim_amounts(farm_id, 0, now);
+            self.update_claim_amounts(farm_id, 2, now);
+        }
+        else if token_type == 1 {
+            self.update_claim_amounts(farm_id, 1, now);
+            self.update_claim_amounts(farm_id, 2, now);
+        } else if token_type == 2 {
+            self.update_claim_amounts(farm_id, 2, now);
         }
+        farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let mut stake_info = farm_info.stake_infos.get(account_id).unwrap();
+
 
-        if stake_info.reward_amount != 0 && token_type == 2 {
-            for index in 0..stake_info.unlocked_at.len() {
-                if stake_info.reward_locked.get(index).unwrap() == U128(0) {
-                    continue;
-                } else {
-                    let mut last_time = now;
-                    if (stake_info.unlocked_at.get(index).unwrap() / 1000) < (now / 1000) {
-                        last_time = stake_info.unlocked_at.get(index).unwrap();
-                    }
-                    if (last_time / 1000) <= (stake_info.claimed_reward_at / 1000) {
-                        continue;
-                    }
-
-                    let mut reward_amount: u128 = 0;
-                    reward_amount = u128::from(stake_info.reward_locked.get(index).unwrap())
-                    .checked_mul(100000000)
-                    .unwrap()
-                    .checked_div(farm_info.total_reward_amount)
-                    .unwrap()
-                    .checked_mul(farm_info.reward_reward_rate)
-                    .unwrap()
-                    .checked_mul(((last_time / 1000) - (stake_info.claimed_reward_at / 1000)).try_into().unwrap())
-                    .unwrap()
-                    .checked_div(100000000)
+        if token_type == 0 {
+            claim_amount = self.claim_amount(farm_id, account_id.clone(), 0, now);
+            farm_info.distributed_reward_amount += claim_amount;
+            claim_amount -= self.internal_charge_fee(
+                farm_id,
+                account_id,
+                farm_info.token_id.as_str(),
+                "reward",
+                claim_amount,
+                self.fees_of(&farm_info).reward_fee_bps,
+            );
+            stake_info.reward_token_to_claim = 0;
+            stake_info.claimed_token_at = now;
+            self.internal_add_position(
+                farm_id,
+                &mut stake_info,
+                NewPosition {
+                    kind: PositionKind::Reward,
+                    amount: claim_amount,
+                    weight: claim_amount,
+                    created_at: now,
+                    lock_duration: farm_info.max_reward_vesting_duration,
+                    storage_payer: None,
+                },
+            );
+
+            stake_info.reward_amount = stake_info.reward_amount.checked_add(claim_amount)
                     .unwrap();
-                    claim_amount = claim_amount.checked_add(reward_amount).unwrap();
-                }
-            }
+            farm_info.total_reward_amount = farm_info.total_reward_amount.checked_add(claim_amount).unwrap();
+        }
+        else if token_type == 1 {
+            claim_amount = self.claim_amount(farm_id, account_id.clone(), 1, now);
+            farm_info.distributed_reward_amount += claim_amount;
+            claim_amount -= self.internal_charge_fee(
+                farm_id,
+                account_id,
+                farm_info.token_id.as_str(),
+                "reward",
+                claim_amount,
+                self.fees_of(&farm_info).reward_fee_bps,
+            );
+            stake_info.reward_lp_to_claim = 0;
+            stake_info.claimed_lp_at = now;
+            self.internal_add_position(
+                farm_id,
+                &mut stake_info,
+                NewPosition {
+                    kind: PositionKind::Reward,
+                    amount: claim_amount,
+                    weight: claim_amount,
+                    created_at: now,
+                    lock_duration: farm_info.max_reward_vesting_duration,
+                    storage_payer: None,
+                },
+            );
+
+            stake_info.reward_amount = stake_info.reward_amount.checked_add(claim_amount)
+                    .unwrap();
+            farm_info.total_reward_amount = farm_info.total_reward_amount.checked_add(claim_amount).unwrap();
+        }
+        else if token_type == 2 {
+            claim_amount = stake_info.reward_reward_to_claim;
+            farm_info.distributed_reward_amount += claim_amount;
+            claim_amount -= self.internal_charge_fee(
+                farm_id,
+                account_id,
+                farm_info.token_id.as_str(),
+                "reward",
+                claim_amount,
+                self.fees_of(&farm_info).reward_fee_bps,
+            );
+            stake_info.reward_reward_to_claim = 0;
+            self.internal_add_position(
+                farm_id,
+                &mut stake_info,
+                NewPosition {
+                    kind: PositionKind::Reward,
+                    amount: claim_amount,
+                    weight: claim_amount,
+                    created_at: now,
+                    lock_duration: farm_info.max_reward_vesting_duration,
+                    storage_payer: None,
+                },
+            );
+
+            stake_info.reward_amount = stake_info.reward_amount.checked_add(claim_amount).unwrap();
+            farm_info.total_reward_amount = farm_info.total_reward_amount.checked_add(claim_amount).unwrap();
         }
 
+        farm_info.stake_infos.insert(account_id, &stake_info);
+        self.farm_infos.replace(farm_id.into(), &farm_info);
         claim_amount
     }
 
-    pub(crate) fn update_claim_amounts(&mut self, farm_id: u16, token_type: u8, now: u64) {
-        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+    /// Withdraws `amount` from the unlocked positions of the given pool, oldest first. Returns
+    /// the bytes refunded to the storage payers of emptied positions.
+    pub(crate) fn internal_unstake(&mut self, farm_id: u16, account_id: &AccountId, token_type: u8, amount: U128) -> u64 {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+
         if token_type == 0 && farm_info.farm_type == 1 {
-            return;
+            env::panic_str("Token Pool does not exist in this Farm");
         } else if token_type == 1 && farm_info.farm_type == 0 {
-            return;
+            env::panic_str("LP Pool does not exist in this Farm");
         }
-        let keys_as_vector = farm_info.stake_infos.keys_as_vector();
-        let keys_vec = keys_as_vector.to_vec();
-        let total_reward_amount = farm_info.total_reward_amount;
-        for key in keys_vec.iter() {
-            let mut stake_info: StakeInfo = farm_info.stake_infos.get(&key).unwrap();
-            let mut now_time = now;
-            if token_type != 2 {
-                if now_time < farm_info.starting_at {
-                    return;
-                } else if now_time > farm_info.ending_at {
-                    now_time = farm_info.ending_at;
-                }
-            }
-    
-            if stake_info.token_amount != 0 && token_type == 0 {
-                if (now_time / 1000) > (stake_info.claimed_token_at / 1000) {
-                    stake_info.reward_token_to_claim = stake_info.reward_token_to_claim.checked_add(
-                        stake_info.token_weight
-                            .checked_mul(100000000)
-                            .unwrap()
-                            .checked_div(farm_info.total_token_weight)
-                            .unwrap()
-                            .checked_mul(farm_info.token_reward_rate)
-                            .unwrap()
-                            .checked_mul(((now_time / 1000) - (stake_info.claimed_token_at / 1000)).try_into().unwrap())
-                            .unwrap()
-                            .checked_div(100000000)
-                            .unwrap(),
-                    ).unwrap();
-                    stake_info.claimed_token_at = now_time;
-                }
-            }
-            if stake_info.lp_share_amount != 0 && token_type == 1 {
-                if (now_time / 1000) > (stake_info.claimed_lp_at / 1000) {
-                    stake_info.reward_lp_to_claim = stake_info.reward_lp_to_claim.checked_add(
-                        stake_info.lp_share_weight
-                            .checked_mul(100000000)
-                            .unwrap()
-                            .checked_div(farm_info.total_lp_share_weight)
-                            .unwrap()
-                            .checked_mul(farm_info.pool_reward_rate)
-                            .unwrap()
-                            .checked_mul(((now_time / 1000) - (stake_info.claimed_lp_at / 1000)).try_into().unwrap())
-                            .unwrap()
-                            .checked_div(100000000)
-                            .unwrap(),
-                    ).unwrap();
-                    stake_info.claimed_lp_at = now_time;
-                }
+
+        let kind = PositionKind::from_token_type(token_type);
+        let now = env::block_timestamp() / 1000000;
+        let stake_info = farm_info.stake_infos.get(account_id).unwrap();
+        assert!(
+            amount.0 <= stake_info.matured_amount(kind, now),
+            "Amount must be smaller than available amount"
+        );
+
+        self.update_claim_amounts(farm_id, token_type, now);
+
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let mut stake_info = farm_info.stake_infos.get(account_id).unwrap();
+
+        let mut remaining = amount.0;
+        let mut weight = 0;
+        let mut refunded = 0;
+        for position in stake_info.sorted_positions() {
+            if remaining == 0 {
+                break;
             }
-            if stake_info.reward_amount != 0 && token_type == 2 {
-                let mut sum_reward_amount: u128 = 0;
-                for index in 0..stake_info.unlocked_at.len() {
-                    if stake_info.reward_locked.get(index).unwrap() == U128(0) {
-                        continue;
-                    } else {
-                        let mut last_time = now_time;
-                        if (stake_info.unlocked_at.get(index).unwrap() / 1000) < (now_time / 1000) {
-                            last_time = stake_info.unlocked_at.get(index).unwrap();
-                        }
-                        if (last_time / 1000) <= (stake_info.claimed_reward_at / 1000) {
-                            continue;
-                        }
-
-                        let mut reward_amount: u128 = 0;
-                        reward_amount = u128::from(stake_info.reward_locked.get(index).unwrap())
-                        .checked_mul(100000000)
-                        .unwrap()
-                        .checked_div(total_reward_amount)
-                        .unwrap()
-                        .checked_mul(farm_info.reward_reward_rate)
-                        .unwrap()
-                        .checked_mul(((last_time / 1000) - (stake_info.claimed_reward_at / 1000)).try_into().unwrap())
-                        .unwrap()
-                        .checked_div(100000000)
-                        .unwrap();
-                        sum_reward_amount = sum_reward_amount.checked_add(reward_amount).unwrap();
-                        stake_info.reward_locked.replace(index, &U128::from(u128::from(stake_info.reward_locked.get(index).unwrap()).checked_add(reward_amount).unwrap()));
-                    }
-                }
-                stake_info.reward_amount = stake_info.reward_amount.checked_add(sum_reward_amount).unwrap();
-                farm_info.total_reward_amount = farm_info.total_reward_amount.checked_add(sum_reward_amount).unwrap();
-                stake_info.claimed_reward_at = now_time;
+            if position.kind != kind || position.is_locked(now) {
+                continue;
             }
-            farm_info.stake_infos.insert(&key, &stake_info);
+            let taken = min(remaining, position.amount);
+            let (taken_weight, position_refunded) = self.internal_reduce_position(&mut stake_info, position, taken);
+            weight += taken_weight;
+            refunded += position_refunded;
+            remaining -= taken;
         }
+        self.internal_release(&mut farm_info, &mut stake_info, kind, amount.0, weight);
+
+        farm_info.stake_infos.insert(account_id, &stake_info);
         self.farm_infos.replace(farm_id.into(), &farm_info);
+        refunded
     }
 }
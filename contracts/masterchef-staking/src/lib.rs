@@ -1,5 +1,5 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, UnorderedMap, UnorderedSet, Vector};
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, UnorderedSet, Vector};
 use near_sdk::json_types::{U128, U64};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
@@ -9,24 +9,75 @@ use near_sdk::{
 use std::convert::TryInto;
 use std::cmp::min;
 
+use crate::access::*;
+use crate::emission::*;
+use crate::epoch::*;
+use crate::events::*;
 use crate::external::*;
 use crate::farm_info::*;
+use crate::fees::*;
+use crate::fixed_point::*;
+use crate::limits::*;
+use crate::lock_nft::*;
+use crate::nft_boost::*;
+use crate::position::*;
+use crate::rewards::*;
+use crate::solvency::*;
 use crate::stake_info::*;
 use crate::swap::*;
+use crate::timelock::*;
+use crate::unbonding::*;
 
 use near_sdk::env::STORAGE_PRICE_PER_BYTE;
 
+mod access;
+mod emission;
+mod epoch;
+mod events;
 mod external;
 mod farm_info;
+mod fees;
+mod fixed_point;
 mod ft_callbacks;
 mod internal;
+mod limits;
+mod lock_nft;
+mod nft_boost;
+mod overview;
+mod ownership;
+mod position;
+mod preview;
+mod rescue;
+mod rewards;
+mod roles;
+mod rollover;
+mod solvency;
 mod stake_info;
+mod stats;
 mod swap;
+mod timelock;
+mod unbonding;
 mod view;
 
+#[cfg(test)]
+mod tests;
+
+/// receiver traits and roles, used by the offline reward simulator to drive the contract directly
+pub use crate::ft_callbacks::{FungibleTokenReceiver, MFTTokenReceiver};
+pub use crate::nft_boost::NonFungibleTokenReceiver;
+pub use crate::roles::Role;
+
 // TODO check seller supports storage_deposit at ft_token_id they want to post sale in
 
 const GAS_FOR_FT_TRANSFER: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_NFT_TRANSFER: Gas = Gas(15_000_000_000_000);
+const GAS_FOR_NFT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000);
+const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(20_000_000_000_000);
+const GAS_FOR_BALANCE_OF: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_RESCUE_CALLBACK: Gas = Gas(20_000_000_000_000);
+const GAS_FOR_SOLVENCY_CALLBACK: Gas = Gas(30_000_000_000_000);
+const GAS_FOR_CLAIM_PER_FARM: Gas = Gas(25_000_000_000_000);
+const MAX_CLAIM_ALL_FARMS: usize = 10;
 const STORAGE_PER_SALE: u128 = 1000 * STORAGE_PRICE_PER_BYTE;
 const TOKEN_CONTRACT_ID: &str = "unet.testnet";
 const REF_CONTRACT_ID: &str = "ref-finance-101.testnet";
@@ -50,19 +101,61 @@ pub struct StorageBalanceBounds {
 //     pub swap_farms: UnorderedMap<AccountId, SwapFarmInfo>,
 // }
 
+/// Layout of `Contract` before positions, roles and the timelock, read by `migrate`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV0 {
+    pub owner_id: AccountId,
+    pub farm_infos: Vector<FarmInfoV0>,
+    pub storage_deposits: LookupMap<AccountId, Balance>,
+    pub admin_ids: UnorderedSet<AccountId>,
+    pub editing_new_farm: EditingFarmInfo,
+    pub confirmed_admins_for_new_farm: UnorderedSet<AccountId>,
+    pub swap_farms: UnorderedMap<AccountId, SwapFarmInfo>,
+    pub editing_swap_farm_infos: UnorderedMap<AccountId, EditingSwapFarmInfoData>,
+    pub token_stake_info: LookupMap<AccountId, u128>,
+    pub lp_stake_info: LookupMap<u64, u128>,
+}
+
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     pub owner_id: AccountId,
+    pub proposed_owner_id: Option<AccountId>,
+    pub owner_renounced: bool,
+    pub dao_mode: bool,
     pub farm_infos: Vector<FarmInfo>,
     pub storage_deposits: LookupMap<AccountId, Balance>,
-    pub admin_ids: UnorderedSet<AccountId>,
+    /// storage bytes paid from each account's storage deposit, the most it gets refunded
+    pub storage_charged: LookupMap<AccountId, u64>,
+    pub role_holders: LookupMap<Role, UnorderedSet<AccountId>>,
     pub editing_new_farm: EditingFarmInfo,
     pub confirmed_admins_for_new_farm: UnorderedSet<AccountId>,
     pub swap_farms: UnorderedMap<AccountId, SwapFarmInfo>,
     pub editing_swap_farm_infos: UnorderedMap<AccountId, EditingSwapFarmInfoData>,
     pub token_stake_info: LookupMap<AccountId, u128>,
     pub lp_stake_info: LookupMap<u64, u128>,
+    pub nft_boost_collections: UnorderedMap<AccountId, u32>,
+    pub next_lock_id: u64,
+    pub lock_tokens: UnorderedMap<u64, LockToken>,
+    pub lock_tokens_per_owner: LookupMap<AccountId, UnorderedSet<u64>>,
+    pub rollover_accounts: LookupSet<AccountId>,
+    pub paused: bool,
+    pub default_fees: FeeConfig,
+    pub treasury_id: AccountId,
+    pub treasury: UnorderedMap<String, u128>,
+    pub timelock_delay: u64,
+    pub next_operation_id: u64,
+    pub timelock_operations: UnorderedMap<u64, TimelockOperation>,
+    /// token paid out to swap stakers
+    pub unet_token_id: AccountId,
+    /// Ref exchange holding the LP shares
+    pub ref_exchange_id: AccountId,
+    /// farms whose stake infos are still in the legacy layout, with the index of the next one to convert
+    pub migrating_farms: UnorderedMap<u16, u64>,
+    /// UNET owed to swap stakers, kept up to date by swap stakes and unstakes
+    pub swap_owed: u128,
+    /// swap farms whose stakes are not yet counted in `swap_owed`, with the index of the next one
+    pub migrating_swap_farms: UnorderedMap<AccountId, u64>,
 }
 
 /// Helper structure to for keys of the persistent collections.
@@ -97,6 +190,33 @@ pub enum StorageKey {
     },
     TokenStakeInfo,
     LPStakeInfo,
+    NftBoostCollections,
+    LockTokens,
+    LockTokensPerOwner,
+    LockTokensPerOwnerInner {
+        account_id: AccountId,
+    },
+    Positions {
+        farm_id: u16,
+        account_id: AccountId,
+    },
+    Unbondings {
+        farm_id: u16,
+        account_id: AccountId,
+    },
+    RolloverAccounts,
+    FarmAllowlist {
+        farm_id: u16,
+    },
+    RoleHolders,
+    RoleHoldersInner {
+        role: Role,
+    },
+    Treasury,
+    TimelockOperations,
+    StorageCharged,
+    MigratingFarms,
+    MigratingSwapFarms,
 }
 
 #[near_bindgen]
@@ -104,10 +224,14 @@ impl Contract {
     #[init]
     pub fn new(owner_id: AccountId) -> Self {
         let this = Self {
-            owner_id: owner_id.into(),
+            owner_id: owner_id.clone(),
+            proposed_owner_id: None,
+            owner_renounced: false,
+            dao_mode: false,
             farm_infos: Vector::new(StorageKey::FarmInfos),
             storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
-            admin_ids: UnorderedSet::new(StorageKey::AdminIds),
+            storage_charged: LookupMap::new(StorageKey::StorageCharged),
+            role_holders: LookupMap::new(StorageKey::RoleHolders),
             editing_new_farm: EditingFarmInfo {
                 farm_id: 0,
                 farm_type: 0,
@@ -129,6 +253,23 @@ impl Contract {
             editing_swap_farm_infos: UnorderedMap::new(StorageKey::EditingSwapFarmInfo),
             token_stake_info: LookupMap::new(StorageKey::TokenStakeInfo),
             lp_stake_info: LookupMap::new(StorageKey::LPStakeInfo),
+            nft_boost_collections: UnorderedMap::new(StorageKey::NftBoostCollections),
+            next_lock_id: 0,
+            lock_tokens: UnorderedMap::new(StorageKey::LockTokens),
+            lock_tokens_per_owner: LookupMap::new(StorageKey::LockTokensPerOwner),
+            rollover_accounts: LookupSet::new(StorageKey::RolloverAccounts),
+            paused: false,
+            default_fees: FeeConfig::default(),
+            treasury_id: owner_id.clone(),
+            treasury: UnorderedMap::new(StorageKey::Treasury),
+            timelock_delay: 0,
+            next_operation_id: 0,
+            timelock_operations: UnorderedMap::new(StorageKey::TimelockOperations),
+            unet_token_id: AccountId::new_unchecked(TOKEN_CONTRACT_ID.to_string()),
+            ref_exchange_id: AccountId::new_unchecked(REF_CONTRACT_ID.to_string()),
+            migrating_farms: UnorderedMap::new(StorageKey::MigratingFarms),
+            swap_owed: 0,
+            migrating_swap_farms: UnorderedMap::new(StorageKey::MigratingSwapFarms),
         };
         this
     }
@@ -154,97 +295,129 @@ impl Contract {
     //     this
     // }
 
-    #[payable]
-    pub fn add_admin(&mut self, account_id: AccountId) {
-        self.assert_owner();
-        self.admin_ids.insert(&account_id);
+    /// Upgrades the state written before positions. Farm headers are converted here, stake infos
+    /// farm by farm with `migrate_stake_infos`, the contract stays paused until all are converted.
+    /// Only the owner of the previous state can migrate.
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let prev: ContractV0 = env::state_read().expect("ERR_NOT_INITIALIZED");
+        assert_eq!(env::predecessor_account_id(), prev.owner_id, "Owner's method");
+
+        let mut farm_infos: Vector<FarmInfo> = Vector::new(StorageKey::FarmInfos);
+        let mut migrating_farms = UnorderedMap::new(StorageKey::MigratingFarms);
+        for farm_info in prev.farm_infos.iter() {
+            if !farm_info.stake_infos.is_empty() {
+                migrating_farms.insert(&farm_info.farm_id, &0);
+            }
+            // overwrites the header read just before, the stake infos keep their prefix
+            farm_infos.push(&FarmInfo::from(farm_info));
+        }
+
+        let mut migrating_swap_farms = UnorderedMap::new(StorageKey::MigratingSwapFarms);
+        for (token_id, swap_farm_info) in prev.swap_farms.iter() {
+            if !swap_farm_info.stake_infos.is_empty() {
+                migrating_swap_farms.insert(&token_id, &0);
+            }
+        }
+
+        let mut this = Contract::new(prev.owner_id.clone());
+        this.farm_infos = farm_infos;
+        this.storage_deposits = prev.storage_deposits;
+        this.editing_new_farm = prev.editing_new_farm;
+        this.confirmed_admins_for_new_farm = prev.confirmed_admins_for_new_farm;
+        this.swap_farms = prev.swap_farms;
+        this.editing_swap_farm_infos = prev.editing_swap_farm_infos;
+        this.token_stake_info = prev.token_stake_info;
+        this.lp_stake_info = prev.lp_stake_info;
+
+        // admins become farm managers, they confirmed new farms before roles existed
+        let mut admin_ids = prev.admin_ids;
+        if !admin_ids.is_empty() {
+            let mut farm_managers = UnorderedSet::new(StorageKey::RoleHoldersInner { role: Role::FarmManager });
+            farm_managers.extend(admin_ids.iter());
+            this.role_holders.insert(&Role::FarmManager, &farm_managers);
+            admin_ids.clear();
+        }
+        this.paused = !migrating_farms.is_empty() || !migrating_swap_farms.is_empty();
+        this.migrating_farms = migrating_farms;
+        this.migrating_swap_farms = migrating_swap_farms;
+        this
     }
 
-    #[payable]
-    pub fn remove_admin(&mut self, account_id: AccountId) {
-        self.assert_owner();
-        self.admin_ids.remove(&account_id);
-        let confirmed_admins_for_new_farm = self.confirmed_admins_for_new_farm.as_vector().to_vec();
-        if confirmed_admins_for_new_farm.contains(&account_id) {
-            self.confirmed_admins_for_new_farm.remove(&account_id);
-            if self.confirmed_admins_for_new_farm.len() == self.admin_ids.len() {
-                self.farm_infos.push(&FarmInfo::new(
-                    self.farm_infos.len() as u16,
-                    self.editing_new_farm.farm_type,
-                    self.editing_new_farm.token_id.clone(),
-                    self.editing_new_farm.token_decimal,
-                    self.editing_new_farm.pool_id,
-                    self.editing_new_farm.token_reward_rate,
-                    self.editing_new_farm.pool_reward_rate,
-                    self.editing_new_farm.reward_reward_rate,
-                    self.editing_new_farm.token_weight_rate,
-                    self.editing_new_farm.pool_weight_rate,
-                    self.editing_new_farm.max_token_vesting_duration,
-                    self.editing_new_farm.max_reward_vesting_duration,
-                    self.editing_new_farm.starting_at,
-                    self.editing_new_farm.ending_at,
-                ));
-                self.confirmed_admins_for_new_farm.clear();
-                self.editing_new_farm = EditingFarmInfo {
-                    farm_id: 0,
-                    farm_type: 0,
-                    token_id: AccountId::new_unchecked(TOKEN_CONTRACT_ID.to_string()),
-                    token_decimal: 0,
-                    pool_id: 0,
-                    token_reward_rate: U128(0),
-                    pool_reward_rate: U128(0),
-                    reward_reward_rate: U128(0),
-                    token_weight_rate: 0,
-                    pool_weight_rate: 0,
-                    max_token_vesting_duration: 0,
-                    max_reward_vesting_duration: 0,
-                    starting_at: 0,
-                    ending_at: 0,
-                };
+    /// Converts up to `limit` stake infos of the farm from the parallel lock vectors to
+    /// positions, starting at index `from` which must be where the previous call stopped.
+    /// Returns the index to continue from, the contract is unpaused once every farm is done.
+    /// Requires the `Upgrader` role.
+    pub fn migrate_stake_infos(&mut self, farm_id: u16, from: u64, limit: u64) -> u64 {
+        self.assert_role(Role::Upgrader);
+        let next = self.migrating_farms.get(&farm_id).expect("Farm is already migrated");
+        assert_eq!(from, next, "Stake infos are converted in order, continue from {}", next);
+        let now = env::block_timestamp() / 1000000;
+
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let legacy_infos: UnorderedMap<AccountId, LegacyStakeInfo> = with_layout(&farm_info.stake_infos);
+        let keys = legacy_infos.keys_as_vector();
+        let end = min(from.saturating_add(limit), keys.len());
+        for index in from..end {
+            let account_id = keys.get(index).unwrap();
+            let legacy = legacy_infos.get(&account_id).unwrap();
+            let stake_info = self.internal_migrate_stake_info(farm_id, legacy, now);
+            // `insert` would decode the replaced value with the new layout
+            farm_info.stake_infos.insert_raw(&account_id.try_to_vec().unwrap(), &stake_info.try_to_vec().unwrap());
+        }
+
+        if end == keys.len() {
+            self.migrating_farms.remove(&farm_id);
+            if self.is_migrated() {
+                self.paused = false;
             }
+        } else {
+            self.migrating_farms.insert(&farm_id, &end);
         }
+        end
+    }
 
-        let swap_token_len = self.editing_swap_farm_infos.keys_as_vector().to_vec().len();
-        if swap_token_len > 0 {
-            let swap_tokens = self.editing_swap_farm_infos.keys_as_vector().to_vec();
-            for idx in 0..swap_token_len {
-                let swap_token = swap_tokens.get(idx).unwrap();
-                let mut editing_swap_farm_info =
-                    self.editing_swap_farm_infos.get(&swap_token).unwrap();
-                if editing_swap_farm_info
-                    .confirmed_admins
-                    .contains(&account_id)
-                {
-                    editing_swap_farm_info.confirmed_admins.remove(&account_id);
-                    if self.admin_ids.len() <= editing_swap_farm_info.confirmed_admins.len() {
-                        let mut swap_farm_data = self.swap_farms.get(&swap_token).unwrap();
-                        swap_farm_data.min_lock_time = editing_swap_farm_info.min_lock_time;
-                        swap_farm_data.max_lock_time = editing_swap_farm_info.max_lock_time;
-                        swap_farm_data.swap_rate = editing_swap_farm_info.swap_rate;
-                        self.swap_farms.insert(&swap_token, &swap_farm_data);
-
-                        editing_swap_farm_info.confirmed_admins.clear();
-                        self.editing_swap_farm_infos
-                            .insert(&swap_token, &editing_swap_farm_info);
-                    } else {
-                        self.editing_swap_farm_infos
-                            .insert(&swap_token, &editing_swap_farm_info);
-                    }
-                }
+    /// Adds up to `limit` swap stakes of the swap farm to `swap_owed`, starting at index `from`
+    /// which must be where the previous call stopped. Returns the index to continue from.
+    /// Requires the `Upgrader` role.
+    pub fn migrate_swap_stakes(&mut self, token_id: AccountId, from: u64, limit: u64) -> u64 {
+        self.assert_role(Role::Upgrader);
+        let next = self.migrating_swap_farms.get(&token_id).expect("Swap farm is already migrated");
+        assert_eq!(from, next, "Swap stakes are counted in order, continue from {}", next);
+
+        let swap_farm_info = self.swap_farms.get(&token_id).unwrap();
+        let stake_infos = swap_farm_info.stake_infos.values_as_vector();
+        let end = min(from.saturating_add(limit), stake_infos.len());
+        for index in from..end {
+            let swap_stake_info = stake_infos.get(index).unwrap();
+            self.swap_owed = self.swap_owed.saturating_add(swap_stake_info.token_amount.0);
+        }
+
+        if end == stake_infos.len() {
+            self.migrating_swap_farms.remove(&token_id);
+            if self.is_migrated() {
+                self.paused = false;
             }
+        } else {
+            self.migrating_swap_farms.insert(&token_id, &end);
         }
+        end
+    }
+
+    /// whether every stake info and swap stake has been migrated
+    pub fn is_migrated(&self) -> bool {
+        self.migrating_farms.is_empty() && self.migrating_swap_farms.is_empty()
     }
 
+    /// deprecated, lists the `FarmManager` role holders
     pub fn get_adminlist(&self) -> Vec<AccountId> {
-        self.admin_ids.to_vec()
+        self.get_role_holders(Role::FarmManager)
     }
 
 
     #[payable]
     pub fn storage_deposit(&mut self, account_id: Option<AccountId>) {
-        let storage_account_id = account_id
-            .map(|a| a.into())
-            .unwrap_or_else(env::predecessor_account_id);
+        let storage_account_id = account_id.unwrap_or_else(env::predecessor_account_id);
         let deposit = env::attached_deposit();
         assert!(
             deposit >= STORAGE_PER_SALE,
@@ -0,0 +1,129 @@
+use crate::*;
+
+/// Deposit limits of a farm pool. Stakes going over a cap are cut to what still fits and the
+/// rest is returned to the sender as the unused amount of the transfer.
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct StakeLimits {
+    pub max_total_amount: Option<u128>,
+    pub max_account_amount: Option<u128>,
+    pub min_stake_amount: u128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PoolCapacityView {
+    pub max_total_amount: Option<U128>,
+    pub max_account_amount: Option<U128>,
+    pub min_stake_amount: U128,
+    /// `None` when the pool has no cap
+    pub remaining_total: Option<U128>,
+    /// `None` without a per-account cap or when no account was given
+    pub remaining_account: Option<U128>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmCapacityView {
+    pub token: PoolCapacityView,
+    pub lp_share: PoolCapacityView,
+}
+
+impl StakeLimits {
+    /// part of `amount` the pool accepts, given what is staked in total and by the account
+    pub fn accepted_amount(&self, amount: u128, total_amount: u128, account_amount: u128) -> u128 {
+        assert!(
+            amount >= self.min_stake_amount,
+            "Stake must be at least {}",
+            self.min_stake_amount
+        );
+        let mut accepted = amount;
+        if let Some(max_total_amount) = self.max_total_amount {
+            accepted = min(accepted, max_total_amount.saturating_sub(total_amount));
+        }
+        if let Some(max_account_amount) = self.max_account_amount {
+            accepted = min(accepted, max_account_amount.saturating_sub(account_amount));
+        }
+        assert!(accepted > 0, "Stake cap reached");
+        assert!(
+            accepted >= self.min_stake_amount,
+            "Remaining capacity is below the minimum stake of {}",
+            self.min_stake_amount
+        );
+        accepted
+    }
+
+    fn capacity_view(&self, total_amount: u128, account_amount: Option<u128>) -> PoolCapacityView {
+        PoolCapacityView {
+            max_total_amount: self.max_total_amount.map(U128),
+            max_account_amount: self.max_account_amount.map(U128),
+            min_stake_amount: U128(self.min_stake_amount),
+            remaining_total: self
+                .max_total_amount
+                .map(|max_total_amount| U128(max_total_amount.saturating_sub(total_amount))),
+            remaining_account: self.max_account_amount.and_then(|max_account_amount| {
+                account_amount.map(|account_amount| U128(max_account_amount.saturating_sub(account_amount)))
+            }),
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_set_farm_limits(
+        &mut self,
+        farm_id: u16,
+        token_type: u8,
+        max_total_amount: Option<U128>,
+        max_account_amount: Option<U128>,
+        min_stake_amount: U128,
+    ) {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let limits = StakeLimits {
+            max_total_amount: max_total_amount.map(|amount| amount.0),
+            max_account_amount: max_account_amount.map(|amount| amount.0),
+            min_stake_amount: min_stake_amount.0,
+        };
+        match token_type {
+            0 => farm_info.token_limits = limits,
+            1 => farm_info.lp_share_limits = limits,
+            _ => env::panic_str("Invalid token type"),
+        }
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the caps and minimum stake of the token (`token_type` 0) or LP (`token_type` 1) pool.
+    /// Requires the `FarmManager` role. Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_farm_limits(
+        &mut self,
+        farm_id: u16,
+        token_type: u8,
+        max_total_amount: Option<U128>,
+        max_account_amount: Option<U128>,
+        min_stake_amount: U128,
+    ) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.internal_set_farm_limits(farm_id, token_type, max_total_amount, max_account_amount, min_stake_amount);
+    }
+
+    pub fn get_farm_capacity(&self, farm_id: u16, account_id: Option<AccountId>) -> FarmCapacityView {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        // `None` without an account, `Some(None)` for an account that has not staked yet
+        let stake_info = account_id.map(|account_id| farm_info.stake_infos.get(&account_id));
+        FarmCapacityView {
+            token: farm_info.token_limits.capacity_view(
+                farm_info.total_token_amount,
+                stake_info.as_ref().map(|info| info.as_ref().map_or(0, |info| info.token_amount)),
+            ),
+            lp_share: farm_info.lp_share_limits.capacity_view(
+                farm_info.total_lp_share_amount,
+                stake_info.as_ref().map(|info| info.as_ref().map_or(0, |info| info.lp_share_amount)),
+            ),
+        }
+    }
+}
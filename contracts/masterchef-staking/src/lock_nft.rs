@@ -0,0 +1,407 @@
+use crate::*;
+
+use near_sdk::PromiseResult;
+use std::collections::HashMap;
+
+/// Every position of a farm is a NEP-171 token, its token id is the position id as a string.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LockToken {
+    pub farm_id: u16,
+    pub owner_id: AccountId,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NFTContractMetadata {
+    pub spec: String,
+    pub name: String,
+    pub symbol: String,
+    pub icon: Option<String>,
+    pub base_uri: Option<String>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub starts_at: Option<String>,
+    pub expires_at: Option<String>,
+    pub extra: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Token {
+    pub token_id: String,
+    pub owner_id: AccountId,
+    pub metadata: Option<TokenMetadata>,
+    pub approved_account_ids: HashMap<AccountId, u64>,
+}
+
+fn parse_lock_id(token_id: &str) -> u64 {
+    token_id.parse::<u64>().expect("Invalid token id")
+}
+
+impl Contract {
+    pub(crate) fn internal_mint_lock(&mut self, lock_id: u64, farm_id: u16, owner_id: &AccountId) {
+        self.lock_tokens.insert(
+            &lock_id,
+            &LockToken {
+                farm_id,
+                owner_id: owner_id.clone(),
+            },
+        );
+        self.internal_add_lock_to_owner(owner_id, lock_id);
+        emit_nft_event(
+            "nft_mint",
+            NftMintData {
+                owner_id,
+                token_ids: vec![lock_id.to_string()],
+            },
+        );
+    }
+
+    pub(crate) fn internal_burn_lock(&mut self, lock_id: u64) {
+        if let Some(lock_token) = self.lock_tokens.remove(&lock_id) {
+            self.internal_remove_lock_from_owner(&lock_token.owner_id, lock_id);
+            emit_nft_event(
+                "nft_burn",
+                NftMintData {
+                    owner_id: &lock_token.owner_id,
+                    token_ids: vec![lock_id.to_string()],
+                },
+            );
+        }
+    }
+
+    fn internal_add_lock_to_owner(&mut self, owner_id: &AccountId, lock_id: u64) {
+        let mut lock_ids = self.lock_tokens_per_owner.get(owner_id).unwrap_or_else(|| {
+            UnorderedSet::new(StorageKey::LockTokensPerOwnerInner {
+                account_id: owner_id.clone(),
+            })
+        });
+        lock_ids.insert(&lock_id);
+        self.lock_tokens_per_owner.insert(owner_id, &lock_ids);
+    }
+
+    fn internal_remove_lock_from_owner(&mut self, owner_id: &AccountId, lock_id: u64) {
+        if let Some(mut lock_ids) = self.lock_tokens_per_owner.get(owner_id) {
+            lock_ids.remove(&lock_id);
+            if lock_ids.is_empty() {
+                self.lock_tokens_per_owner.remove(owner_id);
+            } else {
+                self.lock_tokens_per_owner.insert(owner_id, &lock_ids);
+            }
+        }
+    }
+
+    pub(crate) fn internal_position(&self, lock_id: u64) -> Option<(u16, AccountId, Position)> {
+        let lock_token = self.lock_tokens.get(&lock_id)?;
+        let farm_info = self.farm_infos.get(lock_token.farm_id.into()).unwrap();
+        let stake_info = farm_info.stake_infos.get(&lock_token.owner_id).unwrap();
+        let position = stake_info.positions.get(&lock_id)?;
+        Some((lock_token.farm_id, lock_token.owner_id, position))
+    }
+
+    fn internal_lock_token_view(&self, lock_id: u64) -> Option<Token> {
+        let (farm_id, owner_id, position) = self.internal_position(lock_id)?;
+        let position_view = PositionView::new(farm_id, owner_id.clone(), &position);
+        Some(Token {
+            token_id: lock_id.to_string(),
+            owner_id,
+            metadata: Some(TokenMetadata {
+                title: Some(format!("Farm {} position #{}", farm_id, lock_id)),
+                description: Some(format!(
+                    "Staking position locked for {} ms",
+                    position.duration
+                )),
+                starts_at: Some(position.locked_at.to_string()),
+                expires_at: Some(position.unlocked_at.to_string()),
+                extra: Some(near_sdk::serde_json::to_string(&position_view).unwrap()),
+            }),
+            approved_account_ids: HashMap::new(),
+        })
+    }
+
+    /// Moves a still locked position with its weight and share of the pending rewards
+    /// from `sender_id` to `receiver_id`. Both accounts are settled first so neither side
+    /// gains or loses accrual for the time before the transfer. The other stakers only need
+    /// settling when the pool's total weight changes, i.e. when the two accounts have
+    /// different NFT boosts.
+    pub(crate) fn internal_transfer_lock(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        lock_id: u64,
+        memo: Option<String>,
+    ) {
+        let mut lock_token = self.lock_tokens.get(&lock_id).expect("Token not found");
+        assert_eq!(&lock_token.owner_id, sender_id, "Sender is not the token owner");
+        assert_ne!(sender_id, receiver_id, "Current and next owner must differ");
+        let farm_id = lock_token.farm_id;
+        let now = env::block_timestamp() / 1000000;
+
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let sender_info = farm_info.stake_infos.get(sender_id).unwrap();
+        let position = sender_info.positions.get(&lock_id).unwrap();
+        assert!(position.is_locked(now), "Only locked positions can be transferred");
+        let token_type = position.kind.token_type();
+        let reweighted = position.kind != PositionKind::Reward
+            && farm_info.stake_infos.get(receiver_id).map_or(position.weight, |receiver_info| {
+                receiver_info.boosted_weight(unboost_weight(&sender_info, position.weight))
+            }) != position.weight;
+        if reweighted {
+            self.update_claim_amounts(farm_id, token_type, now);
+        }
+
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let mut sender_info = farm_info.stake_infos.get(sender_id).unwrap();
+        let mut receiver_info = farm_info
+            .stake_infos
+            .get(receiver_id)
+            .unwrap_or_else(|| StakeInfo::new(farm_id, receiver_id.clone(), now));
+        farm_info.settle_stake_info(&mut sender_info, token_type, now);
+        farm_info.settle_stake_info(&mut receiver_info, token_type, now);
+
+        let mut position = sender_info.positions.remove(&lock_id).unwrap();
+
+        match position.kind {
+            PositionKind::Token => {
+                let pending = mul_div(position.weight, sender_info.reward_token_to_claim, sender_info.token_weight);
+                let received_weight = receiver_info.boosted_weight(unboost_weight(&sender_info, position.weight));
+
+                sender_info.token_amount -= position.amount;
+                sender_info.token_weight -= position.weight;
+                sender_info.reward_token_to_claim -= pending;
+                receiver_info.token_amount += position.amount;
+                receiver_info.token_weight += received_weight;
+                receiver_info.reward_token_to_claim += pending;
+                receiver_info.claimed_token_at = now;
+                farm_info.total_token_weight = farm_info.total_token_weight - position.weight + received_weight;
+                position.weight = received_weight;
+            }
+            PositionKind::LpShare => {
+                let pending = mul_div(position.weight, sender_info.reward_lp_to_claim, sender_info.lp_share_weight);
+                let received_weight = receiver_info.boosted_weight(unboost_weight(&sender_info, position.weight));
+
+                sender_info.lp_share_amount -= position.amount;
+                sender_info.lp_share_weight -= position.weight;
+                sender_info.reward_lp_to_claim -= pending;
+                receiver_info.lp_share_amount += position.amount;
+                receiver_info.lp_share_weight += received_weight;
+                receiver_info.reward_lp_to_claim += pending;
+                receiver_info.claimed_lp_at = now;
+                farm_info.total_lp_share_weight = farm_info.total_lp_share_weight - position.weight + received_weight;
+                position.weight = received_weight;
+            }
+            PositionKind::Reward => {
+                let pending = mul_div(position.amount, sender_info.reward_reward_to_claim, sender_info.reward_amount);
+
+                sender_info.reward_amount -= position.amount;
+                sender_info.reward_reward_to_claim -= pending;
+                receiver_info.reward_amount += position.amount;
+                receiver_info.reward_reward_to_claim += pending;
+                receiver_info.claimed_reward_at = now;
+            }
+        }
+        receiver_info.positions.insert(&lock_id, &position);
+
+        farm_info.stake_infos.insert(sender_id, &sender_info);
+        farm_info.stake_infos.insert(receiver_id, &receiver_info);
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+
+        lock_token.owner_id = receiver_id.clone();
+        self.lock_tokens.insert(&lock_id, &lock_token);
+        self.internal_remove_lock_from_owner(sender_id, lock_id);
+        self.internal_add_lock_to_owner(receiver_id, lock_id);
+
+        emit_nft_event(
+            "nft_transfer",
+            NftTransferData {
+                old_owner_id: sender_id,
+                new_owner_id: receiver_id,
+                token_ids: vec![lock_id.to_string()],
+                memo,
+            },
+        );
+    }
+}
+
+impl Contract {
+    /// A transferred position is a new stake for the receiver: the farm's access rules and the
+    /// receiver's cap apply. The farm total does not change.
+    fn assert_can_receive_lock(&self, receiver_id: &AccountId, lock_id: u64) {
+        let (farm_id, _, position) = self.internal_position(lock_id).expect("Token not found");
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        assert!(
+            self.internal_may_receive(&farm_info, receiver_id),
+            "Receiver is not eligible for this Farm"
+        );
+        let receiver_info = farm_info.stake_infos.get(receiver_id);
+        let (limits, staked) = match position.kind {
+            PositionKind::Token => (&farm_info.token_limits, receiver_info.map_or(0, |info| info.token_amount)),
+            PositionKind::LpShare => (&farm_info.lp_share_limits, receiver_info.map_or(0, |info| info.lp_share_amount)),
+            PositionKind::Reward => return,
+        };
+        if let Some(max_account_amount) = limits.max_account_amount {
+            assert!(
+                staked + position.amount <= max_account_amount,
+                "Position does not fit under the receiver's cap"
+            );
+        }
+    }
+}
+
+fn unboost_weight(stake_info: &StakeInfo, weight: u128) -> u128 {
+    if let Some(nft_boost) = &stake_info.nft_boost {
+        weight
+            .checked_mul(BOOST_MULTIPLIER_DENOMINATOR)
+            .unwrap()
+            .checked_div(u128::from(nft_boost.multiplier))
+            .unwrap()
+    } else {
+        weight
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn nft_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+    ) {
+        self.assert_not_paused();
+        assert_one_yocto();
+        assert!(approval_id.is_none(), "Approvals are not supported");
+        let sender_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        self.assert_can_receive_lock(&receiver_id, parse_lock_id(&token_id));
+        self.internal_transfer_lock(&sender_id, &receiver_id, parse_lock_id(&token_id), memo);
+        self.internal_charge_storage(&sender_id, initial_storage_usage);
+    }
+
+    #[payable]
+    pub fn nft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        token_id: String,
+        approval_id: Option<u64>,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        self.assert_not_paused();
+        assert_one_yocto();
+        assert!(approval_id.is_none(), "Approvals are not supported");
+        let sender_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        self.assert_can_receive_lock(&receiver_id, parse_lock_id(&token_id));
+        self.internal_transfer_lock(&sender_id, &receiver_id, parse_lock_id(&token_id), memo);
+        self.internal_charge_storage(&sender_id, initial_storage_usage);
+
+        ext_nft_receiver::ext(receiver_id.clone())
+            .with_static_gas(GAS_FOR_NFT_TRANSFER_CALL)
+            .nft_on_transfer(sender_id.clone(), sender_id.clone(), token_id.clone(), msg)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .nft_resolve_transfer(sender_id, receiver_id, token_id),
+            )
+            .into()
+    }
+
+    /// Returns true if the token stayed with `receiver_id`.
+    #[private]
+    pub fn nft_resolve_transfer(
+        &mut self,
+        previous_owner_id: AccountId,
+        receiver_id: AccountId,
+        token_id: String,
+    ) -> bool {
+        let must_revert = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                near_sdk::serde_json::from_slice::<bool>(&value).unwrap_or(true)
+            }
+            _ => true,
+        };
+        if !must_revert {
+            return true;
+        }
+
+        let lock_id = parse_lock_id(&token_id);
+        let now = env::block_timestamp() / 1000000;
+        if let Some((_, owner_id, position)) = self.internal_position(lock_id) {
+            // the receiver may have moved it on already, and matured positions are no longer transferable
+            if owner_id == receiver_id && position.is_locked(now) {
+                self.internal_transfer_lock(&receiver_id, &previous_owner_id, lock_id, None);
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn nft_token(&self, token_id: String) -> Option<Token> {
+        self.internal_lock_token_view(parse_lock_id(&token_id))
+    }
+
+    pub fn nft_metadata(&self) -> NFTContractMetadata {
+        NFTContractMetadata {
+            spec: "nft-1.0.0".to_string(),
+            name: "UON Staking Locks".to_string(),
+            symbol: "UONLOCK".to_string(),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        }
+    }
+
+    pub fn nft_total_supply(&self) -> U128 {
+        U128(u128::from(self.lock_tokens.len()))
+    }
+
+    pub fn nft_tokens(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Token> {
+        let start = u128::from(from_index.unwrap_or(U128(0))) as usize;
+        self.lock_tokens
+            .keys()
+            .skip(start)
+            .take(limit.unwrap_or(50) as usize)
+            .filter_map(|lock_id| self.internal_lock_token_view(lock_id))
+            .collect()
+    }
+
+    pub fn nft_supply_for_owner(&self, account_id: AccountId) -> U128 {
+        U128(
+            self.lock_tokens_per_owner
+                .get(&account_id)
+                .map(|lock_ids| u128::from(lock_ids.len()))
+                .unwrap_or(0),
+        )
+    }
+
+    pub fn nft_tokens_for_owner(
+        &self,
+        account_id: AccountId,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Token> {
+        let lock_ids = if let Some(lock_ids) = self.lock_tokens_per_owner.get(&account_id) {
+            lock_ids
+        } else {
+            return vec![];
+        };
+        let start = u128::from(from_index.unwrap_or(U128(0))) as usize;
+        lock_ids
+            .iter()
+            .skip(start)
+            .take(limit.unwrap_or(50) as usize)
+            .filter_map(|lock_id| self.internal_lock_token_view(lock_id))
+            .collect()
+    }
+}
@@ -0,0 +1,250 @@
+use crate::*;
+
+use near_sdk::PromiseResult;
+
+/// boost multipliers are expressed in basis points, 10000 leaves the weight unchanged
+pub const BOOST_MULTIPLIER_DENOMINATOR: u128 = 10000;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct NftBoost {
+    pub nft_contract_id: AccountId,
+    pub token_id: String,
+    pub multiplier: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftBoostArgs {
+    pub farm_id: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftBoostViewInfo {
+    pub nft_contract_id: AccountId,
+    pub token_id: String,
+    pub multiplier: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftBoostCollectionViewInfo {
+    pub nft_contract_id: AccountId,
+    pub multiplier: u32,
+}
+
+/// callbacks from NFT Contracts
+pub trait NonFungibleTokenReceiver {
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: String,
+        msg: String,
+    ) -> PromiseOrValue<bool>;
+}
+
+impl Contract {
+    fn is_nft_boosted(&self, farm_id: u16, account_id: &AccountId) -> bool {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        farm_info
+            .stake_infos
+            .get(account_id)
+            .is_some_and(|stake_info| stake_info.nft_boost.is_some())
+    }
+
+    /// Settles the account's token and LP rewards at its current weights, then replaces its
+    /// NFT boost and rescales the weights of its positions to the new multiplier.
+    fn internal_set_nft_boost(&mut self, farm_id: u16, account_id: &AccountId, nft_boost: Option<NftBoost>, now: u64) -> Option<NftBoost> {
+        self.update_claim_amounts(farm_id, 0, now);
+        self.update_claim_amounts(farm_id, 1, now);
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let mut stake_info = farm_info.stake_infos.get(account_id).expect("No stake in this Farm");
+
+        let multiplier_of = |nft_boost: &Option<NftBoost>| {
+            nft_boost
+                .as_ref()
+                .map_or(BOOST_MULTIPLIER_DENOMINATOR, |nft_boost| u128::from(nft_boost.multiplier))
+        };
+        let numerator = multiplier_of(&nft_boost);
+        let previous = std::mem::replace(&mut stake_info.nft_boost, nft_boost);
+        let (prev_token_weight, prev_lp_share_weight) = stake_info.rescale_weights(numerator, multiplier_of(&previous));
+        farm_info.total_token_weight = farm_info.total_token_weight - prev_token_weight + stake_info.token_weight;
+        farm_info.total_lp_share_weight =
+            farm_info.total_lp_share_weight - prev_lp_share_weight + stake_info.lp_share_weight;
+
+        farm_info.stake_infos.insert(account_id, &stake_info);
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+        previous
+    }
+}
+
+#[near_bindgen]
+impl NonFungibleTokenReceiver for Contract {
+    /// Deposits an NFT of an allowlisted collection into a farm and boosts the owner's weights.
+    fn nft_on_transfer(
+        &mut self,
+        sender_id: AccountId,
+        previous_owner_id: AccountId,
+        token_id: String,
+        msg: String,
+    ) -> PromiseOrValue<bool> {
+        self.assert_not_paused();
+        let NftBoostArgs { farm_id } =
+            near_sdk::serde_json::from_str(&msg).expect("Invalid NftBoostArgs");
+        let nft_contract_id = env::predecessor_account_id();
+        let multiplier = self
+            .nft_boost_collections
+            .get(&nft_contract_id)
+            .expect("NFT collection is not allowed to boost");
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let account_id = previous_owner_id;
+        let now = env::block_timestamp() / 1000000;
+
+        // a boost without a stake would need storage nobody paid for
+        if self.farm_infos.get(farm_id.into()).unwrap().stake_infos.get(&account_id).is_none() {
+            env::log_str(&format!("{} has no stake in farm {}, returning NFT {}", account_id, farm_id, token_id));
+            return PromiseOrValue::Value(true);
+        }
+
+        let boosted = self.is_nft_boosted(farm_id, &account_id);
+        assert!(!boosted, "Farm is already boosted by an NFT");
+
+        self.internal_set_nft_boost(
+            farm_id,
+            &account_id,
+            Some(NftBoost {
+                nft_contract_id,
+                token_id: token_id.clone(),
+                multiplier,
+            }),
+            now,
+        );
+        env::log_str(&format!(
+            "{} boosted farm {} with NFT {} sent by {}",
+            account_id, farm_id, token_id, sender_id
+        ));
+        PromiseOrValue::Value(false)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_set_nft_boost_collection(&mut self, nft_contract_id: AccountId, multiplier: u32) {
+        assert!(
+            u128::from(multiplier) >= BOOST_MULTIPLIER_DENOMINATOR,
+            "Multiplier must be at least {}",
+            BOOST_MULTIPLIER_DENOMINATOR
+        );
+        self.nft_boost_collections.insert(&nft_contract_id, &multiplier);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires the `FarmManager` role. Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_nft_boost_collection(&mut self, nft_contract_id: AccountId, multiplier: u32) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.internal_set_nft_boost_collection(nft_contract_id, multiplier);
+    }
+
+    /// Requires the `FarmManager` role. Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn remove_nft_boost_collection(&mut self, nft_contract_id: AccountId) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.nft_boost_collections.remove(&nft_contract_id);
+    }
+
+    /// Settles rewards with the boosted weights, removes the boost and returns the NFT to its
+    /// owner. The boost is restored by `on_nft_withdraw` if the NFT cannot be sent back.
+    #[payable]
+    pub fn nft_withdraw(&mut self, farm_id: u16) -> Promise {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let now = env::block_timestamp() / 1000000;
+        let boosted = self.is_nft_boosted(farm_id, &account_id);
+        assert!(boosted, "No NFT boost in this Farm");
+
+        let nft_boost = self.internal_set_nft_boost(farm_id, &account_id, None, now).unwrap();
+        ext_transfer::ext(nft_boost.nft_contract_id.clone())
+            .with_attached_deposit(1)
+            .with_static_gas(GAS_FOR_NFT_TRANSFER)
+            .nft_transfer(account_id.clone(), nft_boost.token_id.clone(), None, None)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .on_nft_withdraw(
+                        farm_id,
+                        account_id,
+                        nft_boost.nft_contract_id,
+                        nft_boost.token_id,
+                        nft_boost.multiplier,
+                    ),
+            )
+    }
+
+    /// Puts the boost back if the NFT transfer of `nft_withdraw` failed, so the NFT stays on
+    /// record and can be withdrawn again. Returns true if the NFT was returned.
+    #[private]
+    pub fn on_nft_withdraw(
+        &mut self,
+        farm_id: u16,
+        account_id: AccountId,
+        nft_contract_id: AccountId,
+        token_id: String,
+        multiplier: u32,
+    ) -> bool {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return true;
+        }
+        let now = env::block_timestamp() / 1000000;
+        let boosted = self.is_nft_boosted(farm_id, &account_id);
+        if boosted {
+            // another NFT was deposited in the meantime, only one can be on record
+            env::log_str(&format!(
+                "NFT {} of {} could not be returned to {} and farm {} is boosted by another NFT",
+                token_id, nft_contract_id, account_id, farm_id
+            ));
+            return false;
+        }
+        self.internal_set_nft_boost(
+            farm_id,
+            &account_id,
+            Some(NftBoost {
+                nft_contract_id,
+                token_id,
+                multiplier,
+            }),
+            now,
+        );
+        false
+    }
+
+    pub fn get_nft_boost_collections(&self) -> Vec<NftBoostCollectionViewInfo> {
+        self.nft_boost_collections
+            .iter()
+            .map(|(nft_contract_id, multiplier)| NftBoostCollectionViewInfo {
+                nft_contract_id,
+                multiplier,
+            })
+            .collect()
+    }
+
+    pub fn get_nft_boost(&self, farm_id: u16, account_id: AccountId) -> Option<NftBoostViewInfo> {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        farm_info
+            .stake_infos
+            .get(&account_id)
+            .and_then(|stake_info| stake_info.nft_boost)
+            .map(|nft_boost| NftBoostViewInfo {
+                nft_contract_id: nft_boost.nft_contract_id,
+                token_id: nft_boost.token_id,
+                multiplier: nft_boost.multiplier,
+            })
+    }
+}
@@ -0,0 +1,140 @@
+use crate::*;
+
+/// Staked amount of one kind of position, split by whether it is still locked.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PrincipalView {
+    pub amount: U128,
+    pub locked: U128,
+    pub available: U128,
+}
+
+impl PrincipalView {
+    fn new(locked: u128, available: u128) -> Self {
+        Self {
+            amount: U128(locked + available),
+            locked: U128(locked),
+            available: U128(available),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmOverview {
+    pub farm_id: u16,
+    pub farm_type: u8,
+    pub token_id: AccountId,
+    pub pool_id: u64,
+    pub token: PrincipalView,
+    pub lp_share: PrincipalView,
+    /// claimed rewards still vesting or not withdrawn yet
+    pub reward: PrincipalView,
+    pub pending_token_reward: U128,
+    pub pending_lp_reward: U128,
+    pub pending_reward_reward: U128,
+    /// earliest unlock of a locked position, `None` if nothing is locked
+    pub next_unlock_at: Option<u64>,
+    /// NFT boost included
+    pub token_weight: U128,
+    pub lp_share_weight: U128,
+    pub total_token_weight: U128,
+    pub total_lp_share_weight: U128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SwapFarmOverview {
+    pub token_id: AccountId,
+    /// UNET still owed, `available` is what `unstake_swap` can pay out now
+    pub unet: PrincipalView,
+    /// earliest time a swap is fully vested, `None` if every swap is
+    pub next_unlock_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountOverview {
+    pub account_id: AccountId,
+    pub farms: Vec<FarmOverview>,
+    pub swap_farms: Vec<SwapFarmOverview>,
+}
+
+impl Contract {
+    fn farm_overview(&self, farm_info: &FarmInfo, stake_info: &StakeInfo, now: u64) -> FarmOverview {
+        let farm_id = farm_info.farm_id;
+        let principal =
+            |kind| PrincipalView::new(stake_info.locked_amount(kind, now), stake_info.matured_amount(kind, now));
+        let pending = |token_type| U128(self.claim_amount(farm_id, stake_info.owner_id.clone(), token_type, now));
+        FarmOverview {
+            farm_id,
+            farm_type: farm_info.farm_type,
+            token_id: farm_info.token_id.clone(),
+            pool_id: farm_info.pool_id,
+            token: principal(PositionKind::Token),
+            lp_share: principal(PositionKind::LpShare),
+            reward: principal(PositionKind::Reward),
+            pending_token_reward: pending(0),
+            pending_lp_reward: pending(1),
+            pending_reward_reward: pending(2),
+            next_unlock_at: stake_info
+                .positions
+                .values()
+                .filter(|position| position.is_locked(now))
+                .map(|position| position.unlocked_at)
+                .min(),
+            token_weight: U128(stake_info.token_weight),
+            lp_share_weight: U128(stake_info.lp_share_weight),
+            total_token_weight: U128(farm_info.total_token_weight),
+            total_lp_share_weight: U128(farm_info.total_lp_share_weight),
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Everything the account holds in every farm and swap farm, as of the current block.
+    pub fn get_account_overview(&self, account_id: AccountId) -> AccountOverview {
+        let now = env::block_timestamp() / 1000000;
+        let farms = self
+            .farm_infos
+            .iter()
+            .filter_map(|farm_info| {
+                let stake_info = farm_info.stake_infos.get(&account_id)?;
+                Some(self.farm_overview(&farm_info, &stake_info, now))
+            })
+            .collect();
+
+        let swap_farms = self
+            .swap_farms
+            .iter()
+            .filter_map(|(token_id, swap_farm_info)| {
+                let stake_info = swap_farm_info.stake_infos.get(&account_id)?;
+                let mut locked = 0;
+                let mut available = 0;
+                let mut next_unlock_at: Option<u64> = None;
+                for idx in 0..stake_info.token_locked.len() {
+                    let amount = stake_info.token_locked.get(idx).unwrap().0;
+                    let vested = swap_vested_amount_of(&swap_farm_info, &stake_info, idx, now);
+                    available += vested;
+                    locked += amount - vested;
+                    let unlocked_at = stake_info.unlocked_at.get(idx).unwrap();
+                    if amount > vested && unlocked_at > now {
+                        next_unlock_at = Some(next_unlock_at.map_or(unlocked_at, |next| min(next, unlocked_at)));
+                    }
+                }
+                Some(SwapFarmOverview {
+                    token_id,
+                    unet: PrincipalView::new(locked, available),
+                    next_unlock_at,
+                })
+            })
+            .collect();
+
+        AccountOverview {
+            account_id,
+            farms,
+            swap_farms,
+        }
+    }
+}
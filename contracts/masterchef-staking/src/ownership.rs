@@ -0,0 +1,98 @@
+use crate::*;
+
+impl Contract {
+    /// whether the call comes from the owner DAO, which confirms multi-holder edits on its own
+    pub(crate) fn is_dao_call(&self) -> bool {
+        self.dao_mode && !self.owner_renounced && env::predecessor_account_id() == self.owner_id
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires the owner. The proposed account becomes the owner once it calls
+    /// `accept_ownership`, a new proposal replaces the pending one.
+    #[payable]
+    pub fn propose_owner(&mut self, new_owner_id: AccountId) {
+        self.assert_owner();
+        assert_one_yocto();
+        assert_ne!(new_owner_id, self.owner_id, "Account is already the owner");
+        self.proposed_owner_id = Some(new_owner_id.clone());
+        emit_ownership_event("owner_proposed", &self.owner_id, Some(&new_owner_id));
+    }
+
+    #[payable]
+    pub fn accept_ownership(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert_eq!(
+            self.proposed_owner_id.as_ref(),
+            Some(&account_id),
+            "Not the proposed owner"
+        );
+        self.proposed_owner_id = None;
+        let old_owner_id = std::mem::replace(&mut self.owner_id, account_id);
+        emit_ownership_event("ownership_transferred", &old_owner_id, Some(&self.owner_id));
+    }
+
+    /// Requires the owner.
+    #[payable]
+    pub fn cancel_owner_proposal(&mut self) {
+        self.assert_owner();
+        assert_one_yocto();
+        let proposed_owner_id = self.proposed_owner_id.take().expect("No pending owner proposal");
+        emit_ownership_event("owner_proposal_cancelled", &self.owner_id, Some(&proposed_owner_id));
+    }
+
+    /// Requires the owner. Owner-only actions (granting and revoking roles) are frozen for
+    /// good and the owner no longer passes role checks, accounts holding a role keep it.
+    #[payable]
+    pub fn renounce_ownership(&mut self) {
+        self.assert_owner();
+        assert_one_yocto();
+        self.owner_renounced = true;
+        self.proposed_owner_id = None;
+        emit_ownership_event("ownership_renounced", &self.owner_id, None);
+    }
+
+    /// Requires the owner. In DAO mode the owner is expected to be a Sputnik v2 DAO whose
+    /// function-call proposals batch the privileged calls. A farm or swap farm edit the DAO
+    /// saves or confirms applies at once, the vote stands in for the per-holder confirmations.
+    #[payable]
+    pub fn set_dao_mode(&mut self, enabled: bool) {
+        self.assert_owner();
+        assert_one_yocto();
+        self.dao_mode = enabled;
+    }
+
+    pub fn is_dao_mode(&self) -> bool {
+        self.dao_mode
+    }
+
+    /// Requires the owner. Points the contract at the UNET token paying swap stakers and at the
+    /// Ref exchange holding LP shares, for other networks and local sandboxes.
+    #[payable]
+    pub fn set_external_contracts(&mut self, unet_token_id: AccountId, ref_exchange_id: AccountId) {
+        self.assert_owner();
+        assert_one_yocto();
+        self.unet_token_id = unet_token_id;
+        self.ref_exchange_id = ref_exchange_id;
+    }
+
+    /// `(unet_token_id, ref_exchange_id)`
+    pub fn get_external_contracts(&self) -> (AccountId, AccountId) {
+        (self.unet_token_id.clone(), self.ref_exchange_id.clone())
+    }
+
+    /// `None` once ownership was renounced
+    pub fn get_owner(&self) -> Option<AccountId> {
+        if self.owner_renounced {
+            None
+        } else {
+            Some(self.owner_id.clone())
+        }
+    }
+
+    pub fn get_proposed_owner(&self) -> Option<AccountId> {
+        self.proposed_owner_id.clone()
+    }
+}
@@ -0,0 +1,434 @@
+use crate::*;
+
+/// What a position locks: farm tokens, Ref LP shares or claimed rewards.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PositionKind {
+    Token,
+    LpShare,
+    Reward,
+}
+
+impl PositionKind {
+    /// maps the `token_type` argument used across the contract methods
+    pub fn from_token_type(token_type: u8) -> Self {
+        match token_type {
+            0 => PositionKind::Token,
+            1 => PositionKind::LpShare,
+            2 => PositionKind::Reward,
+            _ => env::panic_str("Invalid token type"),
+        }
+    }
+
+    pub fn token_type(&self) -> u8 {
+        match self {
+            PositionKind::Token => 0,
+            PositionKind::LpShare => 1,
+            PositionKind::Reward => 2,
+        }
+    }
+}
+
+/// A single stake or claimed reward with its own lock. The id is shared with the
+/// NEP-171 lock token representing the position. Reward positions weigh their amount.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct Position {
+    pub id: u64,
+    pub kind: PositionKind,
+    pub amount: u128,
+    pub weight: u128,
+    pub locked_at: u64,
+    pub unlocked_at: u64,
+    pub duration: u64,
+    /// account charged for the storage of a position staked on someone else's behalf, its
+    /// storage is refunded to that account when the position is removed
+    pub storage_payer: Option<AccountId>,
+}
+
+impl Position {
+    pub fn is_locked(&self, now: u64) -> bool {
+        self.unlocked_at > now
+    }
+}
+
+/// A position to open with `internal_add_position`, locked from `created_at` for `lock_duration`.
+pub struct NewPosition {
+    pub kind: PositionKind,
+    pub amount: u128,
+    pub weight: u128,
+    pub created_at: u64,
+    pub lock_duration: u64,
+    pub storage_payer: Option<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PositionView {
+    pub id: u64,
+    pub farm_id: u16,
+    pub owner_id: AccountId,
+    pub kind: PositionKind,
+    pub amount: U128,
+    pub weight: U128,
+    pub locked_at: u64,
+    pub unlocked_at: u64,
+    pub duration: u64,
+}
+
+impl PositionView {
+    pub fn new(farm_id: u16, owner_id: AccountId, position: &Position) -> Self {
+        Self {
+            id: position.id,
+            farm_id,
+            owner_id,
+            kind: position.kind,
+            amount: U128(position.amount),
+            weight: U128(position.weight),
+            locked_at: position.locked_at,
+            unlocked_at: position.unlocked_at,
+            duration: position.duration,
+        }
+    }
+}
+
+impl StakeInfo {
+    /// positions sorted by id, i.e. in the order they were opened
+    pub fn sorted_positions(&self) -> Vec<Position> {
+        let mut positions = self.positions.values().collect::<Vec<Position>>();
+        positions.sort_by_key(|position| position.id);
+        positions
+    }
+
+    pub fn locked_amount(&self, kind: PositionKind, now: u64) -> u128 {
+        self.positions
+            .values()
+            .filter(|position| position.kind == kind && position.is_locked(now))
+            .map(|position| position.amount)
+            .sum()
+    }
+
+    pub fn matured_amount(&self, kind: PositionKind, now: u64) -> u128 {
+        self.positions
+            .values()
+            .filter(|position| position.kind == kind && !position.is_locked(now))
+            .map(|position| position.amount)
+            .sum()
+    }
+
+    /// Scales the weight of every token and LP position by `numerator / denominator` and
+    /// recomputes the account weights from them. Returns the previous account weights.
+    pub fn rescale_weights(&mut self, numerator: u128, denominator: u128) -> (u128, u128) {
+        let previous = (self.token_weight, self.lp_share_weight);
+        self.token_weight = 0;
+        self.lp_share_weight = 0;
+        for mut position in self.sorted_positions() {
+            if position.kind == PositionKind::Reward {
+                continue;
+            }
+            position.weight = position.weight
+                .checked_mul(numerator)
+                .unwrap()
+                .checked_div(denominator)
+                .unwrap();
+            if position.kind == PositionKind::Token {
+                self.token_weight += position.weight;
+            } else {
+                self.lp_share_weight += position.weight;
+            }
+            self.positions.insert(&position.id, &position);
+        }
+        previous
+    }
+}
+
+impl Contract {
+    /// Opens a position for the account and mints the lock token representing it.
+    /// Empty positions, e.g. a claim of zero rewards, are not recorded.
+    pub(crate) fn internal_add_position(&mut self, farm_id: u16, stake_info: &mut StakeInfo, position: NewPosition) {
+        if position.amount == 0 {
+            return;
+        }
+        let id = self.next_lock_id;
+        self.next_lock_id += 1;
+        stake_info.positions.insert(
+            &id,
+            &Position {
+                id,
+                kind: position.kind,
+                amount: position.amount,
+                weight: position.weight,
+                locked_at: position.created_at,
+                unlocked_at: position.created_at + position.lock_duration,
+                duration: position.lock_duration,
+                storage_payer: position.storage_payer,
+            },
+        );
+        self.internal_mint_lock(id, farm_id, &stake_info.owner_id);
+    }
+
+    /// Takes `amount` out of a position together with the matching part of its weight.
+    /// Emptied positions are removed and their lock token burnt, the freed storage goes back
+    /// to the position's storage payer. Returns the weight taken and the bytes refunded.
+    pub(crate) fn internal_reduce_position(
+        &mut self,
+        stake_info: &mut StakeInfo,
+        mut position: Position,
+        amount: u128,
+    ) -> (u128, u64) {
+        assert!(amount <= position.amount, "Amount must be smaller than position amount");
+        let weight = if amount == position.amount {
+            position.weight
+        } else {
+            mul_div(amount, position.weight, position.amount)
+        };
+        position.amount -= amount;
+        position.weight -= weight;
+        let mut refunded = 0;
+        if position.amount == 0 {
+            let initial_storage_usage = env::storage_usage();
+            stake_info.positions.remove(&position.id);
+            self.internal_burn_lock(position.id);
+            if let Some(payer_id) = &position.storage_payer {
+                refunded = self.internal_refund_storage(payer_id, initial_storage_usage);
+            }
+        } else {
+            stake_info.positions.insert(&position.id, &position);
+        }
+        (weight, refunded)
+    }
+
+    /// Lowers the account and farm totals by an amount leaving the farm.
+    pub(crate) fn internal_remove_stake(
+        &self,
+        farm_info: &mut FarmInfo,
+        stake_info: &mut StakeInfo,
+        kind: PositionKind,
+        amount: u128,
+        weight: u128,
+    ) {
+        match kind {
+            PositionKind::Token => {
+                stake_info.token_weight = stake_info.token_weight.checked_sub(weight).unwrap();
+                farm_info.total_token_weight = farm_info.total_token_weight.checked_sub(weight).unwrap();
+                stake_info.token_amount = stake_info.token_amount.checked_sub(amount).unwrap();
+                farm_info.total_token_amount = farm_info.total_token_amount.checked_sub(amount).unwrap();
+            }
+            PositionKind::LpShare => {
+                stake_info.lp_share_weight = stake_info.lp_share_weight.checked_sub(weight).unwrap();
+                farm_info.total_lp_share_weight = farm_info.total_lp_share_weight.checked_sub(weight).unwrap();
+                stake_info.lp_share_amount = stake_info.lp_share_amount.checked_sub(amount).unwrap();
+                farm_info.total_lp_share_amount = farm_info.total_lp_share_amount.checked_sub(amount).unwrap();
+            }
+            PositionKind::Reward => {
+                stake_info.reward_amount = stake_info.reward_amount.checked_sub(amount).unwrap();
+                farm_info.total_reward_amount = farm_info.total_reward_amount.checked_sub(amount).unwrap();
+            }
+        }
+    }
+
+    /// Lowers the account and farm totals by a withdrawn amount and sends it to the account,
+    /// or queues it when the farm has an unbonding period.
+    pub(crate) fn internal_release(
+        &mut self,
+        farm_info: &mut FarmInfo,
+        stake_info: &mut StakeInfo,
+        kind: PositionKind,
+        amount: u128,
+        weight: u128,
+    ) {
+        self.internal_remove_stake(farm_info, stake_info, kind, amount, weight);
+        // rewards already paid their fee when claimed
+        let fee = match kind {
+            PositionKind::Token => self.internal_charge_fee(
+                farm_info.farm_id,
+                &stake_info.owner_id,
+                farm_info.token_id.as_str(),
+                "withdraw",
+                amount,
+                self.fees_of(farm_info).stake_fee_bps,
+            ),
+            PositionKind::LpShare => self.internal_charge_fee(
+                farm_info.farm_id,
+                &stake_info.owner_id,
+                &lp_share_token_id(farm_info.pool_id),
+                "withdraw",
+                amount,
+                self.fees_of(farm_info).stake_fee_bps,
+            ),
+            PositionKind::Reward => 0,
+        };
+        let amount = amount - fee;
+        if amount == 0 {
+            return;
+        }
+        if farm_info.unbonding_period > 0 {
+            self.internal_queue_unbonding(farm_info, stake_info, kind, amount);
+        } else {
+            self.internal_send(farm_info, stake_info.owner_id.clone(), kind, amount);
+        }
+    }
+
+    /// Converts a stake info written with the parallel lock vectors. Every still locked entry
+    /// becomes a position with a new lock token, the matured remainder of each kind becomes one
+    /// position and the account weights are split over the positions in proportion to their amounts.
+    pub(crate) fn internal_migrate_stake_info(
+        &mut self,
+        farm_id: u16,
+        mut legacy: LegacyStakeInfo,
+        now: u64,
+    ) -> StakeInfo {
+        let mut stake_info = StakeInfo::new(farm_id, legacy.owner_id.clone(), legacy.created_at);
+        stake_info.token_amount = legacy.token_amount;
+        stake_info.lp_share_amount = legacy.lp_share_amount;
+        stake_info.reward_amount = legacy.reward_amount;
+        stake_info.token_weight = legacy.token_weight;
+        stake_info.lp_share_weight = legacy.lp_share_weight;
+        stake_info.reward_token_to_claim = legacy.reward_token_to_claim;
+        stake_info.reward_lp_to_claim = legacy.reward_lp_to_claim;
+        stake_info.claimed_token_at = legacy.claimed_token_at;
+        stake_info.claimed_lp_at = legacy.claimed_lp_at;
+        stake_info.claimed_reward_at = legacy.claimed_reward_at;
+
+        for kind in [PositionKind::Token, PositionKind::LpShare, PositionKind::Reward] {
+            let (amounts, total_amount, total_weight) = match kind {
+                PositionKind::Token => (&legacy.token_locked, legacy.token_amount, legacy.token_weight),
+                PositionKind::LpShare => (&legacy.lp_share_locked, legacy.lp_share_amount, legacy.lp_share_weight),
+                PositionKind::Reward => (&legacy.reward_locked, legacy.reward_amount, legacy.reward_amount),
+            };
+            let mut positions = vec![];
+            let mut matured: Option<Position> = None;
+            for index in 0..amounts.len() {
+                let amount = amounts.get(index).unwrap().0;
+                if amount == 0 {
+                    continue;
+                }
+                let unlocked_at = legacy.unlocked_at.get(index).unwrap();
+                let duration = legacy.staking_duration.get(index).unwrap();
+                if unlocked_at > now {
+                    positions.push(Position {
+                        id: 0,
+                        kind,
+                        amount,
+                        weight: 0,
+                        locked_at: unlocked_at.saturating_sub(duration),
+                        unlocked_at,
+                        duration,
+                        storage_payer: None,
+                    });
+                } else if matured.is_none() {
+                    matured = Some(Position {
+                        id: 0,
+                        kind,
+                        amount: 0,
+                        weight: 0,
+                        locked_at: unlocked_at.saturating_sub(duration),
+                        unlocked_at,
+                        duration,
+                        storage_payer: None,
+                    });
+                }
+            }
+            // unstaking used to leave the vectors untouched, only the aggregate knows what is left
+            let locked_amount: u128 = positions.iter().map(|position| position.amount).sum();
+            let matured_amount = total_amount.saturating_sub(locked_amount);
+            match matured {
+                Some(mut position) if matured_amount > 0 => {
+                    position.amount = matured_amount;
+                    positions.push(position);
+                }
+                Some(_) => {}
+                None if matured_amount > 0 => positions.push(Position {
+                    id: 0,
+                    kind,
+                    amount: matured_amount,
+                    weight: 0,
+                    locked_at: legacy.created_at,
+                    unlocked_at: legacy.created_at,
+                    duration: 0,
+                    storage_payer: None,
+                }),
+                None => {}
+            }
+
+            let mut weight_left = total_weight;
+            let count = positions.len();
+            for (index, mut position) in positions.into_iter().enumerate() {
+                position.weight = if index + 1 == count {
+                    weight_left
+                } else {
+                    mul_div(position.amount, total_weight, total_amount)
+                };
+                weight_left -= position.weight;
+                position.id = self.next_lock_id;
+                self.next_lock_id += 1;
+                self.internal_mint_lock(position.id, farm_id, &stake_info.owner_id);
+                stake_info.positions.insert(&position.id, &position);
+            }
+        }
+
+        legacy.token_locked.clear();
+        legacy.lp_share_locked.clear();
+        legacy.reward_locked.clear();
+        legacy.unlocked_at.clear();
+        legacy.staking_duration.clear();
+        stake_info
+    }
+
+    /// Returns the bytes refunded to the storage payer of an emptied position.
+    pub(crate) fn internal_unstake_position(
+        &mut self,
+        farm_id: u16,
+        account_id: &AccountId,
+        position_id: u64,
+        amount: U128,
+    ) -> u64 {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let now = env::block_timestamp() / 1000000;
+        let stake_info = self.staking_informations_by_owner_id(farm_id, account_id.clone());
+        let position = stake_info.positions.get(&position_id).expect("Position not found");
+        assert!(!position.is_locked(now), "Position is still locked");
+
+        self.update_claim_amounts(farm_id, position.kind.token_type(), now);
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let mut stake_info = farm_info.stake_infos.get(account_id).unwrap();
+        let position = stake_info.positions.get(&position_id).unwrap();
+        let kind = position.kind;
+        let (weight, refunded) = self.internal_reduce_position(&mut stake_info, position, amount.0);
+        self.internal_release(&mut farm_info, &mut stake_info, kind, amount.0, weight);
+        farm_info.stake_infos.insert(account_id, &stake_info);
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+        refunded
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Withdraws `amount` from a single unlocked position, queued on farms with an unbonding
+    /// period with the entry's storage charged like in `request_unstake`.
+    #[payable]
+    pub fn unstake_position(&mut self, farm_id: u16, position_id: u64, amount: U128) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        let refunded = self.internal_unstake_position(farm_id, &account_id, position_id, amount);
+        self.internal_settle_storage(&account_id, initial_storage_usage - refunded);
+    }
+
+    pub fn get_positions(&self, farm_id: u16, account_id: AccountId) -> Vec<PositionView> {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        if let Some(stake_info) = farm_info.stake_infos.get(&account_id) {
+            stake_info
+                .sorted_positions()
+                .iter()
+                .map(|position| PositionView::new(farm_id, account_id.clone(), position))
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    pub fn get_position(&self, position_id: u64) -> Option<PositionView> {
+        self.internal_position(position_id)
+            .map(|(farm_id, owner_id, position)| PositionView::new(farm_id, owner_id, &position))
+    }
+}
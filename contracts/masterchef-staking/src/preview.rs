@@ -0,0 +1,74 @@
+use crate::*;
+use std::cmp::max;
+
+const DAY: u64 = 24 * 3600 * 1000;
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StakePreview {
+    /// part of the amount the pool's limits accept, the rest is refunded
+    pub accepted_amount: U128,
+    pub fee: U128,
+    pub staked_amount: U128,
+    pub weight: U128,
+    /// share of the pool weight once staked, scaled by 1e24
+    pub share: U128,
+    /// rewards the stake earns over the next day at the current weights and emission
+    pub estimated_reward_per_day: U128,
+    pub unlock_at: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// What staking `amount` tokens or LP shares with `lock_duration` would give `account_id`
+    /// (a new staker if omitted) at the current block, computed by the same `apply_stake` as
+    /// the transfer callbacks. Panics where the stake would.
+    pub fn preview_stake(
+        &self,
+        farm_id: u16,
+        amount: U128,
+        lock_duration: u64,
+        kind: PositionKind,
+        account_id: Option<AccountId>,
+    ) -> StakePreview {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let now = env::block_timestamp() / 1000000;
+        match kind {
+            PositionKind::Token => {
+                assert!(farm_info.farm_type == 0 || farm_info.farm_type == 2, "Token Pool does not exist in this Farm")
+            }
+            PositionKind::LpShare => {
+                assert!(farm_info.farm_type == 1 || farm_info.farm_type == 2, "LP Pool does not exist in this Farm")
+            }
+            PositionKind::Reward => {}
+        }
+
+        let mut stake_info = account_id
+            .and_then(|account_id| farm_info.stake_infos.get(&account_id))
+            .unwrap_or_else(|| StakeInfo::new(farm_id, env::current_account_id(), now));
+        let stake_fee_bps = self.fees_of(&farm_info).stake_fee_bps;
+        let quote = farm_info.apply_stake(&mut stake_info, kind, amount.0, lock_duration, stake_fee_bps);
+
+        let total_weight = if kind == PositionKind::Token {
+            farm_info.total_token_weight
+        } else {
+            farm_info.total_lp_share_weight
+        };
+        let emission = farm_info.pool_emission(kind.token_type());
+        let estimated_reward_per_day = match emission.settle_time(now + DAY) {
+            Some(until) => accrued_reward(&emission, quote.weight, total_weight, max(now, farm_info.starting_at), until),
+            None => 0,
+        };
+
+        StakePreview {
+            accepted_amount: U128(quote.accepted),
+            fee: U128(quote.fee),
+            staked_amount: U128(quote.amount),
+            weight: U128(quote.weight),
+            share: U128(share_of(quote.weight, total_weight)),
+            estimated_reward_per_day: U128(estimated_reward_per_day),
+            unlock_at: now + lock_duration,
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use crate::*;
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RescueData<'a> {
+    pub token_id: &'a str,
+    pub receiver_id: &'a AccountId,
+    pub amount: U128,
+}
+
+impl FarmInfo {
+    /// Rewards the farm may still pay: everything its pools emit from `starting_at` to
+    /// `ending_at`, less what was already credited. Open-ended farms owe without bound.
+    pub fn remaining_reward_budget(&self) -> u128 {
+        if self.ending_at == u64::MAX {
+            return u128::MAX;
+        }
+        let mut budget = self.emitted(self.reward_reward_rate, self.starting_at, self.ending_at);
+        if self.farm_type == 0 || self.farm_type == 2 {
+            budget = budget.saturating_add(self.emitted(self.token_reward_rate, self.starting_at, self.ending_at));
+        }
+        if self.farm_type == 1 || self.farm_type == 2 {
+            budget = budget.saturating_add(self.emitted(self.pool_reward_rate, self.starting_at, self.ending_at));
+        }
+        budget.saturating_sub(self.distributed_reward_amount)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires the owner. Reads the contract's balance of the token (or of Ref LP shares for
+    /// `:<pool_id>`) and sends `amount` to `receiver_id` if it is covered by the balance above
+    /// the tracked liabilities and reward budgets, see `get_token_liabilities`.
+    #[payable]
+    pub fn rescue_tokens(&mut self, token_id: String, receiver_id: AccountId, amount: U128) -> Promise {
+        self.assert_owner();
+        assert_one_yocto();
+        assert!(amount.0 > 0, "Amount must be greater than 0");
+        self.internal_balance_of(&token_id).then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_RESCUE_CALLBACK)
+                .on_rescue_balance(token_id, receiver_id, amount),
+        )
+    }
+
+    #[private]
+    pub fn on_rescue_balance(
+        &mut self,
+        token_id: String,
+        receiver_id: AccountId,
+        amount: U128,
+        #[callback_unwrap] balance: U128,
+    ) -> U128 {
+        let excess = balance.0.saturating_sub(self.internal_liabilities(&token_id).owed_with_budgets());
+        assert!(amount.0 <= excess, "Only {} is above the tracked liabilities", excess);
+        if token_id.starts_with(':') {
+            ext_transfer::ext(self.ref_exchange_id.clone())
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .mft_transfer(token_id.clone(), receiver_id.clone(), amount, None);
+        } else {
+            ext_transfer::ext(AccountId::new_unchecked(token_id.clone()))
+                .with_attached_deposit(1)
+                .with_static_gas(GAS_FOR_FT_TRANSFER)
+                .ft_transfer(receiver_id.clone(), amount, None);
+        }
+        emit_event(
+            "tokens_rescued",
+            RescueData {
+                token_id: &token_id,
+                receiver_id: &receiver_id,
+                amount,
+            },
+        );
+        amount
+    }
+
+    /// everything owed in the token including the remaining reward budgets, what `rescue_tokens` keeps
+    pub fn get_token_liabilities(&self, token_id: String) -> U128 {
+        U128(self.internal_liabilities(&token_id).owed_with_budgets())
+    }
+}
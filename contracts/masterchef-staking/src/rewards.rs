@@ -0,0 +1,166 @@
+//! Reward accrual and swap vesting as pure functions of explicit parameters and timestamps
+//! (ms). The views and the mutations both go through them, nothing here reads `env`.
+
+use crate::*;
+
+/// What one pool of a farm emits: its rate shaped by the farm's schedule, between the farm's
+/// start and end.
+pub struct PoolEmission {
+    pub schedule: EmissionSchedule,
+    pub rate: u128,
+    pub starting_at: u64,
+    pub ending_at: u64,
+}
+
+impl PoolEmission {
+    /// time rewards are settled up to at `now`, `None` before the farm starts
+    pub fn settle_time(&self, now: u64) -> Option<u64> {
+        if now < self.starting_at {
+            None
+        } else {
+            Some(min(now, self.ending_at))
+        }
+    }
+
+    /// time the pool of `token_type` is settled up to at `now`. Reward-on-reward is not clamped,
+    /// it keeps accruing on the rewards that still vest after the farm ends.
+    pub fn pool_settle_time(&self, token_type: u8, now: u64) -> Option<u64> {
+        if token_type == 2 {
+            Some(now)
+        } else {
+            self.settle_time(now)
+        }
+    }
+
+    pub fn emitted(&self, from: u64, to: u64) -> u128 {
+        self.schedule.emitted(self.rate, self.starting_at, from, to)
+    }
+}
+
+/// Reward of `weight` out of `total_weight` from `claimed_at` to `now`, counted in whole seconds.
+pub fn accrued_reward(emission: &PoolEmission, weight: u128, total_weight: u128, claimed_at: u64, now: u64) -> u128 {
+    if now / 1000 <= claimed_at / 1000 {
+        return 0;
+    }
+    reward_for(share_of(weight, total_weight), emission.emitted(claimed_at, now))
+}
+
+/// Reward-on-reward of locked reward positions, given as `(amount, unlocked_at)`, out of
+/// `total_reward_amount` from `claimed_at` to `now`. A position stops accruing once unlocked.
+pub fn reward_on_reward(
+    emission: &PoolEmission,
+    positions: impl IntoIterator<Item = (u128, u64)>,
+    total_reward_amount: u128,
+    claimed_at: u64,
+    now: u64,
+) -> u128 {
+    positions
+        .into_iter()
+        .map(|(amount, unlocked_at)| {
+            let last_time = if unlocked_at / 1000 < now / 1000 { unlocked_at } else { now };
+            accrued_reward(emission, amount, total_reward_amount, claimed_at, last_time)
+        })
+        .sum()
+}
+
+/// Part of a swap stake of `locked` UNET that can be unstaked at `now`. Nothing before
+/// `min_lock_time` has passed since the swap, everything once `unlocked_at` has passed,
+/// linearly from the last unstake in between.
+pub fn swap_vested_amount(
+    locked: u128,
+    claimed_at: u64,
+    unlocked_at: u64,
+    min_lock_time: u64,
+    max_lock_time: u64,
+    now: u64,
+) -> u128 {
+    if unlocked_at < now {
+        locked
+    } else if unlocked_at - max_lock_time + min_lock_time > now {
+        0
+    } else {
+        mul_div(locked, u128::from(now - claimed_at), u128::from(unlocked_at - claimed_at))
+    }
+}
+
+impl FarmInfo {
+    /// emission of the pool of `token_type`, 0 for tokens, 1 for LP shares and 2 for reward-on-reward
+    pub fn pool_emission(&self, token_type: u8) -> PoolEmission {
+        let rate = match token_type {
+            0 => self.token_reward_rate,
+            1 => self.pool_reward_rate,
+            _ => self.reward_reward_rate,
+        };
+        PoolEmission {
+            schedule: self.emission_schedule.clone(),
+            rate,
+            starting_at: self.starting_at,
+            ending_at: self.ending_at,
+        }
+    }
+}
+
+impl FarmInfo {
+    /// Accrues the account's rewards of the pool of `token_type` up to `now`, already clamped
+    /// by the pool's `pool_settle_time`.
+    pub fn accrue(&self, emission: &PoolEmission, stake_info: &mut StakeInfo, token_type: u8, now: u64) {
+        if token_type == 0 {
+            if stake_info.token_amount != 0 && (now / 1000) > (stake_info.claimed_token_at / 1000) {
+                stake_info.reward_token_to_claim += accrued_reward(
+                    emission,
+                    stake_info.token_weight,
+                    self.total_token_weight,
+                    stake_info.claimed_token_at,
+                    now,
+                );
+                stake_info.claimed_token_at = now;
+            }
+        } else if token_type == 1 {
+            if stake_info.lp_share_amount != 0 && (now / 1000) > (stake_info.claimed_lp_at / 1000) {
+                stake_info.reward_lp_to_claim += accrued_reward(
+                    emission,
+                    stake_info.lp_share_weight,
+                    self.total_lp_share_weight,
+                    stake_info.claimed_lp_at,
+                    now,
+                );
+                stake_info.claimed_lp_at = now;
+            }
+        } else {
+            // reward-on-reward is kept apart so the reward positions keep their vesting amounts,
+            // and rewards locked later must not accrue for the time before they existed
+            stake_info.reward_reward_to_claim += reward_on_reward(
+                emission,
+                stake_info.reward_positions(),
+                self.total_reward_amount,
+                stake_info.claimed_reward_at,
+                now,
+            );
+            stake_info.claimed_reward_at = now;
+        }
+    }
+
+    /// Accrues one account's rewards of the pool of `token_type` up to `now`. Exact without
+    /// settling the other stakers as long as the pool totals are unchanged since the account
+    /// was last settled, which holds as every change of the totals settles the whole pool first.
+    pub fn settle_stake_info(&self, stake_info: &mut StakeInfo, token_type: u8, now: u64) {
+        if token_type == 0 && self.farm_type == 1 || token_type == 1 && self.farm_type == 0 {
+            return;
+        }
+        let emission = self.pool_emission(token_type);
+        if let Some(now) = emission.pool_settle_time(token_type, now) {
+            self.accrue(&emission, stake_info, token_type, now);
+        }
+    }
+}
+
+impl StakeInfo {
+    /// `(amount, unlocked_at)` of every reward position
+    pub fn reward_positions(&self) -> Vec<(u128, u64)> {
+        self.positions
+            .values()
+            .filter(|position| position.kind == PositionKind::Reward)
+            .map(|position| (position.amount, position.unlocked_at))
+            .collect()
+    }
+}
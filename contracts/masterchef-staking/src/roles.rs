@@ -0,0 +1,175 @@
+use crate::*;
+
+/// Roles granted by the owner. The owner passes every role check itself until ownership is renounced.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Role {
+    /// creates farms, every holder confirms a new farm, and configures farms
+    FarmManager,
+    /// adds, edits and removes swap farms, every holder confirms an edit
+    SwapManager,
+    Pauser,
+    /// sets protocol fees and withdraws the treasury
+    TreasuryManager,
+    /// runs state migrations after a code upgrade
+    Upgrader,
+}
+
+impl Contract {
+    pub(crate) fn has_role(&self, role: &Role, account_id: &AccountId) -> bool {
+        (!self.owner_renounced && account_id == &self.owner_id)
+            || self
+                .role_holders
+                .get(role)
+                .is_some_and(|holders| holders.contains(account_id))
+    }
+
+    pub(crate) fn assert_role(&self, role: Role) {
+        assert!(
+            self.has_role(&role, &env::predecessor_account_id()),
+            "Requires the {:?} role",
+            role
+        );
+    }
+
+    pub(crate) fn assert_not_paused(&self) {
+        assert!(!self.paused, "Contract is paused");
+    }
+
+    /// number of accounts explicitly granted the role, the owner is not counted
+    pub(crate) fn role_holder_count(&self, role: &Role) -> u64 {
+        self.role_holders.get(role).map_or(0, |holders| holders.len())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires the owner.
+    #[payable]
+    pub fn grant_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_owner();
+        assert_one_yocto();
+        let mut holders = self
+            .role_holders
+            .get(&role)
+            .unwrap_or_else(|| UnorderedSet::new(StorageKey::RoleHoldersInner { role }));
+        holders.insert(&account_id);
+        self.role_holders.insert(&role, &holders);
+        emit_event("role_granted", RoleData { role, account_id: &account_id });
+    }
+
+    /// Requires the owner. Pending farm and swap farm confirmations are re-checked without
+    /// the revoked account, so they go through if it was the last one missing.
+    #[payable]
+    pub fn revoke_role(&mut self, role: Role, account_id: AccountId) {
+        self.assert_owner();
+        assert_one_yocto();
+        if let Some(mut holders) = self.role_holders.get(&role) {
+            holders.remove(&account_id);
+            self.role_holders.insert(&role, &holders);
+        }
+        emit_event("role_revoked", RoleData { role, account_id: &account_id });
+
+        if role == Role::FarmManager {
+            let confirmed_admins_for_new_farm = self.confirmed_admins_for_new_farm.as_vector().to_vec();
+            if confirmed_admins_for_new_farm.contains(&account_id) {
+                self.confirmed_admins_for_new_farm.remove(&account_id);
+                if self.confirmed_admins_for_new_farm.len() == self.role_holder_count(&Role::FarmManager) {
+                    self.farm_infos.push(&FarmInfo::new(
+                        self.farm_infos.len() as u16,
+                        self.editing_new_farm.farm_type,
+                        self.editing_new_farm.token_id.clone(),
+                        self.editing_new_farm.token_decimal,
+                        self.editing_new_farm.pool_id,
+                        self.editing_new_farm.token_reward_rate,
+                        self.editing_new_farm.pool_reward_rate,
+                        self.editing_new_farm.reward_reward_rate,
+                        self.editing_new_farm.token_weight_rate,
+                        self.editing_new_farm.pool_weight_rate,
+                        self.editing_new_farm.max_token_vesting_duration,
+                        self.editing_new_farm.max_reward_vesting_duration,
+                        self.editing_new_farm.starting_at,
+                        self.editing_new_farm.ending_at,
+                    ));
+                    self.confirmed_admins_for_new_farm.clear();
+                    self.editing_new_farm = EditingFarmInfo {
+                        farm_id: 0,
+                        farm_type: 0,
+                        token_id: AccountId::new_unchecked(TOKEN_CONTRACT_ID.to_string()),
+                        token_decimal: 0,
+                        pool_id: 0,
+                        token_reward_rate: U128(0),
+                        pool_reward_rate: U128(0),
+                        reward_reward_rate: U128(0),
+                        token_weight_rate: 0,
+                        pool_weight_rate: 0,
+                        max_token_vesting_duration: 0,
+                        max_reward_vesting_duration: 0,
+                        starting_at: 0,
+                        ending_at: 0,
+                    };
+                }
+            }
+        } else if role == Role::SwapManager {
+            let swap_token_len = self.editing_swap_farm_infos.keys_as_vector().to_vec().len();
+            if swap_token_len > 0 {
+                let swap_tokens = self.editing_swap_farm_infos.keys_as_vector().to_vec();
+                for idx in 0..swap_token_len {
+                    let swap_token = swap_tokens.get(idx).unwrap();
+                    let mut editing_swap_farm_info =
+                        self.editing_swap_farm_infos.get(&swap_token).unwrap();
+                    if editing_swap_farm_info
+                        .confirmed_admins
+                        .contains(&account_id)
+                    {
+                        editing_swap_farm_info.confirmed_admins.remove(&account_id);
+                        if self.role_holder_count(&Role::SwapManager) <= editing_swap_farm_info.confirmed_admins.len() {
+                            let mut swap_farm_data = self.swap_farms.get(&swap_token).unwrap();
+                            swap_farm_data.min_lock_time = editing_swap_farm_info.min_lock_time;
+                            swap_farm_data.max_lock_time = editing_swap_farm_info.max_lock_time;
+                            swap_farm_data.swap_rate = editing_swap_farm_info.swap_rate;
+                            self.swap_farms.insert(&swap_token, &swap_farm_data);
+
+                            editing_swap_farm_info.confirmed_admins.clear();
+                            self.editing_swap_farm_infos
+                                .insert(&swap_token, &editing_swap_farm_info);
+                        } else {
+                            self.editing_swap_farm_infos
+                                .insert(&swap_token, &editing_swap_farm_info);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn get_role_holders(&self, role: Role) -> Vec<AccountId> {
+        self.role_holders.get(&role).map_or(vec![], |holders| holders.to_vec())
+    }
+
+    pub fn account_has_role(&self, role: Role, account_id: AccountId) -> bool {
+        self.has_role(&role, &account_id)
+    }
+
+    /// Requires the `Pauser` role. Stops deposits, claims and position moves, withdrawals stay open.
+    /// Never timelocked, pausing takes effect in the same block.
+    #[payable]
+    pub fn pause(&mut self) {
+        self.assert_role(Role::Pauser);
+        assert_one_yocto();
+        self.paused = true;
+    }
+
+    /// Requires the `Pauser` role, and every stake info to be migrated.
+    #[payable]
+    pub fn unpause(&mut self) {
+        self.assert_role(Role::Pauser);
+        assert_one_yocto();
+        assert!(self.is_migrated(), "Stake infos are still being migrated");
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+}
@@ -0,0 +1,258 @@
+use crate::*;
+
+impl Contract {
+    /// Why the position of `account_id` cannot move from `from_farm` into `to_farm`, if it cannot.
+    fn position_move_error(
+        &self,
+        from_farm: &FarmInfo,
+        to_farm: &FarmInfo,
+        account_id: &AccountId,
+        position: &Position,
+        now: u64,
+    ) -> Option<&'static str> {
+        let accepted = match position.kind {
+            PositionKind::Token => {
+                (to_farm.farm_type == 0 || to_farm.farm_type == 2) && to_farm.token_id == from_farm.token_id
+            }
+            PositionKind::LpShare => {
+                (to_farm.farm_type == 1 || to_farm.farm_type == 2) && to_farm.pool_id == from_farm.pool_id
+            }
+            PositionKind::Reward => to_farm.token_id == from_farm.token_id,
+        };
+        if !accepted {
+            return Some("Farm does not accept this position");
+        }
+        if now >= to_farm.ending_at {
+            return Some("Farm has ended");
+        }
+        if !self.internal_is_eligible(to_farm, account_id, None) {
+            return Some("Account is not eligible for this Farm");
+        }
+        let to_stake = to_farm.stake_infos.get(account_id);
+        let fits = match position.kind {
+            PositionKind::Token => {
+                let staked = to_stake.as_ref().map_or(0, |to_stake| to_stake.token_amount);
+                to_farm.token_limits.accepted_amount(position.amount, to_farm.total_token_amount, staked) == position.amount
+            }
+            PositionKind::LpShare => {
+                let staked = to_stake.as_ref().map_or(0, |to_stake| to_stake.lp_share_amount);
+                to_farm.lp_share_limits.accepted_amount(position.amount, to_farm.total_lp_share_amount, staked) == position.amount
+            }
+            PositionKind::Reward => true,
+        };
+        if !fits {
+            return Some("Position does not fit under the Farm caps");
+        }
+        None
+    }
+
+    /// Moves a position of `account_id` into another farm staking the same token or LP pool.
+    /// The lock end is kept and the weight is recomputed with the target farm's rates for the
+    /// remaining lock. Rewards earned so far stay claimable in the source farm. No tokens move.
+    pub(crate) fn internal_migrate_position(
+        &mut self,
+        from_farm_id: u16,
+        to_farm_id: u16,
+        account_id: &AccountId,
+        position_id: u64,
+        now: u64,
+    ) {
+        assert!(self.farm_infos.len() > from_farm_id.into(), "Invalid Farm ID");
+        assert!(self.farm_infos.len() > to_farm_id.into(), "Invalid Farm ID");
+        assert_ne!(from_farm_id, to_farm_id, "Position is already in this Farm");
+        let from_farm = self.farm_infos.get(from_farm_id.into()).unwrap();
+        let to_farm = self.farm_infos.get(to_farm_id.into()).unwrap();
+        let position = from_farm
+            .stake_infos
+            .get(account_id)
+            .and_then(|stake_info| stake_info.positions.get(&position_id))
+            .expect("Position not found");
+        if let Some(error) = self.position_move_error(&from_farm, &to_farm, account_id, &position, now) {
+            env::panic_str(error);
+        }
+
+        let token_type = position.kind.token_type();
+        self.update_claim_amounts(from_farm_id, token_type, now);
+        self.update_claim_amounts(to_farm_id, token_type, now);
+        self.internal_move_position(from_farm_id, to_farm_id, account_id, position_id, now);
+    }
+
+    /// Moves the position once both farms are settled up to `now` and the move is checked.
+    fn internal_move_position(
+        &mut self,
+        from_farm_id: u16,
+        to_farm_id: u16,
+        account_id: &AccountId,
+        position_id: u64,
+        now: u64,
+    ) {
+        let mut from_farm = self.farm_infos.get(from_farm_id.into()).unwrap();
+        let mut from_stake = from_farm.stake_infos.get(account_id).unwrap();
+        let mut position = from_stake.positions.remove(&position_id).unwrap();
+        self.internal_remove_stake(&mut from_farm, &mut from_stake, position.kind, position.amount, position.weight);
+        from_farm.stake_infos.insert(account_id, &from_stake);
+        self.farm_infos.replace(from_farm_id.into(), &from_farm);
+
+        let mut to_farm = self.farm_infos.get(to_farm_id.into()).unwrap();
+        let mut to_stake = to_farm
+            .stake_infos
+            .get(account_id)
+            .unwrap_or_else(|| StakeInfo::new(to_farm_id, account_id.clone(), now));
+        let remaining_lock = position.unlocked_at.saturating_sub(now);
+        match position.kind {
+            PositionKind::Token => {
+                to_farm.total_token_amount += position.amount;
+                position.weight = to_stake.boosted_weight(to_farm.token_weight_for(position.amount, remaining_lock));
+                to_stake.token_amount += position.amount;
+                to_stake.token_weight += position.weight;
+                to_farm.total_token_weight += position.weight;
+                to_stake.claimed_token_at = now;
+            }
+            PositionKind::LpShare => {
+                to_farm.total_lp_share_amount += position.amount;
+                position.weight = to_stake.boosted_weight(to_farm.lp_share_weight_for(position.amount, remaining_lock));
+                to_stake.lp_share_amount += position.amount;
+                to_stake.lp_share_weight += position.weight;
+                to_farm.total_lp_share_weight += position.weight;
+                to_stake.claimed_lp_at = now;
+            }
+            PositionKind::Reward => {
+                to_stake.reward_amount += position.amount;
+                to_farm.total_reward_amount += position.amount;
+                to_stake.claimed_reward_at = now;
+            }
+        }
+        to_stake.positions.insert(&position_id, &position);
+        to_farm.stake_infos.insert(account_id, &to_stake);
+        self.farm_infos.replace(to_farm_id.into(), &to_farm);
+
+        let mut lock_token = self.lock_tokens.get(&position_id).unwrap();
+        lock_token.farm_id = to_farm_id;
+        self.lock_tokens.insert(&position_id, &lock_token);
+        emit_position_migrated(account_id, position_id, from_farm_id, to_farm_id, position.weight);
+    }
+
+    /// Moves every position of an opted-in staker, or opts the staker out at the first position
+    /// that cannot move. Positions moved before it stay in the successor.
+    fn internal_rollover_account(&mut self, farm_id: u16, successor_farm_id: u16, account_id: &AccountId, now: u64) -> u64 {
+        let initial_storage_usage = env::storage_usage();
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let has_successor_stake = self
+            .farm_infos
+            .get(successor_farm_id.into())
+            .unwrap()
+            .stake_infos
+            .get(account_id)
+            .is_some();
+        // a new stake info in the successor is the only storage a rollover adds
+        if !has_successor_stake && self.storage_deposits.get(account_id).unwrap_or(0) < STORAGE_PER_SALE {
+            self.internal_opt_out_of_rollover(account_id, farm_id, "Insufficient storage deposit");
+            return 0;
+        }
+
+        let mut moved = 0;
+        for position in farm_info.stake_infos.get(account_id).unwrap().sorted_positions() {
+            let from_farm = self.farm_infos.get(farm_id.into()).unwrap();
+            let to_farm = self.farm_infos.get(successor_farm_id.into()).unwrap();
+            if let Some(error) = self.position_move_error(&from_farm, &to_farm, account_id, &position, now) {
+                self.internal_opt_out_of_rollover(account_id, farm_id, error);
+                break;
+            }
+            self.internal_move_position(farm_id, successor_farm_id, account_id, position.id, now);
+            moved += 1;
+        }
+        self.internal_charge_storage(account_id, initial_storage_usage);
+        moved
+    }
+
+    fn internal_opt_out_of_rollover(&mut self, account_id: &AccountId, farm_id: u16, reason: &str) {
+        self.rollover_accounts.remove(account_id);
+        env::log_str(&format!(
+            "{} is opted out of rollovers, a position of farm {} cannot roll over: {}",
+            account_id, farm_id, reason
+        ));
+    }
+
+    pub(crate) fn internal_set_successor_farm(&mut self, farm_id: u16, successor_farm_id: Option<u16>) {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        if let Some(successor_farm_id) = successor_farm_id {
+            assert!(self.farm_infos.len() > successor_farm_id.into(), "Invalid Farm ID");
+            assert_ne!(farm_id, successor_farm_id, "Farm cannot succeed itself");
+        }
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        farm_info.successor_farm_id = successor_farm_id;
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Links the farm that positions of an ended farm roll over into. `None` removes the link.
+    /// Requires the `FarmManager` role. Goes through `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_successor_farm(&mut self, farm_id: u16, successor_farm_id: Option<u16>) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.internal_set_successor_farm(farm_id, successor_farm_id);
+    }
+
+    #[payable]
+    pub fn migrate_position(&mut self, from_farm_id: u16, to_farm_id: u16, position_id: u64) {
+        self.assert_not_paused();
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let now = env::block_timestamp() / 1000000;
+        let initial_storage_usage = env::storage_usage();
+        self.internal_migrate_position(from_farm_id, to_farm_id, &account_id, position_id, now);
+        self.internal_charge_storage(&account_id, initial_storage_usage);
+    }
+
+    /// Opts the caller in or out of having positions moved to the successor of an ended farm.
+    #[payable]
+    pub fn set_rollover(&mut self, enabled: bool) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let initial_storage_usage = env::storage_usage();
+        if enabled {
+            self.rollover_accounts.insert(&account_id);
+            self.internal_charge_storage(&account_id, initial_storage_usage);
+        } else {
+            self.rollover_accounts.remove(&account_id);
+            self.internal_refund_storage(&account_id, initial_storage_usage);
+        }
+    }
+
+    pub fn get_rollover(&self, account_id: AccountId) -> bool {
+        self.rollover_accounts.contains(&account_id)
+    }
+
+    /// Moves every position of the opted-in stakers in `[from_index, from_index + limit)` of an
+    /// ended farm into its successor. Anyone can call it, storage is charged to the stakers. Both
+    /// farms are settled once for the page, stakers with a position the successor does not accept
+    /// are opted out instead of failing the page. Returns the number of positions moved.
+    pub fn rollover_positions(&mut self, farm_id: u16, from_index: u64, limit: u64) -> u64 {
+        self.assert_not_paused();
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let now = env::block_timestamp() / 1000000;
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        assert!(now > farm_info.ending_at, "Farm has not ended yet");
+        let successor_farm_id = farm_info.successor_farm_id.expect("Farm has no successor");
+
+        // every staker of both farms is settled up to `now`, moves later in this block accrue nothing
+        for token_type in 0..3 {
+            self.update_claim_amounts(farm_id, token_type, now);
+            self.update_claim_amounts(successor_farm_id, token_type, now);
+        }
+
+        let account_ids = farm_info.stake_infos.keys_as_vector();
+        let mut moved = 0;
+        for index in from_index..min(from_index.saturating_add(limit), account_ids.len()) {
+            let account_id = account_ids.get(index).unwrap();
+            if self.rollover_accounts.contains(&account_id) {
+                moved += self.internal_rollover_account(farm_id, successor_farm_id, &account_id, now);
+            }
+        }
+        moved
+    }
+}
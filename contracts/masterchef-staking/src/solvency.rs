@@ -0,0 +1,180 @@
+use crate::*;
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmLiabilityView {
+    pub farm_id: u16,
+    /// staked tokens, or LP shares when the report is for `:<pool_id>`
+    pub staked: U128,
+    pub unbonding: U128,
+    pub locked_rewards: U128,
+    /// rewards the farm may still pay, not counted as owed, see `FarmInfo::remaining_reward_budget`
+    pub reward_budget: U128,
+}
+
+impl FarmLiabilityView {
+    fn owed(&self) -> u128 {
+        self.staked
+            .0
+            .saturating_add(self.unbonding.0)
+            .saturating_add(self.locked_rewards.0)
+    }
+}
+
+/// What the contract owes in one token, by farm.
+pub struct Liabilities {
+    pub farms: Vec<FarmLiabilityView>,
+    /// UNET owed to swap stakers
+    pub swap_owed: u128,
+    pub treasury: u128,
+}
+
+impl Liabilities {
+    /// principal, locked rewards, swap stakes and fees, without future rewards
+    pub fn owed(&self) -> u128 {
+        self.farms
+            .iter()
+            .fold(self.swap_owed.saturating_add(self.treasury), |owed, farm| owed.saturating_add(farm.owed()))
+    }
+
+    pub fn owed_with_budgets(&self) -> u128 {
+        self.farms
+            .iter()
+            .fold(self.owed(), |owed, farm| owed.saturating_add(farm.reward_budget.0))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SolvencyReport {
+    pub token_id: String,
+    pub balance: U128,
+    pub owed: U128,
+    pub deficit: U128,
+    pub farms: Vec<FarmLiabilityView>,
+    pub swap_owed: U128,
+    pub treasury: U128,
+    /// whether the contract is paused after the check
+    pub paused: bool,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SolvencyDeficitData<'a> {
+    pub token_id: &'a str,
+    pub balance: U128,
+    pub owed: U128,
+}
+
+impl Contract {
+    /// Liabilities in a token, or in Ref LP shares for `:<pool_id>`.
+    pub(crate) fn internal_liabilities(&self, token_id: &str) -> Liabilities {
+        let treasury = self.treasury.get(&token_id.to_string()).unwrap_or(0);
+        if let Some(pool_id) = token_id.strip_prefix(':') {
+            let pool_id: u64 = pool_id.parse().expect("Invalid Pool ID");
+            let farms = self
+                .farm_infos
+                .iter()
+                .filter(|farm_info| farm_info.farm_type != 0 && farm_info.pool_id == pool_id)
+                .map(|farm_info| FarmLiabilityView {
+                    farm_id: farm_info.farm_id,
+                    staked: U128(farm_info.total_lp_share_amount),
+                    unbonding: U128(farm_info.unbonding_lp_share_amount),
+                    locked_rewards: U128(0),
+                    reward_budget: U128(0),
+                })
+                .collect();
+            return Liabilities {
+                farms,
+                swap_owed: 0,
+                treasury,
+            };
+        }
+
+        let farms = self
+            .farm_infos
+            .iter()
+            .filter(|farm_info| farm_info.token_id.as_str() == token_id)
+            .map(|farm_info| FarmLiabilityView {
+                farm_id: farm_info.farm_id,
+                staked: U128(farm_info.total_token_amount),
+                unbonding: U128(farm_info.unbonding_token_amount + farm_info.unbonding_reward_amount),
+                locked_rewards: U128(farm_info.total_reward_amount),
+                reward_budget: U128(farm_info.remaining_reward_budget()),
+            })
+            .collect();
+        let swap_owed = if token_id == self.unet_token_id.as_str() { self.swap_owed } else { 0 };
+        Liabilities {
+            farms,
+            swap_owed,
+            treasury,
+        }
+    }
+
+    pub(crate) fn internal_balance_of(&self, token_id: &str) -> Promise {
+        if token_id.starts_with(':') {
+            ext_balance::ext(self.ref_exchange_id.clone())
+                .with_static_gas(GAS_FOR_BALANCE_OF)
+                .mft_balance_of(token_id.to_string(), env::current_account_id())
+        } else {
+            ext_balance::ext(token_id.parse().expect("Invalid token id"))
+                .with_static_gas(GAS_FOR_BALANCE_OF)
+                .ft_balance_of(env::current_account_id())
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Compares the contract's balance of a token (or of Ref LP shares for `:<pool_id>`) with
+    /// what it owes. Anyone may run the check, a deficit pauses the contract only when a
+    /// `Pauser` runs it.
+    pub fn check_solvency(&mut self, token_id: String) -> Promise {
+        let pause_on_deficit = self.has_role(&Role::Pauser, &env::predecessor_account_id());
+        self.internal_balance_of(&token_id).then(
+            ext_self::ext(env::current_account_id())
+                .with_static_gas(GAS_FOR_SOLVENCY_CALLBACK)
+                .on_solvency_balance(token_id, pause_on_deficit),
+        )
+    }
+
+    #[private]
+    pub fn on_solvency_balance(
+        &mut self,
+        token_id: String,
+        pause_on_deficit: bool,
+        #[callback_unwrap] balance: U128,
+    ) -> SolvencyReport {
+        let liabilities = self.internal_liabilities(&token_id);
+        let owed = liabilities.owed();
+        let deficit = owed.saturating_sub(balance.0);
+        if deficit > 0 {
+            if pause_on_deficit {
+                self.paused = true;
+            }
+            emit_event(
+                "solvency_deficit",
+                SolvencyDeficitData {
+                    token_id: &token_id,
+                    balance,
+                    owed: U128(owed),
+                },
+            );
+        }
+        SolvencyReport {
+            token_id,
+            balance,
+            owed: U128(owed),
+            deficit: U128(deficit),
+            farms: liabilities.farms,
+            swap_owed: U128(liabilities.swap_owed),
+            treasury: U128(liabilities.treasury),
+            paused: self.paused,
+        }
+    }
+
+    /// tracked liabilities in a token, broken down by farm, without querying the balance
+    pub fn get_liabilities(&self, token_id: String) -> Vec<FarmLiabilityView> {
+        self.internal_liabilities(&token_id).farms
+    }
+}
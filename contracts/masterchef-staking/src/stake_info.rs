@@ -2,6 +2,27 @@ use crate::*;
 
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct StakeInfo {
+    pub owner_id: AccountId,
+    pub token_amount: u128,
+    pub lp_share_amount: u128,
+    pub reward_amount: u128,
+    pub token_weight: u128,
+    pub lp_share_weight: u128,
+    pub reward_token_to_claim: u128,
+    pub reward_lp_to_claim: u128,
+    pub reward_reward_to_claim: u128,
+    pub positions: UnorderedMap<u64, Position>,
+    pub unbondings: Vector<Unbonding>,
+    pub created_at: u64,
+    pub claimed_token_at: u64,
+    pub claimed_lp_at: u64,
+    pub claimed_reward_at: u64,
+    pub nft_boost: Option<NftBoost>,
+}
+
+/// Layout of `StakeInfo` before positions replaced the parallel lock vectors, read by `migrate_stake_infos`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct LegacyStakeInfo {
     pub owner_id: AccountId,
     pub token_amount: u128,
     pub lp_share_amount: u128,
@@ -21,6 +42,57 @@ pub struct StakeInfo {
     pub claimed_reward_at: u64,
 }
 
+/// The same stake infos read with another value layout, a map only stores its prefix and length.
+pub fn with_layout<V, W>(stake_infos: &UnorderedMap<AccountId, V>) -> UnorderedMap<AccountId, W>
+where
+    V: BorshSerialize + BorshDeserialize,
+    W: BorshSerialize + BorshDeserialize,
+{
+    UnorderedMap::try_from_slice(&stake_infos.try_to_vec().unwrap()).unwrap()
+}
+
+impl StakeInfo {
+    pub fn new(farm_id: u16, account_id: AccountId, now: u64) -> Self {
+        Self {
+            owner_id: account_id.clone(),
+            token_amount: 0,
+            lp_share_amount: 0,
+            reward_amount: 0,
+            token_weight: 0,
+            lp_share_weight: 0,
+            reward_token_to_claim: 0,
+            reward_lp_to_claim: 0,
+            reward_reward_to_claim: 0,
+            positions: UnorderedMap::new(StorageKey::Positions {
+                farm_id,
+                account_id: account_id.clone(),
+            }),
+            unbondings: Vector::new(StorageKey::Unbondings {
+                farm_id,
+                account_id,
+            }),
+            created_at: now,
+            claimed_token_at: now,
+            claimed_lp_at: now,
+            claimed_reward_at: now,
+            nft_boost: None,
+        }
+    }
+
+    /// applies the multiplier of the deposited boost NFT, if any, to a newly staked weight
+    pub fn boosted_weight(&self, weight: u128) -> u128 {
+        if let Some(nft_boost) = &self.nft_boost {
+            weight
+                .checked_mul(u128::from(nft_boost.multiplier))
+                .unwrap()
+                .checked_div(BOOST_MULTIPLIER_DENOMINATOR)
+                .unwrap()
+        } else {
+            weight
+        }
+    }
+}
+
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct SwapStakeInfo {
     pub owner_id: AccountId,
@@ -31,172 +103,166 @@ pub struct SwapStakeInfo {
     pub claimed_token_at: Vector<u64>,
 }
 
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimSummary {
+    pub farm_id: u16,
+    pub token_reward: U128,
+    pub lp_reward: U128,
+    pub reward_reward: U128,
+    pub unlocked_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ClaimAllPage {
+    pub claims: Vec<ClaimSummary>,
+    /// `from_farm_id` to continue from when the caller's farms did not fit in one call
+    pub next_farm_id: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MaturedWithdrawal {
+    pub token_amount: U128,
+    pub lp_share_amount: U128,
+    pub reward_amount: U128,
+}
+
 #[near_bindgen]
 impl Contract {
     #[payable]
     pub fn claim_reward(&mut self, farm_id: u16, token_type: u8) {
+        self.assert_not_paused();
         assert_one_yocto();
         let account_id = env::predecessor_account_id();
-        let mut claim_amount = 0;
         let now = env::block_timestamp() / 1000000;
+        self.internal_claim_reward(farm_id, &account_id, token_type, now);
+    }
 
-        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
-        if token_type == 0 && farm_info.farm_type == 1 {
-            env::panic_str("Token Pool does not exist in this Farm");
-        } else if token_type == 1 && farm_info.farm_type == 0 {
-            env::panic_str("LP Pool does not exist in this Farm");
-        }
+    /// Claims token, LP and reward-on-reward rewards of every given farm in one call. Without
+    /// farm ids it claims the first `MAX_CLAIM_ALL_FARMS` farms the caller is in from
+    /// `from_farm_id` on, the page tells where to continue.
+    #[payable]
+    pub fn claim_all(&mut self, farm_ids: Option<Vec<u16>>, from_farm_id: Option<u16>) -> ClaimAllPage {
+        self.assert_not_paused();
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let now = env::block_timestamp() / 1000000;
 
-        if token_type == 0 {
-            self.update_claim_amounts(farm_id, 0, now);
-            self.update_claim_amounts(farm_id, 2, now);
-        }
-        else if token_type == 1 {
-            self.update_claim_amounts(farm_id, 1, now);
-            self.update_claim_amounts(farm_id, 2, now);
-        } else if token_type == 2 {
-            self.update_claim_amounts(farm_id, 2, now);
-            return;
-        }
-        farm_info = self.farm_infos.get(farm_id.into()).unwrap();
-        let mut stake_info = farm_info.stake_infos.get(&account_id).unwrap();
-        let total_reward_amount = farm_info.total_reward_amount;
-
-
-        if token_type == 0 {
-            claim_amount = self.claim_amount(farm_id, account_id.clone(), 0, now);
-            stake_info.reward_token_to_claim = 0;
-            stake_info.claimed_token_at = now;
-            stake_info.token_locked.push(&U128(0));
-            stake_info.lp_share_locked.push(&U128(0));
-            stake_info.reward_locked.push(&U128(claim_amount));
-            stake_info
-                .unlocked_at
-                .push(&(now + farm_info.max_reward_vesting_duration));
-            stake_info.staking_duration.push(&farm_info.max_reward_vesting_duration);
-
-            stake_info.reward_amount = stake_info.reward_amount.checked_add(claim_amount)
-                    .unwrap();
-            farm_info.total_reward_amount = farm_info.total_reward_amount.checked_add(claim_amount).unwrap();
+        let mut next_farm_id = None;
+        let mut farm_ids = farm_ids.unwrap_or_else(|| {
+            let mut farm_ids = vec![];
+            for idx in u64::from(from_farm_id.unwrap_or(0))..self.farm_infos.len() {
+                if farm_ids.len() == MAX_CLAIM_ALL_FARMS {
+                    next_farm_id = Some(idx as u16);
+                    break;
+                }
+                if self.farm_infos.get(idx).unwrap().stake_infos.get(&account_id).is_some() {
+                    farm_ids.push(idx as u16);
+                }
+            }
+            farm_ids
+        });
+        let mut seen = vec![];
+        farm_ids.retain(|farm_id| {
+            if seen.contains(farm_id) {
+                false
+            } else {
+                seen.push(*farm_id);
+                true
+            }
+        });
+        assert!(
+            farm_ids.len() <= MAX_CLAIM_ALL_FARMS,
+            "Too many farms to claim at once, pass at most {} farm ids",
+            MAX_CLAIM_ALL_FARMS
+        );
+        let required_gas = GAS_FOR_CLAIM_PER_FARM.0 * farm_ids.len() as u64;
+        assert!(
+            env::prepaid_gas().0 >= required_gas,
+            "Not enough gas to claim {} farms, attach at least {} TGas",
+            farm_ids.len(),
+            required_gas / 1_000_000_000_000
+        );
+
+        let mut summaries = vec![];
+        for farm_id in farm_ids {
+            assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+            let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+            if farm_info.stake_infos.get(&account_id).is_none() {
+                continue;
+            }
+            let mut token_reward = 0;
+            let mut lp_reward = 0;
+            if farm_info.farm_type == 0 || farm_info.farm_type == 2 {
+                token_reward = self.internal_claim_reward(farm_id, &account_id, 0, now);
+            }
+            if farm_info.farm_type == 1 || farm_info.farm_type == 2 {
+                lp_reward = self.internal_claim_reward(farm_id, &account_id, 1, now);
+            }
+            let reward_reward = self.internal_claim_reward(farm_id, &account_id, 2, now);
+            summaries.push(ClaimSummary {
+                farm_id,
+                token_reward: U128(token_reward),
+                lp_reward: U128(lp_reward),
+                reward_reward: U128(reward_reward),
+                unlocked_at: now + farm_info.max_reward_vesting_duration,
+            });
         }
-        else if token_type == 1 {
-            claim_amount = self.claim_amount(farm_id, account_id.clone(), 1, now);
-            stake_info.reward_lp_to_claim = 0;
-            stake_info.claimed_lp_at = now;
-            stake_info.token_locked.push(&U128(0));
-            stake_info.lp_share_locked.push(&U128(0));
-            stake_info.reward_locked.push(&U128(claim_amount));
-            stake_info
-                .unlocked_at
-                .push(&(now + farm_info.max_reward_vesting_duration));
-            stake_info.staking_duration.push(&farm_info.max_reward_vesting_duration);
-
-            stake_info.reward_amount = stake_info.reward_amount.checked_add(claim_amount)
-                    .unwrap();
-            farm_info.total_reward_amount = farm_info.total_reward_amount.checked_add(claim_amount).unwrap();
+        ClaimAllPage {
+            claims: summaries,
+            next_farm_id,
         }
-
-        farm_info.stake_infos.insert(&account_id, &stake_info);
-        self.farm_infos.replace(farm_id.into(), &farm_info);
     }
 
+    /// Withdraws unlocked stake, oldest positions first. On farms with an unbonding period
+    /// the amount is queued instead, see `request_unstake`, and the storage of the queued entry
+    /// is charged to the caller's storage deposit.
     #[payable]
     pub fn unstake(&mut self, farm_id: u16, token_type: u8, amount: U128) {
         assert_one_yocto();
         let account_id = env::predecessor_account_id();
-        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
-        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let initial_storage_usage = env::storage_usage();
+        let refunded = self.internal_unstake(farm_id, &account_id, token_type, amount);
+        self.internal_settle_storage(&account_id, initial_storage_usage - refunded);
+    }
 
-        let mut stake_info = farm_info.stake_infos.get(&account_id).unwrap();
+    /// Withdraws every unlocked token, LP share and reward position of the farm. The emptied
+    /// positions are removed and the freed storage the caller paid for is refunded to their
+    /// storage balance, amounts queued for unbonding are charged like in `request_unstake`.
+    #[payable]
+    pub fn withdraw_matured(&mut self, farm_id: u16) -> MaturedWithdrawal {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let now = env::block_timestamp() / 1000000;
+        let initial_storage_usage = env::storage_usage();
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let stake_info = farm_info.stake_infos.get(&account_id).expect("No stake in this Farm");
 
-        if token_type == 0 && farm_info.farm_type == 1 {
-            env::panic_str("Token Pool does not exist in this Farm");
-        } else if token_type == 1 && farm_info.farm_type == 0 {
-            env::panic_str("LP Pool does not exist in this Farm");
+        // read every amount up front, unstaking rewrites the positions behind `stake_info`
+        let token_amount = stake_info.matured_amount(PositionKind::Token, now);
+        let lp_share_amount = stake_info.matured_amount(PositionKind::LpShare, now);
+        let reward_amount = stake_info.matured_amount(PositionKind::Reward, now);
+        let mut refunded = 0;
+        if token_amount > 0 {
+            refunded += self.internal_unstake(farm_id, &account_id, 0, U128(token_amount));
         }
-
-        if token_type == 0 {
-            assert!(
-                amount.0
-                    <= (stake_info.token_amount
-                        - self.token_locked_amount(farm_id, account_id.clone())),
-                "Amount must be smaller than available amount"
-            );
-        } else if token_type == 1 {
-            assert!(
-                amount.0
-                    <= (stake_info.lp_share_amount
-                        - self.lp_locked_amount(farm_id, account_id.clone())),
-                "Amount must be smaller than available amount"
-            );
-        } else if token_type == 2 {
-            assert!(
-                amount.0
-                    <= (stake_info.reward_amount
-                        - self.reward_locked_amount(farm_id, account_id.clone())),
-                "Amount must be smaller than available amount"
-            );
+        if lp_share_amount > 0 {
+            refunded += self.internal_unstake(farm_id, &account_id, 1, U128(lp_share_amount));
+        }
+        if reward_amount > 0 {
+            refunded += self.internal_unstake(farm_id, &account_id, 2, U128(reward_amount));
         }
 
-        let now = env::block_timestamp() / 1000000;
+        // what went back to the storage payers of emptied positions is not freed for the caller
+        self.internal_settle_storage(&account_id, initial_storage_usage - refunded);
 
-        self.update_claim_amounts(farm_id, token_type, now);
-
-        farm_info = self.farm_infos.get(farm_id.into()).unwrap();
-        stake_info = farm_info.stake_infos.get(&account_id).unwrap();
-
-        if token_type == 0 {
-            ext_transfer::ext(AccountId::new_unchecked(farm_info.token_id.to_string()))
-                .with_attached_deposit(1)
-                .with_static_gas(GAS_FOR_FT_TRANSFER.into())
-                .ft_transfer(
-                    account_id.clone().try_into().unwrap(), 
-                    amount,
-                    None,
-                );
-            let multiplier: u128 = 10;
-            let reward_weight = u128::from(amount)
-                .checked_mul(100_000_000).unwrap()
-                .checked_div(stake_info.token_amount).unwrap()
-                .checked_mul(stake_info.token_weight).unwrap()
-                .checked_div(100_000_000).unwrap();
-            stake_info.token_weight = stake_info.token_weight.checked_sub(reward_weight).unwrap();
-            farm_info.total_token_weight = farm_info.total_token_weight.checked_sub(reward_weight).unwrap();
-            stake_info.token_amount = stake_info.token_amount.checked_sub(u128::from(amount))
-                    .unwrap();
-            farm_info.total_token_amount = farm_info.total_token_amount.checked_sub(u128::from(amount)).unwrap();
-        } else if token_type == 1 {
-            let reward_weight = u128::from(amount).
-            checked_mul(100_000_000).unwrap().
-            checked_div(stake_info.lp_share_amount).unwrap().
-            checked_mul(stake_info.lp_share_weight).unwrap().
-            checked_div(100_000_000).unwrap();
-            stake_info.lp_share_weight = stake_info.lp_share_weight.checked_sub(reward_weight).unwrap();
-            farm_info.total_lp_share_weight = farm_info.total_lp_share_weight.checked_sub(reward_weight).unwrap();
-            stake_info.lp_share_amount = stake_info.lp_share_amount.checked_sub(u128::from(amount)).unwrap();
-            farm_info.total_lp_share_amount = farm_info.total_lp_share_amount.checked_sub(u128::from(amount)).unwrap();
-            ext_transfer::ext(AccountId::new_unchecked(REF_CONTRACT_ID.to_string()))
-            .with_attached_deposit(1)
-            .with_static_gas(GAS_FOR_FT_TRANSFER.into())
-            .mft_transfer(
-                ":".to_owned() + &farm_info.pool_id.to_string(),
-                account_id.clone().try_into().unwrap(),
-                amount,
-                None,
-            );
-        } else if token_type == 2 {
-            ext_transfer::ext(AccountId::new_unchecked(farm_info.token_id.to_string()))
-                .with_attached_deposit(1)
-                .with_static_gas(GAS_FOR_FT_TRANSFER.into())
-                .ft_transfer(
-                    account_id.clone().try_into().unwrap(), 
-                    amount,
-                    None,
-                );
-            stake_info.reward_amount = stake_info.reward_amount.checked_sub(u128::from(amount)).unwrap();
-            farm_info.total_reward_amount = farm_info.total_reward_amount.checked_sub(u128::from(amount)).unwrap();
+        MaturedWithdrawal {
+            token_amount: U128(token_amount),
+            lp_share_amount: U128(lp_share_amount),
+            reward_amount: U128(reward_amount),
         }
-        farm_info.stake_infos.insert(&account_id, &stake_info);
-        self.farm_infos.replace(farm_id.into(), &farm_info);
     }
 }
@@ -0,0 +1,157 @@
+use crate::*;
+
+const DAY: u64 = 24 * 3600 * 1000;
+const SECONDS_PER_YEAR: u128 = 365 * 24 * 3600;
+/// upper bounds (ms from now) of the lock expiry histogram buckets, a last bucket takes the rest
+const LOCK_EXPIRY_BUCKETS: [u64; 6] = [0, DAY, 7 * DAY, 30 * DAY, 90 * DAY, 365 * DAY];
+/// LP shares are counted with 24 decimals like Ref's
+const LP_SHARE_UNIT: u128 = 1_000_000_000_000_000_000_000_000;
+
+/// Stake principal whose lock ends within `expires_within` ms from now, `Some(0)` for stake
+/// that is already unlocked and `None` for the last bucket.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LockExpiryBucket {
+    pub expires_within: Option<u64>,
+    pub positions: u64,
+    pub token_amount: U128,
+    pub lp_share_amount: U128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FarmStatsView {
+    pub farm_id: u16,
+    /// zero outside of the farm's lifetime
+    pub emission_per_second: EmissionRateView,
+    pub total_token_amount: U128,
+    pub total_lp_share_amount: U128,
+    /// APR of one more token staked with the given lock at the current emission, `None`
+    /// without a token pool. The token pool pays in the staked token, so no price is involved.
+    pub token_apr_bps: Option<U128>,
+    /// reward tokens (smallest unit) a year for one more LP share staked with the given lock,
+    /// `None` without an LP pool. Not an APR, the contract knows no price of the shares.
+    pub lp_share_yearly_reward: Option<U128>,
+    /// accounts of the page with tokens or LP shares staked
+    pub staker_count: u64,
+    /// staked token and LP share positions of the page
+    pub position_count: u64,
+    /// summed lock duration of those positions, the mean is over `position_count`
+    pub total_lock_duration: U128,
+    pub lock_expiries: Vec<LockExpiryBucket>,
+    /// `from_index` of the next page, `None` after the last staker
+    pub next_index: Option<u64>,
+}
+
+/// Yearly reward of `amount` staked now with `lock_duration` at the current emission `rate`, in
+/// the reward token. Weighs the stake like `ft_on_transfer` and `mft_on_transfer`, the farm
+/// totals are restored afterwards.
+fn stake_yearly_reward(farm_info: &mut FarmInfo, token_type: u8, amount: u128, lock_duration: u64, rate: u128) -> u128 {
+    let (weight, total_weight) = if token_type == 0 {
+        farm_info.total_token_amount += amount;
+        let weight = farm_info.token_weight_for(amount, lock_duration);
+        farm_info.total_token_amount -= amount;
+        (weight, farm_info.total_token_weight + weight)
+    } else {
+        farm_info.total_lp_share_amount += amount;
+        let weight = farm_info.lp_share_weight_for(amount, lock_duration);
+        farm_info.total_lp_share_amount -= amount;
+        (weight, farm_info.total_lp_share_weight + weight)
+    };
+    reward_for(share_of(weight, total_weight), rate * SECONDS_PER_YEAR)
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Emission and APR for a stake locked for `lock_duration` of the farm, with the staker
+    /// count and lock distribution of the stakers in `[from_index, from_index + limit)`, as of
+    /// the current block. The counts of all pages add up to the farm's.
+    pub fn get_farm_stats(&self, farm_id: u16, lock_duration: u64, from_index: u64, limit: u64) -> FarmStatsView {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let now = env::block_timestamp() / 1000000;
+
+        let emission_per_second = if farm_info.starting_at <= now && now < farm_info.ending_at {
+            self.get_emission_rate(farm_id, now)
+        } else {
+            EmissionRateView {
+                token_reward_rate: U128(0),
+                pool_reward_rate: U128(0),
+                reward_reward_rate: U128(0),
+            }
+        };
+        let has_token_pool = farm_info.farm_type == 0 || farm_info.farm_type == 2;
+        let has_lp_pool = farm_info.farm_type == 1 || farm_info.farm_type == 2;
+        let token_apr_bps = if has_token_pool {
+            let unit = 10u128.pow(farm_info.token_decimal.into());
+            let rate = emission_per_second.token_reward_rate.0;
+            let yearly_reward = stake_yearly_reward(&mut farm_info, 0, unit, lock_duration, rate);
+            Some(U128(mul_div(yearly_reward, 10000, unit)))
+        } else {
+            None
+        };
+        let lp_share_yearly_reward = if has_lp_pool {
+            let rate = emission_per_second.pool_reward_rate.0;
+            Some(U128(stake_yearly_reward(&mut farm_info, 1, LP_SHARE_UNIT, lock_duration, rate)))
+        } else {
+            None
+        };
+
+        let mut lock_expiries: Vec<LockExpiryBucket> = LOCK_EXPIRY_BUCKETS
+            .iter()
+            .map(|bound| Some(*bound))
+            .chain(std::iter::once(None))
+            .map(|expires_within| LockExpiryBucket {
+                expires_within,
+                positions: 0,
+                token_amount: U128(0),
+                lp_share_amount: U128(0),
+            })
+            .collect();
+        let mut staker_count = 0;
+        let mut position_count = 0;
+        let mut total_lock_duration: u128 = 0;
+        let stake_infos = farm_info.stake_infos.values_as_vector();
+        let end = min(from_index.saturating_add(limit), stake_infos.len());
+        for index in from_index..end {
+            let stake_info = stake_infos.get(index).unwrap();
+            if stake_info.token_amount > 0 || stake_info.lp_share_amount > 0 {
+                staker_count += 1;
+            }
+            for position in stake_info.positions.values() {
+                if position.kind == PositionKind::Reward {
+                    continue;
+                }
+                position_count += 1;
+                total_lock_duration += u128::from(position.duration);
+
+                let expires_in = position.unlocked_at.saturating_sub(now);
+                let bucket = LOCK_EXPIRY_BUCKETS
+                    .iter()
+                    .position(|bound| expires_in <= *bound)
+                    .unwrap_or(LOCK_EXPIRY_BUCKETS.len());
+                let bucket = &mut lock_expiries[bucket];
+                bucket.positions += 1;
+                if position.kind == PositionKind::Token {
+                    bucket.token_amount.0 += position.amount;
+                } else {
+                    bucket.lp_share_amount.0 += position.amount;
+                }
+            }
+        }
+
+        FarmStatsView {
+            farm_id,
+            emission_per_second,
+            total_token_amount: U128(farm_info.total_token_amount),
+            total_lp_share_amount: U128(farm_info.total_lp_share_amount),
+            token_apr_bps,
+            lp_share_yearly_reward,
+            staker_count,
+            position_count,
+            total_lock_duration: U128(total_lock_duration),
+            lock_expiries,
+            next_index: if end < stake_infos.len() { Some(end) } else { None },
+        }
+    }
+}
@@ -52,6 +52,71 @@ impl SwapFarmInfo {
     }
 }
 
+/// unstakable amount of the swap stake at `idx`, see `swap_vested_amount`
+pub(crate) fn swap_vested_amount_of(swap_farm_info: &SwapFarmInfo, stake_info: &SwapStakeInfo, idx: u64, now: u64) -> u128 {
+    swap_vested_amount(
+        stake_info.token_locked.get(idx).unwrap().0,
+        stake_info.claimed_token_at.get(idx).unwrap(),
+        stake_info.unlocked_at.get(idx).unwrap(),
+        swap_farm_info.min_lock_time,
+        swap_farm_info.max_lock_time,
+        now,
+    )
+}
+
+impl Contract {
+    pub(crate) fn internal_save_swap_farm(
+        &mut self,
+        token_id: AccountId,
+        swap_rate: U128,
+        decimal: u32,
+        min_lock_time: u64,
+        max_lock_time: u64,
+    ) {
+        let mut swap_tokens = self.swap_farms.keys_as_vector().to_vec();
+
+        if swap_tokens.contains(&token_id) {
+            let mut swap_farm_info = self.swap_farms.get(&token_id).unwrap();
+            swap_farm_info.min_lock_time = min_lock_time;
+            swap_farm_info.max_lock_time = max_lock_time;
+            swap_farm_info.token_decimal = decimal;
+            swap_farm_info.swap_rate = swap_rate;
+            self.swap_farms.insert(&token_id, &swap_farm_info);
+        } else {
+            self.swap_farms.insert(
+                &token_id,
+                &SwapFarmInfo::new(token_id.clone(), decimal, min_lock_time, max_lock_time, swap_rate),
+            );
+        }
+
+        swap_tokens = self.editing_swap_farm_infos.keys_as_vector().to_vec();
+        if swap_tokens.contains(&token_id) {
+            let mut editing_swap_farm_info = self.editing_swap_farm_infos.get(&token_id).unwrap();
+            editing_swap_farm_info.min_lock_time = min_lock_time;            editing_swap_farm_info.max_lock_time = max_lock_time;
+            editing_swap_farm_info.swap_rate = swap_rate;
+            editing_swap_farm_info.confirmed_admins.clear();
+            self.editing_swap_farm_infos
+                .insert(&token_id, &editing_swap_farm_info);
+        } else {
+            self.editing_swap_farm_infos.insert(
+                &token_id,
+                &EditingSwapFarmInfoData::new(
+                    token_id.clone(),
+                    swap_rate,
+                    min_lock_time,
+                    max_lock_time,
+                    self.editing_swap_farm_infos.len(),
+                ),
+            );
+        }
+    }
+
+    pub(crate) fn internal_remove_swap_farm(&mut self, token_id: &AccountId) {
+        self.swap_farms.remove(token_id);
+        self.editing_swap_farm_infos.remove(token_id);
+    }
+}
+
 #[near_bindgen]
 impl Contract {
     // #[payable]
@@ -71,17 +136,20 @@ impl Contract {
     //     );
     // }
 
+    /// Requires the `SwapManager` role. Only while the timelock is off, swap farms are then
+    /// edited with `queue_operation`.
     #[payable]
     pub fn confirm_editing_swap_farm_info(&mut self, token_id: AccountId) {
-        self.assert_admin();
+        self.assert_role(Role::SwapManager);
         assert_one_yocto();
+        self.assert_not_timelocked();
         let swap_tokens = self.editing_swap_farm_infos.keys_as_vector().to_vec();
         if swap_tokens.contains(&token_id) {
             let mut editing_swap_farm_data = self.editing_swap_farm_infos.get(&token_id).unwrap();
             editing_swap_farm_data
                 .confirmed_admins
                 .insert(&env::predecessor_account_id());
-            if self.admin_ids.len() <= editing_swap_farm_data.confirmed_admins.len() {
+            if self.is_dao_call() || self.role_holder_count(&Role::SwapManager) <= editing_swap_farm_data.confirmed_admins.len() {
                 editing_swap_farm_data.confirmed_admins.clear();
                 let mut swap_farm_data = self.swap_farms.get(&token_id).unwrap();
                 swap_farm_data.min_lock_time = editing_swap_farm_data.min_lock_time;
@@ -96,6 +164,8 @@ impl Contract {
         }
     }
 
+    /// Requires the `SwapManager` role. Only while the timelock is off, swap farms are then
+    /// edited with `queue_operation`.
     #[payable]
     pub fn save_editing_swap_farm_info(
         &mut self,
@@ -104,8 +174,9 @@ impl Contract {
         min_lock_time: u64,
         max_lock_time: u64,
     ) {
-        self.assert_admin();
+        self.assert_role(Role::SwapManager);
         assert_one_yocto();
+        self.assert_not_timelocked();
         let swap_tokens = self.editing_swap_farm_infos.keys_as_vector().to_vec();
         if swap_tokens.contains(&token_id) {
             let mut editing_swap_farm_info = self.editing_swap_farm_infos.get(&token_id).unwrap();
@@ -120,8 +191,9 @@ impl Contract {
             self.editing_swap_farm_infos
                 .insert(&token_id, &editing_swap_farm_info);
 
-            if self.admin_ids.as_vector().len()
-                == editing_swap_farm_info.confirmed_admins.as_vector().len()
+            if self.is_dao_call()
+                || self.role_holder_count(&Role::SwapManager)
+                    == editing_swap_farm_info.confirmed_admins.as_vector().len()
             {
                 let mut swap_farm_data = self.swap_farms.get(&token_id).unwrap();
                 swap_farm_data.min_lock_time = min_lock_time;
@@ -146,6 +218,7 @@ impl Contract {
         }
     }
 
+    /// Requires the `SwapManager` role. Goes through `queue_operation` while the timelock is on.
     #[payable]
     pub fn save_swap_farm(
         &mut self,
@@ -155,54 +228,22 @@ impl Contract {
         min_lock_time: u64,
         max_lock_time: u64,
     ) {
-        self.assert_owner();
+        self.assert_role(Role::SwapManager);
         assert_one_yocto();
-        let mut swap_tokens = self.swap_farms.keys_as_vector().to_vec();
-
-        if swap_tokens.contains(&token_id) {
-            let mut swap_farm_info = self.swap_farms.get(&token_id).unwrap();
-            swap_farm_info.min_lock_time = min_lock_time;
-            swap_farm_info.max_lock_time = max_lock_time;
-            swap_farm_info.token_decimal = decimal;
-            swap_farm_info.swap_rate = swap_rate;
-            self.swap_farms.insert(&token_id, &swap_farm_info);
-        } else {
-            self.swap_farms.insert(
-                &token_id,
-                &SwapFarmInfo::new(token_id.clone(), decimal, min_lock_time, max_lock_time, swap_rate),
-            );
-        }
-
-        swap_tokens = self.editing_swap_farm_infos.keys_as_vector().to_vec();
-        if swap_tokens.contains(&token_id) {
-            let mut editing_swap_farm_info = self.editing_swap_farm_infos.get(&token_id).unwrap();
-            editing_swap_farm_info.min_lock_time = min_lock_time;            editing_swap_farm_info.max_lock_time = max_lock_time;
-            editing_swap_farm_info.swap_rate = swap_rate;
-            editing_swap_farm_info.confirmed_admins.clear();
-            self.editing_swap_farm_infos
-                .insert(&token_id, &editing_swap_farm_info);
-        } else {
-            self.editing_swap_farm_infos.insert(
-                &token_id,
-                &EditingSwapFarmInfoData::new(
-                    token_id.clone(),
-                    swap_rate,
-                    min_lock_time,
-                    max_lock_time,
-                    self.editing_swap_farm_infos.len(),
-                ),
-            );
-        }
+        self.assert_not_timelocked();
+        self.internal_save_swap_farm(token_id, swap_rate, decimal, min_lock_time, max_lock_time);
     }
 
+    /// Requires the `SwapManager` role. Goes through `queue_operation` while the timelock is on.
     #[payable]
     pub fn remove_swap_farm(&mut self, token_id: AccountId) {
-        self.assert_owner();
+        self.assert_role(Role::SwapManager);
         assert_one_yocto();
-        self.swap_farms.remove(&token_id);
-        self.editing_swap_farm_infos.remove(&token_id);
+        self.assert_not_timelocked();
+        self.internal_remove_swap_farm(&token_id);
     }
 
+
     #[payable]
     pub fn unstake_swap(
         &mut self,
@@ -235,6 +276,7 @@ impl Contract {
         stake_info
             .claimed_token_at
             .replace(id, &(env::block_timestamp() / 1000000));
+        self.swap_owed = self.swap_owed.saturating_sub(amount_2_unstake.0);
 
         // ext_transfer::ft_transfer(
         //     account_id.clone().try_into().unwrap(),
@@ -244,7 +286,7 @@ impl Contract {
         //     1,
         //     GAS_FOR_FT_TRANSFER,
         // );
-        ext_transfer::ext(AccountId::new_unchecked(TOKEN_CONTRACT_ID.to_string()))
+        ext_transfer::ext(self.unet_token_id.clone())
             .with_attached_deposit(1)
             .with_static_gas(GAS_FOR_FT_TRANSFER)
             .ft_transfer(
@@ -257,47 +299,10 @@ impl Contract {
     pub fn get_swap_unstake_amount(&self, token_id: AccountId, account_id: AccountId) -> Vec<U128> {
         let swap_farm_data = self.swap_farms.get(&token_id).unwrap();
         let stake_info = swap_farm_data.stake_infos.get(&account_id).unwrap();
-        let mut unstake_amount = vec![];
-        let start_idx: u64 = 0;
-        let end_idx = stake_info.token_locked.len();
-        for idx in start_idx..end_idx {
-            if stake_info.unlocked_at.get(idx).unwrap() < env::block_timestamp() / 1000000 {
-                unstake_amount.push(stake_info.token_locked.get(idx).unwrap());
-            } else if u64::from(stake_info.unlocked_at.get(idx).unwrap())
-                .checked_sub(swap_farm_data.max_lock_time)
-                .unwrap()
-                .checked_add(u64::from(swap_farm_data.min_lock_time))
-                .unwrap()
-                > env::block_timestamp() / 1000000
-            {
-                unstake_amount.push(U128(0));
-            } else if stake_info.unlocked_at.get(idx).unwrap() >= env::block_timestamp() / 1000000 {
-                unstake_amount.push(U128::from(
-                    u128::from(stake_info.token_locked.get(idx).unwrap())
-                        .checked_div(10000000000)
-                        .unwrap()
-                        .checked_mul(u128::from(
-                            u128::from(env::block_timestamp() / 1000000)
-                                .checked_sub(u128::from(
-                                    stake_info.claimed_token_at.get(idx).unwrap(),
-                                ))
-                                .unwrap(),
-                        ))
-                        .unwrap()
-                        .checked_div(u128::from(
-                            u128::from(stake_info.unlocked_at.get(idx).unwrap())
-                                .checked_sub(u128::from(
-                                    stake_info.claimed_token_at.get(idx).unwrap(),
-                                ))
-                                .unwrap(),
-                        ))
-                        .unwrap()
-                        .checked_mul(10000000000)
-                        .unwrap(),
-                ));
-            }
-        }
-        unstake_amount
+        let now = env::block_timestamp() / 1000000;
+        (0..stake_info.token_locked.len())
+            .map(|idx| U128(swap_vested_amount_of(&swap_farm_data, &stake_info, idx, now)))
+            .collect()
     }
 
     pub fn get_swap_unstake_amount_by_id(
@@ -309,39 +314,7 @@ impl Contract {
         let swap_farm_data = self.swap_farms.get(&token_id).unwrap();
         let stake_info = swap_farm_data.stake_infos.get(&account_id).unwrap();
         assert!(stake_info.token_locked.len() >= id, "Invalid staking id");
-        let mut unstake_amount = U128(0);
-        if stake_info.unlocked_at.get(id).unwrap() < env::block_timestamp() / 1000000 {
-            unstake_amount = U128::from(u128::from(stake_info.token_locked.get(id).unwrap()));
-        } else if u64::from(stake_info.unlocked_at.get(id).unwrap())
-            .checked_sub(swap_farm_data.max_lock_time)
-            .unwrap()
-            .checked_add(u64::from(swap_farm_data.min_lock_time))
-            .unwrap()
-            > env::block_timestamp() / 1000000
-        {
-            unstake_amount = U128(0);
-        } else {
-            unstake_amount = U128::from(
-                u128::from(stake_info.token_locked.get(id).unwrap())
-                    .checked_div(10000000000)
-                    .unwrap()
-                    .checked_mul(u128::from(
-                        u128::from(env::block_timestamp() / 1000000)
-                            .checked_sub(u128::from(stake_info.claimed_token_at.get(id).unwrap()))
-                            .unwrap(),
-                    ))
-                    .unwrap()
-                    .checked_div(u128::from(
-                        u128::from(stake_info.unlocked_at.get(id).unwrap())
-                            .checked_sub(u128::from(stake_info.claimed_token_at.get(id).unwrap()))
-                            .unwrap(),
-                    ))
-                    .unwrap()
-                    .checked_mul(10000000000)
-                    .unwrap(),
-            );
-        };
-        unstake_amount
+        let now = env::block_timestamp() / 1000000;
+        U128(swap_vested_amount_of(&swap_farm_data, &stake_info, id, now))
     }
-
 }
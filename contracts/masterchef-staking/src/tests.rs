@@ -0,0 +1,1513 @@
+use crate::ft_callbacks::*;
+use crate::*;
+
+use near_sdk::test_utils::{accounts, VMContextBuilder};
+use near_sdk::{testing_env, PromiseResult};
+use proptest::prelude::*;
+
+const ONE_TOKEN: u128 = 1_000_000_000_000_000_000;
+const TOKEN_REWARD_RATE: u128 = ONE_TOKEN;
+const REWARD_REWARD_RATE: u128 = ONE_TOKEN / 10;
+
+fn token_id() -> AccountId {
+    AccountId::new_unchecked(TOKEN_CONTRACT_ID.to_string())
+}
+
+/// `timestamp` is in milliseconds like every time stored by the contract
+fn set_context(predecessor: AccountId, timestamp: u64, deposit: Balance) {
+    testing_env!(VMContextBuilder::new()
+        .current_account_id(AccountId::new_unchecked("staking.testnet".to_string()))
+        .predecessor_account_id(predecessor)
+        .block_timestamp(timestamp * 1_000_000)
+        .attached_deposit(deposit)
+        .build());
+}
+
+/// context of a callback run by the contract itself after a promise with `result`
+fn set_callback_context(timestamp: u64, result: PromiseResult) {
+    let contract_id = AccountId::new_unchecked("staking.testnet".to_string());
+    testing_env!(
+        VMContextBuilder::new()
+            .current_account_id(contract_id.clone())
+            .predecessor_account_id(contract_id)
+            .block_timestamp(timestamp * 1_000_000)
+            .build(),
+        near_sdk::VMConfig::test(),
+        near_sdk::RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![result],
+    );
+}
+
+fn setup_token_farm() -> Contract {
+    // start from empty storage, property tests set up many contracts on one thread
+    env::set_blockchain_interface(near_sdk::MockedBlockchain::default());
+    set_context(accounts(0), 0, 1);
+    let mut contract = Contract::new(accounts(0));
+    contract.add_farm(
+        0,
+        token_id(),
+        18,
+        0,
+        U128(TOKEN_REWARD_RATE),
+        U128(0),
+        U128(REWARD_REWARD_RATE),
+        1000,
+        0,
+        1_000_000,
+        1_000_000,
+        0,
+        100_000_000,
+    );
+    contract
+}
+
+fn stake_tokens(contract: &mut Contract, account_id: AccountId, amount: u128, timestamp: u64) {
+    set_context(token_id(), timestamp, 0);
+    contract.ft_on_transfer(
+        account_id,
+        U128(amount),
+        "{\"stake_type\": 0, \"farm_id\": 0, \"lock_duration\": 100000}".to_string(),
+    );
+}
+
+/// (id, amount, unlocked_at) of every reward position, with the account's reward amount
+fn reward_vesting(contract: &Contract, account_id: &AccountId) -> (Vec<(u64, u128, u64)>, u128) {
+    let stake_info = contract.staking_informations_by_owner_id(0, account_id.clone());
+    (
+        stake_info
+            .sorted_positions()
+            .iter()
+            .filter(|position| position.kind == PositionKind::Reward)
+            .map(|position| (position.id, position.amount, position.unlocked_at))
+            .collect(),
+        stake_info.reward_amount,
+    )
+}
+
+fn stake_tokens_for(contract: &mut Contract, sender_id: AccountId, beneficiary_id: &AccountId, amount: u128, timestamp: u64) {
+    set_context(token_id(), timestamp, 0);
+    contract.ft_on_transfer(
+        sender_id,
+        U128(amount),
+        format!(
+            "{{\"stake_type\": 0, \"farm_id\": 0, \"lock_duration\": 100000, \"beneficiary\": \"{}\"}}",
+            beneficiary_id
+        ),
+    );
+}
+
+#[test]
+fn stake_for_a_beneficiary_charges_the_sender_storage() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(2), 0, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+
+    stake_tokens_for(&mut contract, accounts(2), &accounts(3), 100 * ONE_TOKEN, 1_000);
+
+    let farm_info = contract.farm_infos.get(0).unwrap();
+    assert!(farm_info.stake_infos.get(&accounts(2)).is_none());
+    let positions = contract.get_positions(0, accounts(3));
+    assert_eq!(positions.len(), 1);
+    assert_eq!(positions[0].amount.0, 100 * ONE_TOKEN);
+    assert_eq!(positions[0].unlocked_at, 101_000);
+    assert!(contract.storage_balance_of(accounts(2)).0 < 10 * STORAGE_PER_SALE);
+    assert_eq!(contract.storage_balance_of(accounts(3)).0, 0);
+
+    let logs = near_sdk::test_utils::get_logs();
+    assert!(logs.iter().any(|log| log.contains("\"event\":\"stake_for\"")
+        && log.contains("\"sender_id\":\"charlie\"")
+        && log.contains("\"beneficiary_id\":\"danny\"")));
+}
+
+#[test]
+fn stake_for_reports_the_amount_after_the_deposit_fee() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.add_farm(1, token_id(), 18, 1, U128(0), U128(TOKEN_REWARD_RATE), U128(0), 0, 1000, 1_000_000, 1_000_000, 0, 100_000_000);
+    contract.set_farm_fees(1, Some(FeeConfig { reward_fee_bps: 0, stake_fee_bps: 100 }));
+    set_context(accounts(2), 0, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+
+    set_context(AccountId::new_unchecked(REF_CONTRACT_ID.to_string()), 1_000, 0);
+    contract.mft_on_transfer(
+        ":1".to_string(),
+        accounts(2),
+        U128(1000),
+        format!("{{\"stake_type\": 0, \"farm_id\": 1, \"lock_duration\": 0, \"beneficiary\": \"{}\"}}", accounts(3)),
+    );
+    assert_eq!(contract.get_positions(1, accounts(3))[0].amount.0, 990);
+    let logs = near_sdk::test_utils::get_logs();
+    assert!(logs.iter().any(|log| log.contains("\"event\":\"stake_for\"") && log.contains("\"amount\":\"990\"")));
+}
+
+#[test]
+#[should_panic(expected = "Insufficient storage deposit")]
+fn stake_for_a_beneficiary_needs_a_sender_storage_deposit() {
+    let mut contract = setup_token_farm();
+    stake_tokens_for(&mut contract, accounts(2), &accounts(3), 100 * ONE_TOKEN, 1_000);
+}
+
+#[test]
+fn claim_all_settles_every_farm_of_the_caller() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.add_farm(0, token_id(), 18, 0, U128(2 * TOKEN_REWARD_RATE), U128(0), U128(0), 1000, 0, 1_000_000, 1_000_000, 0, 100_000_000);
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    set_context(token_id(), 1_000, 0);
+    contract.ft_on_transfer(
+        accounts(1),
+        U128(100 * ONE_TOKEN),
+        "{\"stake_type\": 0, \"farm_id\": 1, \"lock_duration\": 0}".to_string(),
+    );
+
+    set_context(accounts(1), 11_000, 1);
+    let summaries = contract.claim_all(None, None).claims;
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[0].farm_id, 0);
+    assert_eq!(summaries[0].token_reward.0, 10 * TOKEN_REWARD_RATE);
+    assert_eq!(summaries[1].farm_id, 1);
+    assert_eq!(summaries[1].token_reward.0, 20 * TOKEN_REWARD_RATE);
+    assert_eq!(summaries[1].unlocked_at, 1_011_000);
+    assert_eq!(reward_vesting(&contract, &accounts(1)).1, 10 * TOKEN_REWARD_RATE);
+    assert_eq!(contract.staking_informations_by_owner_id(1, accounts(1)).reward_amount, 20 * TOKEN_REWARD_RATE);
+
+    // farms listed twice are claimed once, farms without a stake are skipped
+    set_context(accounts(2), 11_000, 1);
+    assert!(contract.claim_all(Some(vec![0, 1, 0]), None).claims.is_empty());
+}
+
+#[test]
+fn claim_all_pages_through_many_farms() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    for _ in 0..11 {
+        contract.add_farm(0, token_id(), 18, 0, U128(TOKEN_REWARD_RATE), U128(0), U128(0), 1000, 0, 1_000_000, 1_000_000, 0, 100_000_000);
+    }
+    for farm_id in 0..12 {
+        stake_in_farm(&mut contract, accounts(1), farm_id, 100 * ONE_TOKEN, 1_000);
+    }
+
+    set_context(accounts(1), 11_000, 1);
+    let page = contract.claim_all(None, None);
+    assert_eq!(page.claims.len(), 10);
+    assert_eq!(page.next_farm_id, Some(10));
+    let page = contract.claim_all(None, page.next_farm_id);
+    assert_eq!(page.claims.iter().map(|claim| claim.farm_id).collect::<Vec<_>>(), vec![10, 11]);
+    assert_eq!(page.next_farm_id, None);
+}
+
+#[test]
+#[should_panic(expected = "Too many farms to claim at once, pass at most 10 farm ids")]
+fn claim_all_is_bounded_for_explicit_farm_ids() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(1), 11_000, 1);
+    contract.claim_all(Some((0..11).collect()), None);
+}
+
+#[test]
+fn withdraw_matured_releases_only_unlocked_positions() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    set_context(token_id(), 1_000, 0);
+    contract.ft_on_transfer(
+        accounts(1),
+        U128(50 * ONE_TOKEN),
+        "{\"stake_type\": 0, \"farm_id\": 0, \"lock_duration\": 1000000}".to_string(),
+    );
+    set_context(accounts(1), 11_000, 1);
+    contract.claim_reward(0, 0);
+
+    set_context(accounts(1), 200_000, 1);
+    let withdrawal = contract.withdraw_matured(0);
+    assert_eq!(withdrawal.token_amount.0, 100 * ONE_TOKEN);
+    assert_eq!(withdrawal.lp_share_amount.0, 0);
+    assert_eq!(withdrawal.reward_amount.0, 0);
+    let positions = contract.get_positions(0, accounts(1));
+    assert_eq!(positions.len(), 2);
+    assert!(positions.iter().all(|position| position.unlocked_at > 200_000));
+    let stake_info = contract.staking_informations_by_owner_id(0, accounts(1));
+    assert_eq!(stake_info.token_amount, 50 * ONE_TOKEN);
+    assert_eq!(contract.farm_infos.get(0).unwrap().total_token_amount, 50 * ONE_TOKEN);
+
+    // nothing left to withdraw until the next lock ends
+    let withdrawal = contract.withdraw_matured(0);
+    assert_eq!(withdrawal.token_amount.0, 0);
+}
+
+#[test]
+fn withdraw_matured_refunds_storage_to_whoever_paid_it() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+
+    // the contract paid for the storage of a plain stake, nothing is credited for freeing it
+    set_context(accounts(1), 200_000, 1);
+    contract.withdraw_matured(0);
+    assert!(contract.get_positions(0, accounts(1)).is_empty());
+    assert_eq!(contract.storage_balance_of(accounts(1)).0, 0);
+
+    // a staker paying for a beneficiary gets the position's storage back when it is withdrawn
+    set_context(accounts(2), 200_000, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+    stake_tokens_for(&mut contract, accounts(2), &accounts(3), 100 * ONE_TOKEN, 200_000);
+    let first_balance = contract.storage_balance_of(accounts(2)).0;
+    stake_tokens_for(&mut contract, accounts(2), &accounts(3), 100 * ONE_TOKEN, 200_000);
+    let sender_balance = contract.storage_balance_of(accounts(2)).0;
+    let position_cost = first_balance - sender_balance;
+    set_context(accounts(3), 400_000, 1);
+    contract.withdraw_matured(0);
+    assert_eq!(contract.storage_balance_of(accounts(3)).0, 0);
+    assert!(contract.storage_balance_of(accounts(2)).0 >= sender_balance + 2 * position_cost);
+}
+
+fn setup_unbonding_farm() -> Contract {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_farm_unbonding_period(0, 50_000);
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    contract
+}
+
+#[test]
+fn request_unstake_queues_until_the_unbonding_period_passes() {
+    let mut contract = setup_unbonding_farm();
+    set_context(accounts(1), 0, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+
+    set_context(accounts(1), 101_000, 1);
+    let unbonding = contract.request_unstake(0, 0, U128(40 * ONE_TOKEN));
+    assert_eq!((unbonding.amount.0, unbonding.ready_at), (40 * ONE_TOKEN, 151_000));
+    assert_eq!(contract.farm_infos.get(0).unwrap().total_token_amount, 60 * ONE_TOKEN);
+    assert_eq!(contract.farm_infos.get(0).unwrap().unbonding_token_amount, 40 * ONE_TOKEN);
+    assert!(contract.storage_balance_of(accounts(1)).0 < 10 * STORAGE_PER_SALE);
+
+    set_context(accounts(1), 151_000, 1);
+    let withdrawal = contract.withdraw_unbonded(0);
+    assert_eq!(withdrawal.token_amount.0, 40 * ONE_TOKEN);
+    assert!(contract.get_unbondings(0, accounts(1)).is_empty());
+    assert_eq!(contract.farm_infos.get(0).unwrap().unbonding_token_amount, 0);
+    assert_eq!(contract.storage_balance_of(accounts(1)).0, 10 * STORAGE_PER_SALE);
+}
+
+#[test]
+#[should_panic(expected = "Nothing to withdraw")]
+fn withdraw_unbonded_waits_for_the_period() {
+    let mut contract = setup_unbonding_farm();
+    set_context(accounts(1), 0, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+    set_context(accounts(1), 101_000, 1);
+    contract.request_unstake(0, 0, U128(40 * ONE_TOKEN));
+    set_context(accounts(1), 150_000, 1);
+    contract.withdraw_unbonded(0);
+}
+
+#[test]
+#[should_panic(expected = "Insufficient storage deposit")]
+fn unstake_into_unbonding_charges_storage_like_request_unstake() {
+    let mut contract = setup_unbonding_farm();
+    set_context(accounts(1), 101_000, 1);
+    contract.unstake(0, 0, U128(40 * ONE_TOKEN));
+}
+
+/// farm 1 stakes the same token as farm 0 but ends at 50 s
+fn setup_ending_farm() -> Contract {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.add_farm(0, token_id(), 18, 0, U128(TOKEN_REWARD_RATE), U128(0), U128(0), 1000, 0, 1_000_000, 1_000_000, 0, 50_000);
+    contract
+}
+
+fn stake_in_farm(contract: &mut Contract, account_id: AccountId, farm_id: u16, amount: u128, timestamp: u64) {
+    set_context(token_id(), timestamp, 0);
+    contract.ft_on_transfer(
+        account_id,
+        U128(amount),
+        format!("{{\"stake_type\": 0, \"farm_id\": {}, \"lock_duration\": 100000}}", farm_id),
+    );
+}
+
+#[test]
+fn reward_on_reward_keeps_accruing_after_the_farm_ends() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.add_farm(0, token_id(), 18, 0, U128(TOKEN_REWARD_RATE), U128(0), U128(REWARD_REWARD_RATE), 1000, 0, 1_000_000, 1_000_000, 0, 50_000);
+    set_context(accounts(1), 0, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+    stake_in_farm(&mut contract, accounts(1), 1, 100 * ONE_TOKEN, 1_000);
+
+    set_context(accounts(1), 41_000, 1);
+    contract.claim_reward(1, 0);
+    // the token pool stops at 50_000, the reward locked at 41_000 still earns until 60_000
+    set_context(accounts(1), 60_000, 1);
+    contract.claim_reward(1, 0);
+    let stake_info = contract.staking_informations_by_owner_id(1, accounts(1));
+    assert_eq!(stake_info.reward_reward_to_claim, 19 * REWARD_REWARD_RATE);
+}
+
+#[test]
+fn reward_on_reward_view_matches_the_claim_after_the_farm_ends() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.add_farm(0, token_id(), 18, 0, U128(TOKEN_REWARD_RATE), U128(0), U128(REWARD_REWARD_RATE), 1000, 0, 1_000_000, 1_000_000, 0, 50_000);
+    set_context(accounts(1), 0, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+    stake_in_farm(&mut contract, accounts(1), 1, 100 * ONE_TOKEN, 1_000);
+    set_context(accounts(1), 41_000, 1);
+    contract.claim_reward(1, 0);
+
+    set_context(accounts(1), 60_000, 1);
+    let pending = contract.claim_amount(1, accounts(1), 2, 60_000);
+    assert_eq!(pending, 19 * REWARD_REWARD_RATE);
+    assert_eq!(contract.internal_claim_reward(1, &accounts(1), 2, 60_000), pending);
+}
+
+#[test]
+fn migrate_position_moves_the_stake_and_keeps_earned_rewards() {
+    let mut contract = setup_ending_farm();
+    set_context(accounts(1), 0, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+    stake_in_farm(&mut contract, accounts(1), 1, 100 * ONE_TOKEN, 1_000);
+    let position_id = contract.get_positions(1, accounts(1))[0].id;
+    let earned = contract.claim_amount(1, accounts(1), 0, 11_000);
+
+    set_context(accounts(1), 11_000, 1);
+    contract.migrate_position(1, 0, position_id);
+    assert_eq!(contract.get_position(position_id).unwrap().farm_id, 0);
+    assert_eq!(contract.get_positions(0, accounts(1))[0].unlocked_at, 101_000);
+    assert_eq!(contract.farm_infos.get(1).unwrap().total_token_amount, 0);
+    assert_eq!(contract.farm_infos.get(0).unwrap().total_token_amount, 100 * ONE_TOKEN);
+    assert_eq!(contract.claim_amount(1, accounts(1), 0, 21_000), earned);
+}
+
+#[test]
+fn rollover_moves_opted_in_stakers_and_opts_out_failing_ones() {
+    let mut contract = setup_ending_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_successor_farm(1, Some(0));
+    for (account_id, deposit) in [(accounts(1), 10 * STORAGE_PER_SALE), (accounts(2), STORAGE_PER_SALE)] {
+        set_context(account_id.clone(), 0, deposit);
+        contract.storage_deposit(None);
+        set_context(account_id.clone(), 0, 1);
+        contract.set_rollover(true);
+        stake_in_farm(&mut contract, account_id, 1, 100 * ONE_TOKEN, 1_000);
+    }
+    stake_in_farm(&mut contract, accounts(3), 1, 100 * ONE_TOKEN, 1_000);
+
+    set_context(accounts(3), 60_000, 0);
+    assert_eq!(contract.rollover_positions(1, 0, 10), 1);
+    assert_eq!(contract.get_positions(0, accounts(1)).len(), 1);
+    // too little storage left for a stake in the successor
+    assert!(!contract.get_rollover(accounts(2)));
+    assert_eq!(contract.get_positions(1, accounts(2)).len(), 1);
+    assert!(near_sdk::test_utils::get_logs().iter().any(|log| log.contains("charlie is opted out of rollovers")));
+    // never opted in
+    assert_eq!(contract.get_positions(1, accounts(3)).len(), 1);
+    assert_eq!(contract.farm_infos.get(0).unwrap().total_token_amount, 100 * ONE_TOKEN);
+}
+
+#[test]
+#[should_panic(expected = "Farm has not ended yet")]
+fn rollover_waits_for_the_farm_to_end() {
+    let mut contract = setup_ending_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_successor_farm(1, Some(0));
+    set_context(accounts(3), 40_000, 0);
+    contract.rollover_positions(1, 0, 10);
+}
+
+#[test]
+fn reward_on_reward_does_not_mutate_locked_rewards() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+
+    set_context(accounts(1), 11_000, 1);
+    contract.claim_reward(0, 0);
+    let (reward_positions, reward_amount) = reward_vesting(&contract, &accounts(1));
+    assert_eq!(reward_amount, 10 * TOKEN_REWARD_RATE);
+    let total_reward_amount = contract.farm_infos.get(0).unwrap().total_reward_amount;
+
+    set_context(accounts(1), 21_000, 1);
+    assert_eq!(
+        contract.get_reward_claim_amount(0, accounts(1)).0,
+        10 * REWARD_REWARD_RATE
+    );
+    contract.claim_reward(0, 2);
+
+    // the claimed reward-on-reward vests in a position of its own
+    let (positions, amount) = reward_vesting(&contract, &accounts(1));
+    assert_eq!(positions[..reward_positions.len()], reward_positions[..]);
+    assert_eq!(positions[reward_positions.len()..], [(2, 10 * REWARD_REWARD_RATE, 1_021_000)]);
+    assert_eq!(amount, reward_amount + 10 * REWARD_REWARD_RATE);
+    assert_eq!(
+        contract.farm_infos.get(0).unwrap().total_reward_amount,
+        total_reward_amount + 10 * REWARD_REWARD_RATE
+    );
+    assert_eq!(contract.get_reward_claim_amount(0, accounts(1)).0, 0);
+}
+
+#[test]
+fn reward_on_reward_accrues_apart_until_claimed() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+
+    set_context(accounts(1), 11_000, 1);
+    contract.claim_reward(0, 0);
+    let (first_positions, _) = reward_vesting(&contract, &accounts(1));
+
+    // claiming the token pool settles reward-on-reward without touching older entries
+    set_context(accounts(1), 21_000, 1);
+    contract.claim_reward(0, 0);
+    let (reward_positions, reward_amount) = reward_vesting(&contract, &accounts(1));
+    assert_eq!(reward_positions[..first_positions.len()], first_positions[..]);
+    assert_eq!(
+        reward_amount,
+        reward_positions.iter().map(|position| position.1).sum::<u128>()
+    );
+    let stake_info = contract.staking_informations_by_owner_id(0, accounts(1));
+    assert_eq!(stake_info.reward_reward_to_claim, 10 * REWARD_REWARD_RATE);
+
+    set_context(accounts(1), 21_000, 1);
+    contract.claim_reward(0, 2);
+    let stake_info = contract.staking_informations_by_owner_id(0, accounts(1));
+    assert_eq!(stake_info.reward_reward_to_claim, 0);
+    assert_eq!(stake_info.reward_amount, reward_amount + 10 * REWARD_REWARD_RATE);
+    let (positions, amount) = reward_vesting(&contract, &accounts(1));
+    assert_eq!(positions[..reward_positions.len()], reward_positions[..]);
+    assert_eq!(amount, positions.iter().map(|position| position.1).sum::<u128>());
+    assert_eq!(stake_info.reward_amount, amount);
+}
+
+fn boost_nft_id() -> AccountId {
+    AccountId::new_unchecked("boost-nft.testnet".to_string())
+}
+
+fn deposit_boost_nft(contract: &mut Contract, owner_id: AccountId, token_id: &str, timestamp: u64) {
+    set_context(boost_nft_id(), timestamp, 0);
+    contract.nft_on_transfer(owner_id.clone(), owner_id, token_id.to_string(), "{\"farm_id\": 0}".to_string());
+}
+
+#[test]
+fn nft_boost_needs_a_stake_in_the_farm() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_nft_boost_collection(boost_nft_id(), 20000);
+    set_context(boost_nft_id(), 1_000, 0);
+    let returned = contract.nft_on_transfer(accounts(1), accounts(1), "7".to_string(), "{\"farm_id\": 0}".to_string());
+    assert!(matches!(returned, PromiseOrValue::Value(true)));
+    assert!(contract.farm_infos.get(0).unwrap().stake_infos.get(&accounts(1)).is_none());
+}
+
+#[test]
+fn nft_boost_scales_weights_until_withdrawn() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_nft_boost_collection(boost_nft_id(), 20000);
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    stake_tokens(&mut contract, accounts(2), 100 * ONE_TOKEN, 1_000);
+    let weight = contract.staking_informations_by_owner_id(0, accounts(1)).token_weight;
+    let total_weight = contract.farm_infos.get(0).unwrap().total_token_weight;
+    let pending = contract.claim_amount(0, accounts(1), 0, 11_000);
+
+    deposit_boost_nft(&mut contract, accounts(1), "7", 11_000);
+    let stake_info = contract.staking_informations_by_owner_id(0, accounts(1));
+    // rewards up to the deposit are settled at the unboosted weight
+    assert_eq!(stake_info.reward_token_to_claim, pending);
+    assert_eq!(stake_info.token_weight, 2 * weight);
+    assert_eq!(contract.farm_infos.get(0).unwrap().total_token_weight, total_weight + weight);
+    assert_eq!(contract.get_nft_boost(0, accounts(1)).unwrap().token_id, "7");
+
+    set_context(accounts(1), 21_000, 1);
+    contract.nft_withdraw(0);
+    assert_eq!(contract.staking_informations_by_owner_id(0, accounts(1)).token_weight, weight);
+    assert_eq!(contract.farm_infos.get(0).unwrap().total_token_weight, total_weight);
+    assert!(contract.get_nft_boost(0, accounts(1)).is_none());
+}
+
+#[test]
+fn failed_nft_withdraw_restores_the_boost() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_nft_boost_collection(boost_nft_id(), 15000);
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    let weight = contract.staking_informations_by_owner_id(0, accounts(1)).token_weight;
+    deposit_boost_nft(&mut contract, accounts(1), "7", 1_000);
+
+    set_context(accounts(1), 11_000, 1);
+    contract.nft_withdraw(0);
+    set_callback_context(11_000, PromiseResult::Failed);
+    assert!(!contract.on_nft_withdraw(0, accounts(1), boost_nft_id(), "7".to_string(), 15000));
+
+    let nft_boost = contract.get_nft_boost(0, accounts(1)).unwrap();
+    assert_eq!((nft_boost.token_id.as_str(), nft_boost.multiplier), ("7", 15000));
+    assert_eq!(contract.staking_informations_by_owner_id(0, accounts(1)).token_weight, weight * 3 / 2);
+
+    // a successful transfer leaves the boost removed
+    set_context(accounts(1), 21_000, 1);
+    contract.nft_withdraw(0);
+    set_callback_context(21_000, PromiseResult::Successful(vec![]));
+    assert!(contract.on_nft_withdraw(0, accounts(1), boost_nft_id(), "7".to_string(), 15000));
+    assert!(contract.get_nft_boost(0, accounts(1)).is_none());
+}
+
+#[test]
+#[should_panic(expected = "NFT collection is not allowed to boost")]
+fn nft_boost_needs_an_allowed_collection() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    deposit_boost_nft(&mut contract, accounts(1), "7", 1_000);
+}
+
+fn lock_id_of(contract: &Contract, account_id: AccountId) -> String {
+    contract.get_positions(0, account_id)[0].id.to_string()
+}
+
+#[test]
+fn lock_token_moves_the_position_with_its_pending_rewards() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(1), 0, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    stake_tokens(&mut contract, accounts(2), 100 * ONE_TOKEN, 1_000);
+    let token_id = lock_id_of(&contract, accounts(1));
+    let token = contract.nft_token(token_id.clone()).unwrap();
+    assert_eq!(token.owner_id, accounts(1));
+    assert_eq!(contract.nft_total_supply().0, 2);
+
+    let weight = contract.staking_informations_by_owner_id(0, accounts(1)).token_weight;
+    let pending = contract.claim_amount(0, accounts(1), 0, 11_000);
+    let later_pending = contract.claim_amount(0, accounts(1), 0, 21_000);
+    let other_pending = contract.claim_amount(0, accounts(2), 0, 21_000);
+    set_context(accounts(1), 11_000, 1);
+    contract.nft_transfer(accounts(3), token_id.clone(), None, None);
+
+    assert_eq!(contract.nft_token(token_id).unwrap().owner_id, accounts(3));
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 0);
+    let sender_info = contract.staking_informations_by_owner_id(0, accounts(1));
+    assert_eq!((sender_info.token_weight, sender_info.reward_token_to_claim), (0, 0));
+    let receiver_info = contract.staking_informations_by_owner_id(0, accounts(3));
+    assert_eq!((receiver_info.token_weight, receiver_info.reward_token_to_claim), (weight, pending));
+    // the receiver accrues what the sender would have, the other staker is untouched
+    assert_eq!(contract.claim_amount(0, accounts(3), 0, 21_000), later_pending);
+    assert_eq!(contract.claim_amount(0, accounts(2), 0, 21_000), other_pending);
+}
+
+#[test]
+fn refused_lock_token_goes_back_to_the_sender() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(1), 0, 10 * STORAGE_PER_SALE);
+    contract.storage_deposit(None);
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    let token_id = lock_id_of(&contract, accounts(1));
+    let pending = contract.claim_amount(0, accounts(1), 0, 11_000);
+
+    set_context(accounts(1), 11_000, 1);
+    contract.nft_transfer_call(accounts(3), token_id.clone(), None, None, String::new());
+    assert_eq!(contract.nft_token(token_id.clone()).unwrap().owner_id, accounts(3));
+
+    set_callback_context(11_000, PromiseResult::Successful(b"true".to_vec()));
+    assert!(!contract.nft_resolve_transfer(accounts(1), accounts(3), token_id.clone()));
+    assert_eq!(contract.nft_token(token_id).unwrap().owner_id, accounts(1));
+    let stake_info = contract.staking_informations_by_owner_id(0, accounts(1));
+    assert_eq!(stake_info.reward_token_to_claim, pending);
+    assert_eq!(contract.staking_informations_by_owner_id(0, accounts(3)).token_weight, 0);
+}
+
+#[test]
+#[should_panic(expected = "Sender is not the token owner")]
+fn lock_token_is_transferred_only_by_its_owner() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    let token_id = lock_id_of(&contract, accounts(1));
+    set_context(accounts(2), 11_000, 1);
+    contract.nft_transfer(accounts(2), token_id, None, None);
+}
+
+#[test]
+fn halving_emission_integrates_across_halvings() {
+    let schedule = EmissionSchedule::Halving { period: 10_000 };
+    assert_eq!(schedule.emitted(1000, 0, 0, 25_000), 10 * 1000 + 10 * 500 + 5 * 250);
+    assert_eq!(
+        schedule.emitted(1000, 0, 0, 13_000) + schedule.emitted(1000, 0, 13_000, 25_000),
+        schedule.emitted(1000, 0, 0, 25_000)
+    );
+    assert_eq!(schedule.rate_at(1000, 0, 25_000), 250);
+}
+
+#[test]
+fn halving_rate_matches_the_emission_of_each_second() {
+    let schedule = EmissionSchedule::Halving { period: 10_000 };
+    // a farm starting mid-second halves on the same second boundary in both
+    for second in [9_000, 10_000, 19_000, 20_000, 29_000] {
+        assert_eq!(
+            schedule.rate_at(ONE_TOKEN, 500, second + 400),
+            schedule.emitted(ONE_TOKEN, 500, second, second + 1_000),
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "Halving period must be a whole number of seconds")]
+fn halving_period_is_whole_seconds() {
+    EmissionSchedule::Halving { period: 1_500 }.assert_valid();
+}
+
+#[test]
+fn piecewise_emission_integrates_between_points() {
+    let schedule = EmissionSchedule::Piecewise {
+        points: vec![
+            EmissionPoint { timestamp: 10_000, rate_multiplier: 10000 },
+            EmissionPoint { timestamp: 20_000, rate_multiplier: 0 },
+        ],
+    };
+    // flat before the first point, a triangle between the points, nothing after the last one
+    assert_eq!(schedule.emitted(1000, 0, 0, 10_000), 10 * 1000);
+    assert_eq!(schedule.emitted(1000, 0, 10_000, 20_000), 5000);
+    assert_eq!(
+        schedule.emitted(1000, 0, 10_000, 14_000) + schedule.emitted(1000, 0, 14_000, 20_000),
+        5000
+    );
+    assert_eq!(schedule.emitted(1000, 0, 20_000, 30_000), 0);
+    assert_eq!(schedule.rate_at(1000, 0, 14_000), 600);
+}
+
+#[test]
+fn halving_farm_accrues_the_scheduled_emission() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_emission_schedule(0, EmissionSchedule::Halving { period: 10_000 });
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+
+    set_context(accounts(1), 21_000, 1);
+    contract.claim_reward(0, 0);
+    let (_, reward_amount) = reward_vesting(&contract, &accounts(1));
+    assert_eq!(reward_amount, 9 * TOKEN_REWARD_RATE + 5 * TOKEN_REWARD_RATE + TOKEN_REWARD_RATE / 4);
+}
+
+/// farm 1 starts at 10 s and pays 10 tokens in each of three 10 s epochs
+fn setup_epoch_farm() -> Contract {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.add_farm(0, token_id(), 18, 0, U128(0), U128(0), U128(0), 1000, 0, 1_000_000, 1_000_000, 10_000, 20_000);
+    contract.set_farm_epochs(1, 10_000, U128(10 * ONE_TOKEN), U128(0), Some(3));
+    contract
+}
+
+#[test]
+fn epoch_farm_pays_each_epoch_its_amount() {
+    let mut contract = setup_epoch_farm();
+    let farm_info = contract.farm_infos.get(1).unwrap();
+    assert_eq!((farm_info.token_reward_rate, farm_info.ending_at), (ONE_TOKEN, 40_000));
+    stake_in_farm(&mut contract, accounts(1), 1, 100 * ONE_TOKEN, 10_000);
+
+    assert_eq!(contract.claim_amount(1, accounts(1), 0, 20_000), 10 * ONE_TOKEN);
+    assert_eq!(contract.claim_amount(1, accounts(1), 0, 50_000), 30 * ONE_TOKEN);
+    set_context(accounts(1), 25_000, 0);
+    let epochs = contract.get_farm_epochs(1).unwrap();
+    assert_eq!((epochs.current_epoch, epochs.current_epoch_ends_at, epochs.remaining_epochs), (Some(1), Some(30_000), Some(1)));
+}
+
+#[test]
+fn cancelled_epoch_farm_ends_with_the_running_epoch() {
+    let mut contract = setup_epoch_farm();
+    set_context(accounts(0), 15_000, 1);
+    contract.cancel_farm_epochs(1);
+    assert_eq!(contract.farm_infos.get(1).unwrap().ending_at, 20_000);
+    assert_eq!(contract.get_farm_epochs(1).unwrap().total_epochs, Some(1));
+}
+
+#[test]
+#[should_panic(expected = "Rewards per epoch must split evenly over its 3 seconds")]
+fn epoch_rewards_must_split_evenly() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.add_farm(0, token_id(), 18, 0, U128(0), U128(0), U128(0), 1000, 0, 1_000_000, 1_000_000, 10_000, 20_000);
+    contract.set_farm_epochs(1, 3_000, U128(10 * ONE_TOKEN), U128(0), None);
+}
+
+#[test]
+fn epoch_schedule_saturates_far_in_the_future() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.add_farm(0, token_id(), 18, 0, U128(0), U128(0), U128(0), 1000, 0, 1_000_000, 1_000_000, 10_000, 20_000);
+    contract.set_farm_epochs(1, 1_000_000_000_000, U128(0), U128(0), None);
+    let schedule = contract.get_epoch_schedule(1, u32::MAX - 1, 10);
+    assert_eq!(schedule.len(), 1);
+    assert_eq!((schedule[0].starts_at, schedule[0].ends_at), (u64::MAX, u64::MAX));
+}
+
+#[test]
+fn stake_over_the_farm_cap_is_partially_accepted() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_farm_limits(0, 0, Some(U128(150 * ONE_TOKEN)), None, U128(ONE_TOKEN));
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+
+    set_context(token_id(), 2_000, 0);
+    let unused = contract.ft_on_transfer(
+        accounts(2),
+        U128(100 * ONE_TOKEN),
+        "{\"stake_type\": 0, \"farm_id\": 0, \"lock_duration\": 100000}".to_string(),
+    );
+    match unused {
+        PromiseOrValue::Value(unused) => assert_eq!(unused.0, 50 * ONE_TOKEN),
+        PromiseOrValue::Promise(_) => panic!("expected the unused amount"),
+    }
+    let capacity = contract.get_farm_capacity(0, Some(accounts(2)));
+    assert_eq!(capacity.token.remaining_total.unwrap().0, 0);
+    assert_eq!(
+        contract.staking_informations_by_owner_id(0, accounts(2)).token_amount,
+        50 * ONE_TOKEN
+    );
+}
+
+#[test]
+fn merkle_farm_accepts_only_proven_accounts() {
+    let mut contract = setup_token_farm();
+    let leaf = |account_id: AccountId| env::sha256(account_id.as_bytes());
+    let (left, right) = (leaf(accounts(1)), leaf(accounts(2)));
+    let root = if left <= right {
+        env::sha256(&[left.clone(), right.clone()].concat())
+    } else {
+        env::sha256(&[right.clone(), left.clone()].concat())
+    };
+    let hex = |hash: Vec<u8>| hash.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+    set_context(accounts(0), 0, 1);
+    contract.set_farm_access(0, FarmAccessView::Merkle { root: hex(root) });
+
+    assert!(contract.is_eligible(0, accounts(1), Some(vec![hex(right)])));
+    assert!(!contract.is_eligible(0, accounts(3), Some(vec![hex(left)])));
+    assert!(!contract.is_eligible(0, accounts(1), None));
+
+    set_context(token_id(), 1_000, 0);
+    let refunded = contract.ft_on_transfer(
+        accounts(3),
+        U128(ONE_TOKEN),
+        "{\"stake_type\": 0, \"farm_id\": 0, \"lock_duration\": 100000}".to_string(),
+    );
+    match refunded {
+        PromiseOrValue::Value(refunded) => assert_eq!(refunded.0, ONE_TOKEN),
+        PromiseOrValue::Promise(_) => panic!("expected the refunded amount"),
+    }
+}
+
+#[test]
+fn allowlist_farm_accepts_listed_accounts_until_removed() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_farm_access(0, FarmAccessView::Allowlist);
+    contract.add_to_farm_allowlist(0, vec![accounts(1), accounts(2)]);
+    assert_eq!(contract.get_farm_allowlist(0, 0, 10), vec![accounts(1), accounts(2)]);
+    assert!(contract.is_eligible(0, accounts(1), None));
+
+    set_context(accounts(0), 0, 1);
+    contract.remove_from_farm_allowlist(0, vec![accounts(2)]);
+    assert_eq!(contract.get_farm_allowlist(0, 0, 10), vec![accounts(1)]);
+    stake_tokens(&mut contract, accounts(1), ONE_TOKEN, 1_000);
+    assert_eq!(contract.farm_infos.get(0).unwrap().total_token_amount, ONE_TOKEN);
+    stake_tokens(&mut contract, accounts(2), ONE_TOKEN, 1_000);
+    assert_eq!(contract.farm_infos.get(0).unwrap().total_token_amount, ONE_TOKEN);
+}
+
+#[test]
+#[should_panic(expected = "Receiver is not eligible for this Farm")]
+fn lock_token_receiver_must_be_eligible() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_farm_access(0, FarmAccessView::Allowlist);
+    contract.add_to_farm_allowlist(0, vec![accounts(1)]);
+    stake_tokens(&mut contract, accounts(1), ONE_TOKEN, 1_000);
+    let token_id = lock_id_of(&contract, accounts(1));
+    set_context(accounts(1), 11_000, 1);
+    contract.nft_transfer(accounts(3), token_id, None, None);
+}
+
+#[test]
+#[should_panic(expected = "Contract is paused")]
+fn lock_tokens_cannot_move_while_paused() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), ONE_TOKEN, 1_000);
+    let token_id = lock_id_of(&contract, accounts(1));
+    set_context(accounts(0), 2_000, 1);
+    contract.pause();
+    set_context(accounts(1), 11_000, 1);
+    contract.nft_transfer(accounts(3), token_id, None, None);
+}
+
+#[test]
+#[should_panic(expected = "Position does not fit under the receiver's cap")]
+fn lock_token_respects_the_receiver_cap() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    stake_tokens(&mut contract, accounts(3), 100 * ONE_TOKEN, 1_000);
+    set_context(accounts(0), 1_000, 1);
+    contract.set_farm_limits(0, 0, None, Some(U128(150 * ONE_TOKEN)), U128(0));
+    let token_id = lock_id_of(&contract, accounts(1));
+    set_context(accounts(1), 11_000, 1);
+    contract.nft_transfer(accounts(3), token_id, None, None);
+}
+
+#[test]
+fn fee_amount_rounds_up_for_the_protocol() {
+    assert_eq!(fee_amount(0, 30), 0);
+    assert_eq!(fee_amount(1, 1), 1);
+    assert_eq!(fee_amount(10_000, 30), 30);
+    assert_eq!(fee_amount(10_001, 30), 31);
+    assert_eq!(fee_amount(333, 30), 1);
+    assert_eq!(fee_amount(u128::MAX, 0), 0);
+    assert_eq!(fee_amount(u128::MAX / 2, 10000), u128::MAX / 2);
+}
+
+#[test]
+fn deposit_and_reward_fees_go_to_the_treasury() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_farm_fees(0, Some(FeeConfig { reward_fee_bps: 30, stake_fee_bps: 100 }));
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN + 1, 1_000);
+    let stake_info = contract.staking_informations_by_owner_id(0, accounts(1));
+    // 1% of 100 tokens and 1 yocto, rounded up
+    assert_eq!(stake_info.token_amount, 99 * ONE_TOKEN);
+
+    set_context(accounts(1), 11_000, 1);
+    contract.claim_reward(0, 0);
+    let (_, reward_amount) = reward_vesting(&contract, &accounts(1));
+    let reward_fee = fee_amount(10 * TOKEN_REWARD_RATE, 30);
+    assert_eq!(reward_amount, 10 * TOKEN_REWARD_RATE - reward_fee);
+
+    let balances = contract.get_treasury_balances();
+    assert_eq!(balances.len(), 1);
+    assert_eq!(balances[0].token_id, token_id().to_string());
+    assert_eq!(balances[0].amount.0, ONE_TOKEN + 1 + reward_fee);
+}
+
+#[test]
+#[should_panic(expected = "Requires the TreasuryManager role")]
+fn fees_need_the_treasury_manager_role() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.grant_role(Role::TreasuryManager, accounts(1));
+    assert_eq!(contract.get_role_holders(Role::TreasuryManager), vec![accounts(1)]);
+    set_context(accounts(1), 0, 1);
+    contract.set_default_fees(FeeConfig { reward_fee_bps: 10, stake_fee_bps: 0 });
+
+    set_context(accounts(0), 0, 1);
+    contract.revoke_role(Role::TreasuryManager, accounts(1));
+    set_context(accounts(1), 0, 1);
+    contract.set_default_fees(FeeConfig::default());
+}
+
+#[test]
+#[should_panic(expected = "Ownership was renounced")]
+fn ownership_moves_in_two_steps_and_can_be_renounced() {
+    let mut contract = setup_token_farm();
+    contract.propose_owner(accounts(1));
+    assert_eq!(contract.get_owner(), Some(accounts(0)));
+
+    set_context(accounts(1), 0, 1);
+    contract.accept_ownership();
+    assert_eq!(contract.get_owner(), Some(accounts(1)));
+    assert_eq!(contract.get_proposed_owner(), None);
+    assert!(!contract.account_has_role(Role::FarmManager, accounts(0)));
+
+    contract.renounce_ownership();
+    assert_eq!(contract.get_owner(), None);
+    assert!(!contract.account_has_role(Role::FarmManager, accounts(1)));
+    contract.grant_role(Role::Pauser, accounts(1));
+}
+
+#[test]
+fn timelocked_farm_is_added_after_the_delay() {
+    let mut contract = setup_token_farm();
+    contract.set_timelock_delay(1000);
+    let id = contract.queue_operation(TimelockAction::RemoveSwapFarm { token_id: token_id() });
+    contract.cancel_operation(id);
+    let id = contract.queue_operation(TimelockAction::AddFarm {
+        farm_type: 0,
+        token_id: token_id(),
+        token_decimal: 18,
+        pool_id: 0,
+        token_reward_rate: U128(TOKEN_REWARD_RATE),
+        pool_reward_rate: U128(0),
+        reward_reward_rate: U128(0),
+        token_weight_rate: 1000,
+        pool_weight_rate: 0,
+        max_token_vesting_duration: 0,
+        max_reward_vesting_duration: 0,
+        starting_at: 0,
+        ending_at: 100_000,
+    });
+    let operations = contract.get_queued_operations(0, 10);
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0].eta, 1000);
+
+    set_context(accounts(0), 1000, 1);
+    contract.execute_operation(id);
+    assert_eq!(contract.farm_infos.len(), 2);
+    assert!(contract.get_queued_operations(0, 10).is_empty());
+}
+
+#[test]
+#[should_panic(expected = "Operation is not ready before 1000")]
+fn timelocked_operation_waits_for_its_eta() {
+    let mut contract = setup_token_farm();
+    contract.set_timelock_delay(1000);
+    let id = contract.queue_operation(TimelockAction::SetTimelockDelay { delay: 0 });
+    set_context(accounts(0), 999, 1);
+    contract.execute_operation(id);
+}
+
+#[test]
+fn timelocked_config_changes_apply_at_their_eta() {
+    let mut contract = setup_token_farm();
+    contract.set_timelock_delay(1000);
+    let limits_id = contract.queue_operation(TimelockAction::SetFarmLimits {
+        farm_id: 0,
+        token_type: 0,
+        max_total_amount: Some(U128(500 * ONE_TOKEN)),
+        max_account_amount: None,
+        min_stake_amount: U128(0),
+    });
+    let fees = FeeConfig { reward_fee_bps: 100, stake_fee_bps: 0 };
+    let fees_id = contract.queue_operation(TimelockAction::SetFarmFees { farm_id: 0, fees: Some(fees) });
+    assert!(contract.farm_infos.get(0).unwrap().token_limits.max_total_amount.is_none());
+
+    set_context(accounts(0), 1000, 1);
+    contract.execute_operation(limits_id);
+    contract.execute_operation(fees_id);
+    let farm_info = contract.farm_infos.get(0).unwrap();
+    assert_eq!(farm_info.token_limits.max_total_amount, Some(500 * ONE_TOKEN));
+    assert_eq!(contract.get_farm_fees(0), fees);
+}
+
+#[test]
+fn timelocked_unbonding_and_boost_changes_apply_at_their_eta() {
+    let mut contract = setup_token_farm();
+    contract.set_timelock_delay(1000);
+    let unbonding_id = contract.queue_operation(TimelockAction::SetFarmUnbondingPeriod {
+        farm_id: 0,
+        unbonding_period: 50_000,
+    });
+    let boost_id = contract.queue_operation(TimelockAction::SetNftBoostCollection {
+        nft_contract_id: accounts(3),
+        multiplier: 15_000,
+    });
+    assert_eq!(contract.farm_infos.get(0).unwrap().unbonding_period, 0);
+    assert!(contract.nft_boost_collections.get(&accounts(3)).is_none());
+
+    set_context(accounts(0), 1000, 1);
+    contract.execute_operation(unbonding_id);
+    contract.execute_operation(boost_id);
+    assert_eq!(contract.farm_infos.get(0).unwrap().unbonding_period, 50_000);
+    assert_eq!(contract.nft_boost_collections.get(&accounts(3)), Some(15_000));
+}
+
+#[test]
+#[should_panic(expected = "Timelocked, use queue_operation")]
+fn unbonding_period_is_timelocked() {
+    let mut contract = setup_token_farm();
+    contract.set_timelock_delay(1000);
+    contract.set_farm_unbonding_period(0, 50_000);
+}
+
+#[test]
+#[should_panic(expected = "Unbonding period must be at most 2592000000")]
+fn unbonding_period_is_capped() {
+    let mut contract = setup_token_farm();
+    contract.set_farm_unbonding_period(0, MAX_UNBONDING_PERIOD + 1);
+}
+
+#[test]
+#[should_panic(expected = "Timelocked, use queue_operation")]
+fn config_setters_are_timelocked() {
+    let mut contract = setup_token_farm();
+    contract.set_timelock_delay(1000);
+    contract.set_emission_schedule(0, EmissionSchedule::Halving { period: 10_000 });
+}
+
+#[test]
+#[should_panic(expected = "Owner's method")]
+fn farm_managers_cannot_add_farms_directly() {
+    let mut contract = setup_token_farm();
+    contract.grant_role(Role::FarmManager, accounts(1));
+    set_context(accounts(1), 0, 1);
+    contract.add_farm(0, token_id(), 18, 0, U128(TOKEN_REWARD_RATE), U128(0), U128(0), 1000, 0, 1_000_000, 1_000_000, 0, 50_000);
+}
+
+#[test]
+fn dao_owner_creates_farms_without_holder_confirmations() {
+    let mut contract = setup_token_farm();
+    contract.grant_role(Role::FarmManager, accounts(1));
+    contract.grant_role(Role::FarmManager, accounts(2));
+    let save = |contract: &mut Contract| {
+        contract.save_editing_farm_info(
+            0,
+            AccountId::new_unchecked("other.testnet".to_string()),
+            18,
+            0,
+            U128(TOKEN_REWARD_RATE),
+            U128(0),
+            U128(0),
+            1000,
+            0,
+            0,
+            0,
+            0,
+            100_000,
+        )
+    };
+    save(&mut contract);
+    assert_eq!(contract.farm_infos.len(), 1);
+
+    contract.set_dao_mode(true);
+    save(&mut contract);
+    assert_eq!(contract.farm_infos.len(), 2);
+    assert!(contract.get_confirmed_admins_for_new_farm().is_empty());
+}
+
+#[test]
+fn rescue_is_limited_to_the_balance_above_liabilities() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 10 * ONE_TOKEN, 0);
+    // 100_000 seconds of token and reward-on-reward emission plus the stake
+    let budget = 100_000 * (TOKEN_REWARD_RATE + REWARD_REWARD_RATE);
+    assert_eq!(contract.get_token_liabilities(token_id().to_string()).0, 10 * ONE_TOKEN + budget);
+
+    set_context(accounts(1), 10_000, 1);
+    contract.claim_reward(0, 0);
+    assert_eq!(contract.get_token_liabilities(token_id().to_string()).0, 10 * ONE_TOKEN + budget);
+
+    set_context(AccountId::new_unchecked("staking.testnet".to_string()), 10_000, 0);
+    let balance = U128(10 * ONE_TOKEN + budget + 5);
+    let rescued = contract.on_rescue_balance(token_id().to_string(), accounts(0), U128(5), balance);
+    assert_eq!(rescued.0, 5);
+}
+
+#[test]
+#[should_panic(expected = "Only 5 is above the tracked liabilities")]
+fn rescue_cannot_touch_staked_tokens() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 10 * ONE_TOKEN, 0);
+    let liabilities = contract.get_token_liabilities(token_id().to_string()).0;
+    set_context(AccountId::new_unchecked("staking.testnet".to_string()), 0, 0);
+    contract.on_rescue_balance(token_id().to_string(), accounts(0), U128(6), U128(liabilities + 5));
+}
+
+#[test]
+fn solvency_deficit_pauses_the_contract() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 10 * ONE_TOKEN, 0);
+    set_context(AccountId::new_unchecked("staking.testnet".to_string()), 0, 0);
+
+    let report = contract.on_solvency_balance(token_id().to_string(), true, U128(10 * ONE_TOKEN));
+    assert_eq!(report.deficit.0, 0);
+    assert_eq!(report.farms[0].staked.0, 10 * ONE_TOKEN);
+    assert!(!contract.is_paused());
+
+    let report = contract.on_solvency_balance(token_id().to_string(), false, U128(10 * ONE_TOKEN - 1));
+    assert_eq!(report.deficit.0, 1);
+    assert!(!report.paused);
+
+    let report = contract.on_solvency_balance(token_id().to_string(), true, U128(10 * ONE_TOKEN - 1));
+    assert_eq!(report.deficit.0, 1);
+    assert!(report.paused);
+    assert!(contract.is_paused());
+}
+
+#[test]
+fn swap_stakes_are_counted_in_unet_liabilities() {
+    let mut contract = setup_token_farm();
+    let usdc = accounts(3);
+    set_context(accounts(0), 0, 1);
+    contract.save_swap_farm(usdc.clone(), U128(100_000_000), 18, 0, 10_000);
+    set_context(usdc.clone(), 0, 0);
+    contract.ft_on_transfer(
+        accounts(1),
+        U128(1_000),
+        "{\"stake_type\": 1, \"farm_id\": 0, \"lock_duration\": 0}".to_string(),
+    );
+    assert_eq!(contract.internal_liabilities(token_id().as_str()).swap_owed, 1_000);
+
+    set_context(accounts(1), 10_000, 1);
+    contract.unstake_swap(usdc, accounts(1), 0, U128(400));
+    assert_eq!(contract.internal_liabilities(token_id().as_str()).swap_owed, 600);
+}
+
+#[test]
+#[should_panic(expected = "Only LP shares of the Ref exchange can be staked")]
+fn lp_shares_are_only_accepted_from_the_ref_exchange() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(2), 0, 0);
+    contract.mft_on_transfer(
+        ":1".to_string(),
+        accounts(1),
+        U128(1_000),
+        "{\"stake_type\": 0, \"farm_id\": 0, \"lock_duration\": 0}".to_string(),
+    );
+}
+
+#[test]
+fn small_stake_in_a_large_farm_earns_rewards() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 1_000_000_000_000 * ONE_TOKEN, 0);
+    stake_tokens(&mut contract, accounts(2), ONE_TOKEN, 0);
+    assert!(contract.claim_amount(0, accounts(2), 0, 10_000) > 0);
+}
+
+proptest! {
+    #[test]
+    fn shares_never_pay_out_more_than_emitted(
+        weights in prop::collection::vec(1..u128::MAX / 1024, 1..20),
+        emitted in 0..10u128.pow(36),
+    ) {
+        let total_weight: u128 = weights.iter().sum();
+        let paid: u128 = weights.iter().map(|weight| reward_for(share_of(*weight, total_weight), emitted)).sum();
+        prop_assert!(paid <= emitted);
+        // every staker loses less than `emitted / PRECISION + 1` to rounding
+        prop_assert!(emitted - paid <= weights.len() as u128 * (emitted / PRECISION + 1));
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(32))]
+    #[test]
+    fn stakers_never_claim_more_than_the_farm_emitted(
+        stakes in prop::collection::vec((1..10u128.pow(30), 0..50_000u64), 1..8),
+        claimed_at in 50_000..200_000u64,
+    ) {
+        let mut contract = setup_token_farm();
+        let mut stakes = stakes;
+        stakes.sort_by_key(|(_, staked_at)| *staked_at);
+        let stakers: Vec<AccountId> = (0..stakes.len())
+            .map(|index| AccountId::new_unchecked(format!("staker{}.testnet", index)))
+            .collect();
+        for ((amount, staked_at), account_id) in stakes.iter().zip(&stakers) {
+            stake_tokens(&mut contract, account_id.clone(), *amount, *staked_at);
+        }
+
+        let claimed: u128 = stakers
+            .iter()
+            .map(|account_id| contract.claim_amount(0, account_id.clone(), 0, claimed_at))
+            .sum();
+        let ending_at = min(claimed_at, 100_000_000);
+        let emitted = TOKEN_REWARD_RATE * u128::from(ending_at / 1000 - stakes[0].1 / 1000);
+        prop_assert!(claimed <= emitted);
+    }
+}
+
+fn constant_emission(rate: u128) -> PoolEmission {
+    PoolEmission {
+        schedule: EmissionSchedule::Constant,
+        rate,
+        starting_at: 10_000,
+        ending_at: 110_000,
+    }
+}
+
+#[test]
+fn settle_time_is_clamped_to_the_farm_lifetime() {
+    let emission = constant_emission(1);
+    assert_eq!(emission.settle_time(9_999), None);
+    assert_eq!(emission.settle_time(10_000), Some(10_000));
+    assert_eq!(emission.settle_time(50_000), Some(50_000));
+    assert_eq!(emission.settle_time(200_000), Some(110_000));
+}
+
+#[test]
+fn accrued_reward_counts_whole_seconds_of_the_share() {
+    let emission = constant_emission(ONE_TOKEN);
+    // (weight, total_weight, claimed_at, now, expected)
+    let cases = [
+        (1, 1, 10_000, 20_000, 10 * ONE_TOKEN),
+        (1, 4, 10_000, 20_000, 10 * ONE_TOKEN / 4),
+        (3, 4, 10_000, 20_000, 30 * ONE_TOKEN / 4),
+        (1, 1, 10_500, 10_999, 0),
+        (1, 1, 10_999, 11_000, ONE_TOKEN),
+        (1, 1, 20_000, 10_000, 0),
+        (0, 1, 10_000, 20_000, 0),
+        (1, 0, 10_000, 20_000, 0),
+        (1, 3, 10_000, 11_000, ONE_TOKEN / 3),
+    ];
+    for (weight, total_weight, claimed_at, now, expected) in cases {
+        assert_eq!(accrued_reward(&emission, weight, total_weight, claimed_at, now), expected);
+    }
+}
+
+#[test]
+fn reward_on_reward_stops_when_a_position_unlocks() {
+    let emission = constant_emission(ONE_TOKEN);
+    // one position unlocks halfway, one already unlocked before the last claim
+    let positions = vec![(1, 100_000), (1, 15_000), (2, 5_000)];
+    let reward = reward_on_reward(&emission, positions, 4, 10_000, 20_000);
+    assert_eq!(reward, 10 * ONE_TOKEN / 4 + 5 * ONE_TOKEN / 4);
+    assert_eq!(reward_on_reward(&emission, vec![], 4, 10_000, 20_000), 0);
+}
+
+#[test]
+fn swap_stakes_vest_linearly_after_the_minimum_lock() {
+    // swapped at 0, 1000 ms minimum lock, unlocked at 10_000
+    let cases = [
+        (0, 0, 0),
+        (0, 999, 0),
+        (0, 1_000, 100),
+        (0, 5_000, 500),
+        (0, 10_000, 1_000),
+        (0, 20_000, 1_000),
+        // after unstaking at 5_000 the rest vests from there
+        (5_000, 7_500, 250),
+    ];
+    for (claimed_at, now, expected) in cases {
+        let locked = if claimed_at == 0 { 1_000 } else { 1_000 - 500 };
+        assert_eq!(swap_vested_amount(locked, claimed_at, 10_000, 1_000, 10_000, now), expected);
+    }
+}
+
+#[test]
+fn account_overview_covers_farms_and_swap_farms() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    set_context(accounts(1), 11_000, 1);
+    contract.claim_reward(0, 0);
+
+    let usdc = accounts(3);
+    set_context(accounts(0), 11_000, 1);
+    contract.save_swap_farm(usdc.clone(), U128(100_000_000), 18, 0, 10_000);
+    set_context(usdc.clone(), 11_000, 0);
+    contract.ft_on_transfer(
+        accounts(1),
+        U128(1_000),
+        "{\"stake_type\": 1, \"farm_id\": 0, \"lock_duration\": 0}".to_string(),
+    );
+
+    set_context(accounts(1), 16_000, 0);
+    let overview = contract.get_account_overview(accounts(1));
+    assert_eq!(overview.farms.len(), 1);
+    let farm = &overview.farms[0];
+    assert_eq!(farm.token.locked.0, 100 * ONE_TOKEN);
+    assert_eq!(farm.token.available.0, 0);
+    assert_eq!(farm.reward.locked.0, 10 * TOKEN_REWARD_RATE);
+    assert_eq!(farm.pending_token_reward.0, 5 * TOKEN_REWARD_RATE);
+    assert_eq!(farm.pending_lp_reward.0, 0);
+    assert_eq!(farm.pending_reward_reward.0, 5 * REWARD_REWARD_RATE);
+    assert_eq!(farm.next_unlock_at, Some(101_000));
+    assert_eq!(farm.token_weight, farm.total_token_weight);
+
+    assert_eq!(overview.swap_farms.len(), 1);
+    let swap_farm = &overview.swap_farms[0];
+    assert_eq!(swap_farm.token_id, usdc);
+    assert_eq!(swap_farm.unet.amount.0, 1_000);
+    assert_eq!(swap_farm.unet.available.0, 500);
+    assert_eq!(swap_farm.next_unlock_at, Some(21_000));
+
+    assert!(contract.get_account_overview(accounts(2)).farms.is_empty());
+}
+
+#[test]
+fn farm_stats_count_stakers_and_lock_expiries() {
+    let mut contract = setup_token_farm();
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+    stake_tokens(&mut contract, accounts(2), 100 * ONE_TOKEN, 2_000);
+
+    set_context(accounts(1), 3_000, 0);
+    let stats = contract.get_farm_stats(0, 0, 0, 10);
+    assert_eq!(stats.emission_per_second.token_reward_rate.0, TOKEN_REWARD_RATE);
+    assert_eq!(stats.total_token_amount.0, 200 * ONE_TOKEN);
+    assert_eq!(stats.staker_count, 2);
+    assert_eq!((stats.position_count, stats.total_lock_duration.0), (2, 200_000));
+    assert_eq!(stats.lock_expiries.len(), 7);
+    assert_eq!(stats.lock_expiries[1].expires_within, Some(24 * 3600 * 1000));
+    assert_eq!(stats.lock_expiries[1].positions, 2);
+    assert_eq!(stats.lock_expiries[1].token_amount.0, 200 * ONE_TOKEN);
+    assert!(stats.lp_share_yearly_reward.is_none());
+    assert_eq!(stats.next_index, None);
+
+    // pages add up to the whole farm
+    let first = contract.get_farm_stats(0, 0, 0, 1);
+    assert_eq!((first.staker_count, first.next_index), (1, Some(1)));
+    let second = contract.get_farm_stats(0, 0, 1, 1);
+    assert_eq!((second.staker_count, second.next_index), (1, None));
+    assert_eq!(first.lock_expiries[1].positions + second.lock_expiries[1].positions, 2);
+
+    // longer locks weigh more
+    let locked = contract.get_farm_stats(0, 1_000_000, 0, 10);
+    assert!(locked.token_apr_bps.unwrap().0 > stats.token_apr_bps.unwrap().0);
+
+    set_context(accounts(1), 100_000_000, 0);
+    let ended = contract.get_farm_stats(0, 0, 0, 10);
+    assert_eq!(ended.token_apr_bps.unwrap().0, 0);
+    assert_eq!(ended.lock_expiries[0].positions, 2);
+}
+
+#[test]
+fn stake_preview_matches_the_stake() {
+    let mut contract = setup_token_farm();
+    set_context(accounts(0), 0, 1);
+    contract.set_farm_fees(0, Some(FeeConfig { reward_fee_bps: 0, stake_fee_bps: 100 }));
+    stake_tokens(&mut contract, accounts(1), 100 * ONE_TOKEN, 1_000);
+
+    set_context(accounts(2), 2_000, 0);
+    let preview = contract.preview_stake(0, U128(50 * ONE_TOKEN), 100_000, PositionKind::Token, Some(accounts(2)));
+    assert_eq!(preview.fee.0, ONE_TOKEN / 2);
+    assert_eq!(preview.staked_amount.0, 50 * ONE_TOKEN - ONE_TOKEN / 2);
+    assert_eq!(preview.unlock_at, 102_000);
+    assert_eq!(
+        preview.estimated_reward_per_day.0,
+        reward_for(preview.share.0, 24 * 3600 * TOKEN_REWARD_RATE)
+    );
+
+    stake_tokens(&mut contract, accounts(2), 50 * ONE_TOKEN, 2_000);
+    let stake_info = contract.staking_informations_by_owner_id(0, accounts(2));
+    let farm_info = contract.farm_infos.get(0).unwrap();
+    assert_eq!(stake_info.token_amount, preview.staked_amount.0);
+    assert_eq!(stake_info.token_weight, preview.weight.0);
+    assert_eq!(share_of(stake_info.token_weight, farm_info.total_token_weight), preview.share.0);
+}
+
+#[test]
+#[should_panic(expected = "LP Pool does not exist in this Farm")]
+fn stake_preview_needs_the_pool() {
+    let contract = setup_token_farm();
+    contract.preview_stake(0, U128(ONE_TOKEN), 0, PositionKind::LpShare, None);
+}
+
+/// stake info as the contract before positions wrote it, one token lock per `(amount, unlocked_at, duration)`
+fn legacy_stake_info(account_id: AccountId, locks: &[(u128, u64, u64)], token_amount: u128, token_weight: u128) -> LegacyStakeInfo {
+    let lock_vector = |lock_info_type| StorageKey::LockInfos {
+        farm_id: 0,
+        account_id: account_id.clone(),
+        lock_info_type,
+    };
+    let mut stake_info = LegacyStakeInfo {
+        owner_id: account_id.clone(),
+        token_amount,
+        lp_share_amount: 0,
+        reward_amount: 0,
+        token_weight,
+        lp_share_weight: 0,
+        reward_token_to_claim: 7,
+        reward_lp_to_claim: 0,
+        token_locked: Vector::new(lock_vector(0)),
+        lp_share_locked: Vector::new(lock_vector(1)),
+        reward_locked: Vector::new(lock_vector(2)),
+        unlocked_at: Vector::new(lock_vector(3)),
+        staking_duration: Vector::new(lock_vector(4)),
+        created_at: 0,
+        claimed_token_at: 5_000,
+        claimed_lp_at: 0,
+        claimed_reward_at: 0,
+    };
+    for &(amount, unlocked_at, duration) in locks {
+        stake_info.token_locked.push(&U128(amount));
+        stake_info.lp_share_locked.push(&U128(0));
+        stake_info.reward_locked.push(&U128(0));
+        stake_info.unlocked_at.push(&unlocked_at);
+        stake_info.staking_duration.push(&duration);
+    }
+    stake_info
+}
+
+fn write_legacy_state() {
+    env::set_blockchain_interface(near_sdk::MockedBlockchain::default());
+    set_context(accounts(0), 0, 0);
+    let mut admin_ids = UnorderedSet::new(StorageKey::AdminIds);
+    admin_ids.insert(&accounts(4));
+    let mut stake_infos = UnorderedMap::new(StorageKey::StakeInfos { farm_id: 0 });
+    stake_infos.insert(
+        &accounts(1),
+        &legacy_stake_info(accounts(1), &[(100 * ONE_TOKEN, 200_000, 190_000), (50 * ONE_TOKEN, 20_000, 10_000)], 150 * ONE_TOKEN, 300),
+    );
+    stake_infos.insert(&accounts(2), &legacy_stake_info(accounts(2), &[(10 * ONE_TOKEN, 300_000, 290_000)], 10 * ONE_TOKEN, 20));
+    let mut swap_farm_info = SwapFarmInfo::new(accounts(3), 18, 0, 10_000, U128(100_000_000));
+    swap_farm_info.stake_infos.insert(
+        &accounts(2),
+        &SwapStakeInfo {
+            owner_id: accounts(2),
+            token_amount: U128(1_000),
+            token_locked: Vector::new(b"l".to_vec()),
+            unlocked_at: Vector::new(b"u".to_vec()),
+            created_at: 0,
+            claimed_token_at: Vector::new(b"c".to_vec()),
+        },
+    );
+    let mut swap_farms = UnorderedMap::new(StorageKey::SwapFarms);
+    swap_farms.insert(&accounts(3), &swap_farm_info);
+    let mut farm_infos = Vector::new(StorageKey::FarmInfos);
+    farm_infos.push(&FarmInfoV0 {
+        farm_id: 0,
+        farm_type: 0,
+        token_id: token_id(),
+        token_decimal: 18,
+        pool_id: 0,
+        total_token_amount: 160 * ONE_TOKEN,
+        total_token_weight: 320,
+        total_lp_share_amount: 0,
+        total_lp_share_weight: 0,
+        total_reward_amount: 0,
+        total_reward_weight: 0,
+        token_reward_rate: TOKEN_REWARD_RATE,
+        pool_reward_rate: 0,
+        reward_reward_rate: REWARD_REWARD_RATE,
+        token_weight_rate: 1000,
+        pool_weight_rate: 0,
+        max_token_vesting_duration: 1_000_000,
+        max_reward_vesting_duration: 1_000_000,
+        starting_at: 0,
+        ending_at: 100_000_000,
+        stake_infos,
+    });
+    env::state_write(&ContractV0 {
+        owner_id: accounts(0),
+        farm_infos,
+        storage_deposits: LookupMap::new(StorageKey::StorageDeposits),
+        admin_ids,
+        editing_new_farm: Contract::new(accounts(0)).editing_new_farm,
+        confirmed_admins_for_new_farm: UnorderedSet::new(StorageKey::ConfirmedAdminsForNewFarm),
+        swap_farms,
+        editing_swap_farm_infos: UnorderedMap::new(StorageKey::EditingSwapFarmInfo),
+        token_stake_info: LookupMap::new(StorageKey::TokenStakeInfo),
+        lp_stake_info: LookupMap::new(StorageKey::LPStakeInfo),
+    });
+}
+
+#[test]
+fn migrate_converts_legacy_state_page_by_page() {
+    write_legacy_state();
+    set_context(accounts(0), 100_000, 0);
+    let mut contract = Contract::migrate();
+    let farm_info = contract.farm_infos.get(0).unwrap();
+    assert_eq!((farm_info.total_token_amount, farm_info.total_token_weight), (160 * ONE_TOKEN, 320));
+    assert_eq!(farm_info.token_reward_rate, TOKEN_REWARD_RATE);
+    assert!(contract.is_paused());
+    assert_eq!(contract.get_role_holders(Role::FarmManager), vec![accounts(4)]);
+    assert!(contract.account_has_role(Role::FarmManager, accounts(4)));
+
+    assert_eq!(contract.migrate_stake_infos(0, 0, 1), 1);
+    assert!(contract.is_paused());
+    assert_eq!(contract.migrate_stake_infos(0, 1, 1), 2);
+    assert!(contract.is_paused());
+    assert_eq!(contract.migrate_swap_stakes(accounts(3), 0, 10), 1);
+    assert!(!contract.is_paused());
+    assert_eq!(contract.internal_liabilities(token_id().as_str()).swap_owed, 1_000);
+
+    let stake_info = contract.staking_informations_by_owner_id(0, accounts(1));
+    assert_eq!((stake_info.token_amount, stake_info.token_weight), (150 * ONE_TOKEN, 300));
+    assert_eq!((stake_info.reward_token_to_claim, stake_info.claimed_token_at), (7, 5_000));
+    let positions = contract.get_positions(0, accounts(1));
+    let mut locks: Vec<(u128, u128, u64)> = positions
+        .iter()
+        .map(|position| (position.amount.0, position.weight.0, position.unlocked_at))
+        .collect();
+    locks.sort();
+    assert_eq!(locks, vec![(50 * ONE_TOKEN, 100, 20_000), (100 * ONE_TOKEN, 200, 200_000)]);
+    assert_eq!(contract.nft_supply_for_owner(accounts(1)).0, 2);
+    assert_eq!(contract.get_positions(0, accounts(2))[0].weight.0, 20);
+    assert_eq!(contract.nft_total_supply().0, 3);
+}
+
+#[test]
+#[should_panic(expected = "Stake infos are converted in order, continue from 1")]
+fn migrate_stake_infos_continues_where_it_stopped() {
+    write_legacy_state();
+    set_context(accounts(0), 100_000, 0);
+    let mut contract = Contract::migrate();
+    contract.migrate_stake_infos(0, 0, 1);
+    contract.migrate_stake_infos(0, 0, 1);
+}
@@ -0,0 +1,307 @@
+use crate::*;
+
+/// A privileged call held back until its ETA so stakers see rate and config changes coming.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TimelockAction {
+    AddFarm {
+        farm_type: u8,
+        token_id: AccountId,
+        token_decimal: u8,
+        pool_id: u64,
+        token_reward_rate: U128,
+        pool_reward_rate: U128,
+        reward_reward_rate: U128,
+        token_weight_rate: u16,
+        pool_weight_rate: u16,
+        max_token_vesting_duration: u64,
+        max_reward_vesting_duration: u64,
+        starting_at: u64,
+        ending_at: u64,
+    },
+    SaveSwapFarm {
+        token_id: AccountId,
+        swap_rate: U128,
+        decimal: u32,
+        min_lock_time: u64,
+        max_lock_time: u64,
+    },
+    RemoveSwapFarm {
+        token_id: AccountId,
+    },
+    SetTimelockDelay {
+        delay: u64,
+    },
+    SetEmissionSchedule {
+        farm_id: u16,
+        emission_schedule: EmissionSchedule,
+    },
+    SetFarmEpochs {
+        farm_id: u16,
+        epoch_length: u64,
+        token_reward_per_epoch: U128,
+        pool_reward_per_epoch: U128,
+        total_epochs: Option<u32>,
+    },
+    SetFarmLimits {
+        farm_id: u16,
+        token_type: u8,
+        max_total_amount: Option<U128>,
+        max_account_amount: Option<U128>,
+        min_stake_amount: U128,
+    },
+    SetFarmAccess {
+        farm_id: u16,
+        access: FarmAccessView,
+    },
+    SetDefaultFees {
+        fees: FeeConfig,
+    },
+    SetFarmFees {
+        farm_id: u16,
+        fees: Option<FeeConfig>,
+    },
+    SetFarmUnbondingPeriod {
+        farm_id: u16,
+        unbonding_period: u64,
+    },
+    SetNftBoostCollection {
+        nft_contract_id: AccountId,
+        multiplier: u32,
+    },
+    RemoveNftBoostCollection {
+        nft_contract_id: AccountId,
+    },
+    SetSuccessorFarm {
+        farm_id: u16,
+        successor_farm_id: Option<u16>,
+    },
+    SetTreasuryAccount {
+        account_id: AccountId,
+    },
+}
+
+impl TimelockAction {
+    /// role needed to queue and execute the action, `None` when only the owner may
+    fn required_role(&self) -> Option<Role> {
+        match self {
+            TimelockAction::SetEmissionSchedule { .. }
+            | TimelockAction::SetFarmEpochs { .. }
+            | TimelockAction::SetFarmLimits { .. }
+            | TimelockAction::SetFarmAccess { .. }
+            | TimelockAction::SetFarmUnbondingPeriod { .. }
+            | TimelockAction::SetNftBoostCollection { .. }
+            | TimelockAction::RemoveNftBoostCollection { .. }
+            | TimelockAction::SetSuccessorFarm { .. } => Some(Role::FarmManager),
+            TimelockAction::SaveSwapFarm { .. } | TimelockAction::RemoveSwapFarm { .. } => Some(Role::SwapManager),
+            TimelockAction::SetDefaultFees { .. }
+            | TimelockAction::SetFarmFees { .. }
+            | TimelockAction::SetTreasuryAccount { .. } => Some(Role::TreasuryManager),
+            TimelockAction::AddFarm { .. } | TimelockAction::SetTimelockDelay { .. } => None,
+        }
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TimelockOperation {
+    pub id: u64,
+    pub action: TimelockAction,
+    pub proposer_id: AccountId,
+    pub queued_at: u64,
+    pub eta: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TimelockData<'a> {
+    pub id: u64,
+    pub account_id: &'a AccountId,
+    pub eta: u64,
+}
+
+impl Contract {
+    /// Direct calls of timelocked methods only go through while no delay is set.
+    pub(crate) fn assert_not_timelocked(&self) {
+        assert!(self.timelock_delay == 0, "Timelocked, use queue_operation");
+    }
+
+    fn assert_action_role(&self, action: &TimelockAction) {
+        match action.required_role() {
+            Some(role) => self.assert_role(role),
+            None => self.assert_owner(),
+        }
+    }
+
+    fn internal_apply_action(&mut self, action: TimelockAction) {
+        match action {
+            TimelockAction::AddFarm {
+                farm_type,
+                token_id,
+                token_decimal,
+                pool_id,
+                token_reward_rate,
+                pool_reward_rate,
+                reward_reward_rate,
+                token_weight_rate,
+                pool_weight_rate,
+                max_token_vesting_duration,
+                max_reward_vesting_duration,
+                starting_at,
+                ending_at,
+            } => {
+                self.farm_infos.push(&FarmInfo::new(
+                    self.farm_infos.len() as u16,
+                    farm_type,
+                    token_id,
+                    token_decimal,
+                    pool_id,
+                    token_reward_rate,
+                    pool_reward_rate,
+                    reward_reward_rate,
+                    token_weight_rate,
+                    pool_weight_rate,
+                    max_token_vesting_duration,
+                    max_reward_vesting_duration,
+                    starting_at,
+                    ending_at,
+                ));
+            }
+            TimelockAction::SaveSwapFarm {
+                token_id,
+                swap_rate,
+                decimal,
+                min_lock_time,
+                max_lock_time,
+            } => self.internal_save_swap_farm(token_id, swap_rate, decimal, min_lock_time, max_lock_time),
+            TimelockAction::RemoveSwapFarm { token_id } => self.internal_remove_swap_farm(&token_id),
+            TimelockAction::SetTimelockDelay { delay } => self.timelock_delay = delay,
+            TimelockAction::SetEmissionSchedule {
+                farm_id,
+                emission_schedule,
+            } => self.internal_set_emission_schedule(farm_id, emission_schedule),
+            TimelockAction::SetFarmEpochs {
+                farm_id,
+                epoch_length,
+                token_reward_per_epoch,
+                pool_reward_per_epoch,
+                total_epochs,
+            } => self.internal_set_farm_epochs(farm_id, epoch_length, token_reward_per_epoch, pool_reward_per_epoch, total_epochs),
+            TimelockAction::SetFarmLimits {
+                farm_id,
+                token_type,
+                max_total_amount,
+                max_account_amount,
+                min_stake_amount,
+            } => self.internal_set_farm_limits(farm_id, token_type, max_total_amount, max_account_amount, min_stake_amount),
+            TimelockAction::SetFarmAccess { farm_id, access } => self.internal_set_farm_access(farm_id, access),
+            TimelockAction::SetDefaultFees { fees } => self.internal_set_default_fees(fees),
+            TimelockAction::SetFarmFees { farm_id, fees } => self.internal_set_farm_fees(farm_id, fees),
+            TimelockAction::SetFarmUnbondingPeriod {
+                farm_id,
+                unbonding_period,
+            } => self.internal_set_farm_unbonding_period(farm_id, unbonding_period),
+            TimelockAction::SetNftBoostCollection {
+                nft_contract_id,
+                multiplier,
+            } => self.internal_set_nft_boost_collection(nft_contract_id, multiplier),
+            TimelockAction::RemoveNftBoostCollection { nft_contract_id } => {
+                self.nft_boost_collections.remove(&nft_contract_id);
+            }
+            TimelockAction::SetSuccessorFarm {
+                farm_id,
+                successor_farm_id,
+            } => self.internal_set_successor_farm(farm_id, successor_farm_id),
+            TimelockAction::SetTreasuryAccount { account_id } => self.treasury_id = account_id,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires the owner. Turning the timelock on takes effect at once, changing or
+    /// removing an existing delay has to be queued as `SetTimelockDelay`.
+    #[payable]
+    pub fn set_timelock_delay(&mut self, delay: u64) {
+        self.assert_owner();
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.timelock_delay = delay;
+    }
+
+    /// Requires the role of the queued action, `AddFarm` and `SetTimelockDelay` require the owner.
+    #[payable]
+    pub fn queue_operation(&mut self, action: TimelockAction) -> u64 {
+        self.assert_action_role(&action);
+        assert_one_yocto();
+        assert!(self.timelock_delay > 0, "Timelock is off, call the method directly");
+        let account_id = env::predecessor_account_id();
+        let now = env::block_timestamp() / 1000000;
+        let id = self.next_operation_id;
+        self.next_operation_id += 1;
+        let operation = TimelockOperation {
+            id,
+            action,
+            proposer_id: account_id.clone(),
+            queued_at: now,
+            eta: now + self.timelock_delay,
+        };
+        self.timelock_operations.insert(&id, &operation);
+        emit_event(
+            "operation_queued",
+            TimelockData {
+                id,
+                account_id: &account_id,
+                eta: operation.eta,
+            },
+        );
+        id
+    }
+
+    /// Requires the role of the queued action, `AddFarm` and `SetTimelockDelay` require the owner.
+    #[payable]
+    pub fn execute_operation(&mut self, id: u64) {
+        let operation = self.timelock_operations.get(&id).expect("No queued operation");
+        self.assert_action_role(&operation.action);
+        assert_one_yocto();
+        let now = env::block_timestamp() / 1000000;
+        assert!(now >= operation.eta, "Operation is not ready before {}", operation.eta);
+        self.timelock_operations.remove(&id);
+        self.internal_apply_action(operation.action);
+        emit_event(
+            "operation_executed",
+            TimelockData {
+                id,
+                account_id: &env::predecessor_account_id(),
+                eta: operation.eta,
+            },
+        );
+    }
+
+    /// Requires the owner.
+    #[payable]
+    pub fn cancel_operation(&mut self, id: u64) {
+        self.assert_owner();
+        assert_one_yocto();
+        let operation = self.timelock_operations.remove(&id).expect("No queued operation");
+        emit_event(
+            "operation_cancelled",
+            TimelockData {
+                id,
+                account_id: &env::predecessor_account_id(),
+                eta: operation.eta,
+            },
+        );
+    }
+
+    pub fn get_timelock_delay(&self) -> u64 {
+        self.timelock_delay
+    }
+
+    pub fn get_queued_operations(&self, from_index: u64, limit: u64) -> Vec<TimelockOperation> {
+        let operations = self.timelock_operations.values_as_vector();
+        (from_index..min(from_index + limit, operations.len()))
+            .map(|index| operations.get(index).unwrap())
+            .collect()
+    }
+}
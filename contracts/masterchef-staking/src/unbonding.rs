@@ -0,0 +1,198 @@
+use crate::*;
+
+/// upper bound of a farm's unbonding period, 30 days
+pub const MAX_UNBONDING_PERIOD: u64 = 30 * 24 * 3600 * 1000;
+
+/// An unstaked amount waiting out the farm's unbonding period. It no longer has weight
+/// and earns nothing, the tokens stay in the contract until `withdraw_unbonded`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Unbonding {
+    pub kind: PositionKind,
+    pub amount: u128,
+    pub requested_at: u64,
+    pub ready_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UnbondingView {
+    pub farm_id: u16,
+    pub kind: PositionKind,
+    pub amount: U128,
+    pub requested_at: u64,
+    pub ready_at: u64,
+}
+
+impl UnbondingView {
+    pub fn new(farm_id: u16, unbonding: &Unbonding) -> Self {
+        Self {
+            farm_id,
+            kind: unbonding.kind,
+            amount: U128(unbonding.amount),
+            requested_at: unbonding.requested_at,
+            ready_at: unbonding.ready_at,
+        }
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_queue_unbonding(
+        &self,
+        farm_info: &mut FarmInfo,
+        stake_info: &mut StakeInfo,
+        kind: PositionKind,
+        amount: u128,
+    ) {
+        let now = env::block_timestamp() / 1000000;
+        match kind {
+            PositionKind::Token => farm_info.unbonding_token_amount += amount,
+            PositionKind::LpShare => farm_info.unbonding_lp_share_amount += amount,
+            PositionKind::Reward => farm_info.unbonding_reward_amount += amount,
+        }
+        stake_info.unbondings.push(&Unbonding {
+            kind,
+            amount,
+            requested_at: now,
+            ready_at: now + farm_info.unbonding_period,
+        });
+    }
+
+    /// Sends farm tokens, Ref LP shares or rewards out of the contract.
+    pub(crate) fn internal_send(
+        &self,
+        farm_info: &FarmInfo,
+        account_id: AccountId,
+        kind: PositionKind,
+        amount: u128,
+    ) {
+        match kind {
+            PositionKind::Token | PositionKind::Reward => {
+                ext_transfer::ext(farm_info.token_id.clone())
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_FT_TRANSFER)
+                    .ft_transfer(account_id, U128(amount), None);
+            }
+            PositionKind::LpShare => {
+                ext_transfer::ext(self.ref_exchange_id.clone())
+                    .with_attached_deposit(1)
+                    .with_static_gas(GAS_FOR_FT_TRANSFER)
+                    .mft_transfer(
+                        ":".to_owned() + &farm_info.pool_id.to_string(),
+                        account_id,
+                        U128(amount),
+                        None,
+                    );
+            }
+        }
+    }
+}
+
+impl Contract {
+    /// Applies to unstakes requested from now on, queued entries keep their `ready_at`.
+    pub(crate) fn internal_set_farm_unbonding_period(&mut self, farm_id: u16, unbonding_period: u64) {
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        assert!(
+            unbonding_period <= MAX_UNBONDING_PERIOD,
+            "Unbonding period must be at most {}",
+            MAX_UNBONDING_PERIOD
+        );
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        farm_info.unbonding_period = unbonding_period;
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires the `FarmManager` role, at most `MAX_UNBONDING_PERIOD`. Goes through
+    /// `queue_operation` while the timelock is on.
+    #[payable]
+    pub fn set_farm_unbonding_period(&mut self, farm_id: u16, unbonding_period: u64) {
+        self.assert_role(Role::FarmManager);
+        assert_one_yocto();
+        self.assert_not_timelocked();
+        self.internal_set_farm_unbonding_period(farm_id, unbonding_period);
+    }
+
+    /// Unstakes `amount` of unlocked stake on a farm with an unbonding period. Weight and rewards
+    /// end now, the tokens can be withdrawn with `withdraw_unbonded` once the entry is ready.
+    #[payable]
+    pub fn request_unstake(&mut self, farm_id: u16, token_type: u8, amount: U128) -> UnbondingView {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        assert!(
+            self.farm_infos.get(farm_id.into()).unwrap().unbonding_period > 0,
+            "Farm has no unbonding period, use unstake"
+        );
+        let initial_storage_usage = env::storage_usage();
+        let queued = self.staking_informations_by_owner_id(farm_id, account_id.clone()).unbondings.len();
+        let refunded = self.internal_unstake(farm_id, &account_id, token_type, amount);
+        self.internal_settle_storage(&account_id, initial_storage_usage - refunded);
+
+        let stake_info = self.staking_informations_by_owner_id(farm_id, account_id);
+        assert!(stake_info.unbondings.len() > queued, "Nothing left to unbond after the withdraw fee");
+        let unbonding = stake_info.unbondings.get(stake_info.unbondings.len() - 1).unwrap();
+        UnbondingView::new(farm_id, &unbonding)
+    }
+
+    /// Sends every unbonding entry of the farm whose period has passed.
+    #[payable]
+    pub fn withdraw_unbonded(&mut self, farm_id: u16) -> MaturedWithdrawal {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert!(self.farm_infos.len() > farm_id.into(), "Invalid Farm ID");
+        let now = env::block_timestamp() / 1000000;
+        let initial_storage_usage = env::storage_usage();
+        let mut farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        let mut stake_info = farm_info.stake_infos.get(&account_id).expect("No stake in this Farm");
+
+        let mut amounts = [0u128; 3];
+        let mut index = 0;
+        while index < stake_info.unbondings.len() {
+            let unbonding = stake_info.unbondings.get(index).unwrap();
+            if unbonding.ready_at > now {
+                index += 1;
+                continue;
+            }
+            amounts[usize::from(unbonding.kind.token_type())] += unbonding.amount;
+            stake_info.unbondings.swap_remove(index);
+        }
+        assert!(amounts.iter().any(|amount| *amount > 0), "Nothing to withdraw");
+
+        farm_info.unbonding_token_amount -= amounts[0];
+        farm_info.unbonding_lp_share_amount -= amounts[1];
+        farm_info.unbonding_reward_amount -= amounts[2];
+        for kind in [PositionKind::Token, PositionKind::LpShare, PositionKind::Reward] {
+            let amount = amounts[usize::from(kind.token_type())];
+            if amount > 0 {
+                self.internal_send(&farm_info, account_id.clone(), kind, amount);
+            }
+        }
+        farm_info.stake_infos.insert(&account_id, &stake_info);
+        self.farm_infos.replace(farm_id.into(), &farm_info);
+        self.internal_refund_storage(&account_id, initial_storage_usage);
+
+        MaturedWithdrawal {
+            token_amount: U128(amounts[0]),
+            lp_share_amount: U128(amounts[1]),
+            reward_amount: U128(amounts[2]),
+        }
+    }
+
+    /// Pending unbonding entries of the account, soonest ready first.
+    pub fn get_unbondings(&self, farm_id: u16, account_id: AccountId) -> Vec<UnbondingView> {
+        let farm_info = self.farm_infos.get(farm_id.into()).unwrap();
+        if let Some(stake_info) = farm_info.stake_infos.get(&account_id) {
+            let mut unbondings = stake_info
+                .unbondings
+                .iter()
+                .map(|unbonding| UnbondingView::new(farm_id, &unbonding))
+                .collect::<Vec<UnbondingView>>();
+            unbondings.sort_by_key(|unbonding| unbonding.ready_at);
+            unbondings
+        } else {
+            vec![]
+        }
+    }
+}
@@ -22,6 +22,9 @@ pub struct FarmSkeletonInfo {
     pub ending_at: u64,
     pub total_token_weight: U128,
     pub total_lp_share_weight: U128,
+    pub unbonding_period: u64,
+    pub successor_farm_id: Option<u16>,
+    pub emission_schedule: EmissionSchedule,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -34,15 +37,12 @@ pub struct StakeSkeletonInfo {
     pub reward_amount: U128,
     pub reward_token_to_claim: U128,
     pub reward_lp_to_claim: U128,
+    pub reward_reward_to_claim: U128,
     pub created_at: u64,
     pub claimed_token_at: u64,
     pub claimed_lp_at: u64,
     pub claimed_reward_at: u64,
-    pub token_locked: Vec<U128>,
-    pub lp_share_locked: Vec<U128>,
-    pub reward_locked: Vec<U128>,
-    pub unlocked_at: Vec<u64>,
-    pub staking_duration: Vec<u64>,
+    pub positions: Vec<PositionView>,
     pub token_weight: U128,
     pub lp_share_weight: U128,
 }
@@ -121,6 +121,7 @@ impl Contract {
         U128(self.lp_locked_amount(farm_id, account_id))
     }
 
+    /// reward-on-reward balance, claimed apart from the locked rewards with `claim_reward(farm_id, 2)`
     pub fn get_reward_claim_amount(&self, farm_id: u16, account_id: AccountId) -> U128 {
         let now = env::block_timestamp() / 1000000;
 
@@ -157,6 +158,9 @@ impl Contract {
             ending_at: farm_info.ending_at,
             total_token_weight: U128(farm_info.total_token_weight),
             total_lp_share_weight: U128(farm_info.total_lp_share_weight),
+            unbonding_period: farm_info.unbonding_period,
+            successor_farm_id: farm_info.successor_farm_id,
+            emission_schedule: farm_info.emission_schedule,
         }
     }
 
@@ -174,21 +178,22 @@ impl Contract {
         if let Some(info) = info {
             let stake_info = StakeSkeletonInfo {
                 farm_id: farm_id,
-                owner_id: info.owner_id,
+                owner_id: info.owner_id.clone(),
                 token_amount: U128(info.token_amount),
                 lp_share_amount: U128(info.lp_share_amount),
                 reward_amount: U128(info.reward_amount),
                 reward_token_to_claim: U128(info.reward_token_to_claim),
                 reward_lp_to_claim: U128(info.reward_lp_to_claim),
+                reward_reward_to_claim: U128(info.reward_reward_to_claim),
                 created_at: info.created_at,
                 claimed_token_at: info.claimed_token_at,
                 claimed_lp_at: info.claimed_lp_at,
                 claimed_reward_at: info.claimed_reward_at,
-                token_locked: info.token_locked.to_vec(),
-                lp_share_locked: info.lp_share_locked.to_vec(),
-                reward_locked: info.reward_locked.to_vec(),
-                unlocked_at: info.unlocked_at.to_vec(),
-                staking_duration: info.staking_duration.to_vec(),
+                positions: info
+                    .sorted_positions()
+                    .iter()
+                    .map(|position| PositionView::new(farm_id, account_id.clone(), position))
+                    .collect(),
                 token_weight: U128(info.token_weight),
                 lp_share_weight: U128(info.lp_share_weight),
             };
@@ -0,0 +1,220 @@
+//! A stripped-down Sputnik v2 DAO for integration tests. It keeps the proposal and vote
+//! interface of the real DAO for function-call proposals only: a proposal holds a batch of
+//! calls to one receiver and runs them as a single promise once enough council members approve.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, UnorderedSet};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, BorshStorageKey, Gas, PanicOnDefault, Promise};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ActionCall {
+    pub method_name: String,
+    pub args: Base64VecU8,
+    pub deposit: U128,
+    pub gas: U64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ProposalKind {
+    FunctionCall {
+        receiver_id: AccountId,
+        actions: Vec<ActionCall>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ProposalInput {
+    pub description: String,
+    pub kind: ProposalKind,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub enum ProposalStatus {
+    InProgress,
+    Approved,
+    Rejected,
+}
+
+/// the subset of Sputnik v2 votes the mock understands
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum Action {
+    VoteApprove,
+    VoteReject,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Proposal {
+    pub proposer: AccountId,
+    pub description: String,
+    pub kind: ProposalKind,
+    pub status: ProposalStatus,
+    pub approvals: Vec<AccountId>,
+    pub rejections: Vec<AccountId>,
+}
+
+#[derive(BorshStorageKey, BorshSerialize)]
+pub enum StorageKey {
+    Council,
+    Proposals,
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Contract {
+    pub council: UnorderedSet<AccountId>,
+    /// approvals (or rejections) needed to settle a proposal
+    pub threshold: u64,
+    pub proposals: LookupMap<u64, Proposal>,
+    pub last_proposal_id: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[init]
+    pub fn new(council: Vec<AccountId>, threshold: u64) -> Self {
+        assert!(threshold > 0 && threshold <= council.len() as u64, "Invalid threshold");
+        let mut this = Self {
+            council: UnorderedSet::new(StorageKey::Council),
+            threshold,
+            proposals: LookupMap::new(StorageKey::Proposals),
+            last_proposal_id: 0,
+        };
+        for account_id in council {
+            this.council.insert(&account_id);
+        }
+        this
+    }
+
+    pub fn add_proposal(&mut self, proposal: ProposalInput) -> u64 {
+        let proposer = env::predecessor_account_id();
+        assert!(self.council.contains(&proposer), "Not a council member");
+        let id = self.last_proposal_id;
+        self.proposals.insert(
+            &id,
+            &Proposal {
+                proposer,
+                description: proposal.description,
+                kind: proposal.kind,
+                status: ProposalStatus::InProgress,
+                approvals: vec![],
+                rejections: vec![],
+            },
+        );
+        self.last_proposal_id += 1;
+        id
+    }
+
+    /// Executes the proposal's calls in one batch when the vote reaches the threshold. Like the
+    /// real DAO, a failing call fails the whole batch but the proposal stays approved.
+    pub fn act_proposal(&mut self, id: u64, action: Action, memo: Option<String>) {
+        if let Some(memo) = memo {
+            env::log_str(&memo);
+        }
+        let account_id = env::predecessor_account_id();
+        assert!(self.council.contains(&account_id), "Not a council member");
+        let mut proposal = self.proposals.get(&id).expect("No proposal");
+        assert_eq!(proposal.status, ProposalStatus::InProgress, "Proposal is not in progress");
+        assert!(
+            !proposal.approvals.contains(&account_id) && !proposal.rejections.contains(&account_id),
+            "Already voted"
+        );
+        match action {
+            Action::VoteApprove => proposal.approvals.push(account_id),
+            Action::VoteReject => proposal.rejections.push(account_id),
+        }
+        if proposal.approvals.len() as u64 >= self.threshold {
+            proposal.status = ProposalStatus::Approved;
+            self.internal_execute(&proposal);
+        } else if proposal.rejections.len() as u64 >= self.threshold {
+            proposal.status = ProposalStatus::Rejected;
+        }
+        self.proposals.insert(&id, &proposal);
+    }
+
+    pub fn get_proposal(&self, id: u64) -> Option<Proposal> {
+        self.proposals.get(&id)
+    }
+
+    pub fn get_last_proposal_id(&self) -> u64 {
+        self.last_proposal_id
+    }
+
+    pub fn get_council(&self) -> Vec<AccountId> {
+        self.council.to_vec()
+    }
+}
+
+impl Contract {
+    fn internal_execute(&self, proposal: &Proposal) {
+        match &proposal.kind {
+            ProposalKind::FunctionCall { receiver_id, actions } => {
+                let mut promise = Promise::new(receiver_id.clone());
+                for action in actions {
+                    promise = promise.function_call(
+                        action.method_name.clone(),
+                        action.args.clone().into(),
+                        action.deposit.0,
+                        Gas(action.gas.0),
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    fn set_context(predecessor: AccountId) {
+        testing_env!(VMContextBuilder::new().predecessor_account_id(predecessor).build());
+    }
+
+    fn proposal() -> ProposalInput {
+        ProposalInput {
+            description: "create a farm".to_string(),
+            kind: ProposalKind::FunctionCall {
+                receiver_id: accounts(3),
+                actions: vec![ActionCall {
+                    method_name: "set_dao_mode".to_string(),
+                    args: b"{\"enabled\":true}".to_vec().into(),
+                    deposit: U128(1),
+                    gas: U64(10_000_000_000_000),
+                }],
+            },
+        }
+    }
+
+    #[test]
+    fn proposal_is_approved_at_the_threshold() {
+        set_context(accounts(0));
+        let mut contract = Contract::new(vec![accounts(0), accounts(1), accounts(2)], 2);
+        let id = contract.add_proposal(proposal());
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(contract.get_proposal(id).unwrap().status, ProposalStatus::InProgress);
+
+        set_context(accounts(1));
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(contract.get_proposal(id).unwrap().status, ProposalStatus::Approved);
+    }
+
+    #[test]
+    #[should_panic(expected = "Not a council member")]
+    fn outsiders_cannot_vote() {
+        set_context(accounts(0));
+        let mut contract = Contract::new(vec![accounts(0)], 1);
+        let id = contract.add_proposal(proposal());
+        set_context(accounts(4));
+        contract.act_proposal(id, Action::VoteApprove, None);
+    }
+}
@@ -0,0 +1,6 @@
+#!/bin/bash
+set -e
+
+RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown --release
+mkdir -p ../../out
+cp ../target/wasm32-unknown-unknown/release/mock_ft.wasm ../../out/mock_ft.wasm
@@ -0,0 +1,139 @@
+//! A NEP-141 fungible token for integration tests. Balances are plain numbers without storage
+//! registration, anyone can `mint`, and `ft_transfer_call` refunds whatever the receiver's
+//! `ft_on_transfer` reports as unused, like the standard implementation.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, near_bindgen, AccountId, Gas, PanicOnDefault, PromiseOrValue,
+    PromiseResult,
+};
+
+const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(35_000_000_000_000);
+
+#[ext_contract(ext_receiver)]
+pub trait FungibleTokenReceiver {
+    fn ft_on_transfer(&mut self, sender_id: AccountId, amount: U128, msg: String) -> PromiseOrValue<U128>;
+}
+
+#[ext_contract(ext_self)]
+pub trait FungibleTokenResolver {
+    fn ft_resolve_transfer(&mut self, sender_id: AccountId, receiver_id: AccountId, amount: U128) -> U128;
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Contract {
+    pub balances: LookupMap<AccountId, u128>,
+    pub total_supply: u128,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[init]
+    pub fn new() -> Self {
+        Self {
+            balances: LookupMap::new(b"b".to_vec()),
+            total_supply: 0,
+        }
+    }
+
+    pub fn mint(&mut self, account_id: AccountId, amount: U128) {
+        self.internal_deposit(&account_id, amount.0);
+        self.total_supply += amount.0;
+    }
+
+    #[payable]
+    pub fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        let _ = memo;
+        self.internal_transfer(&env::predecessor_account_id(), &receiver_id, amount.0);
+    }
+
+    #[payable]
+    pub fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        let _ = memo;
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer(&sender_id, &receiver_id, amount.0);
+        let receiver_gas = env::prepaid_gas() - GAS_FOR_FT_TRANSFER_CALL;
+        ext_receiver::ext(receiver_id.clone())
+            .with_static_gas(receiver_gas)
+            .ft_on_transfer(sender_id.clone(), amount, msg)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .ft_resolve_transfer(sender_id, receiver_id, amount),
+            )
+            .into()
+    }
+
+    /// Refunds the unused amount reported by the receiver, all of it if the call failed.
+    /// Returns the amount the receiver kept.
+    #[private]
+    pub fn ft_resolve_transfer(&mut self, sender_id: AccountId, receiver_id: AccountId, amount: U128) -> U128 {
+        let unused = match env::promise_result(0) {
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map(|unused| unused.0.min(amount.0))
+                .unwrap_or(amount.0),
+            _ => amount.0,
+        };
+        let refund = unused.min(self.ft_balance_of(receiver_id.clone()).0);
+        if refund > 0 {
+            self.internal_transfer(&receiver_id, &sender_id, refund);
+        }
+        U128(amount.0 - refund)
+    }
+
+    pub fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        U128(self.balances.get(&account_id).unwrap_or(0))
+    }
+
+    pub fn ft_total_supply(&self) -> U128 {
+        U128(self.total_supply)
+    }
+}
+
+impl Contract {
+    fn internal_deposit(&mut self, account_id: &AccountId, amount: u128) {
+        let balance = self.balances.get(account_id).unwrap_or(0);
+        self.balances.insert(account_id, &(balance + amount));
+    }
+
+    fn internal_transfer(&mut self, sender_id: &AccountId, receiver_id: &AccountId, amount: u128) {
+        assert!(amount > 0, "The amount should be a positive number");
+        let balance = self.balances.get(sender_id).unwrap_or(0);
+        assert!(balance >= amount, "The account doesn't have enough balance");
+        self.balances.insert(sender_id, &(balance - amount));
+        self.internal_deposit(receiver_id, amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn transfer_moves_minted_balance() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        let mut contract = Contract::new();
+        contract.mint(accounts(0), U128(100));
+        contract.ft_transfer(accounts(1), U128(40), None);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 60);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 40);
+        assert_eq!(contract.ft_total_supply().0, 100);
+    }
+}
@@ -0,0 +1,163 @@
+//! A Ref exchange stand-in for integration tests. It only keeps LP share balances, addressed
+//! like Ref's multi-token interface as `":<pool_id>"`, with `mft_transfer`,
+//! `mft_transfer_call` and `mft_balance_of`. Anyone can `mint` shares of any pool.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, env, ext_contract, near_bindgen, AccountId, Gas, PanicOnDefault, PromiseOrValue,
+    PromiseResult,
+};
+
+const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_MFT_TRANSFER_CALL: Gas = Gas(35_000_000_000_000);
+
+#[ext_contract(ext_receiver)]
+pub trait MFTTokenReceiver {
+    fn mft_on_transfer(
+        &mut self,
+        token_id: String,
+        sender_id: AccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128>;
+}
+
+#[ext_contract(ext_self)]
+pub trait MFTTokenResolver {
+    fn mft_resolve_transfer(
+        &mut self,
+        token_id: String,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128;
+}
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Contract {
+    /// shares by `(token_id, account_id)`
+    pub shares: LookupMap<(String, AccountId), u128>,
+}
+
+fn assert_pool_token(token_id: &str) {
+    assert!(
+        token_id.starts_with(':') && token_id[1..].parse::<u64>().is_ok(),
+        "Only pool shares are supported"
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    #[init]
+    pub fn new() -> Self {
+        Self {
+            shares: LookupMap::new(b"s".to_vec()),
+        }
+    }
+
+    pub fn mint(&mut self, token_id: String, account_id: AccountId, amount: U128) {
+        assert_pool_token(&token_id);
+        self.internal_deposit(&token_id, &account_id, amount.0);
+    }
+
+    #[payable]
+    pub fn mft_transfer(&mut self, token_id: String, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        let _ = memo;
+        self.internal_transfer(&token_id, &env::predecessor_account_id(), &receiver_id, amount.0);
+    }
+
+    #[payable]
+    pub fn mft_transfer_call(
+        &mut self,
+        token_id: String,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        let _ = memo;
+        let sender_id = env::predecessor_account_id();
+        self.internal_transfer(&token_id, &sender_id, &receiver_id, amount.0);
+        let receiver_gas = env::prepaid_gas() - GAS_FOR_MFT_TRANSFER_CALL;
+        ext_receiver::ext(receiver_id.clone())
+            .with_static_gas(receiver_gas)
+            .mft_on_transfer(token_id.clone(), sender_id.clone(), amount, msg)
+            .then(
+                ext_self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_TRANSFER)
+                    .mft_resolve_transfer(token_id, sender_id, receiver_id, amount),
+            )
+            .into()
+    }
+
+    /// Refunds the unused shares reported by the receiver, all of them if the call failed.
+    /// Returns the amount the receiver kept.
+    #[private]
+    pub fn mft_resolve_transfer(
+        &mut self,
+        token_id: String,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        let unused = match env::promise_result(0) {
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map(|unused| unused.0.min(amount.0))
+                .unwrap_or(amount.0),
+            _ => amount.0,
+        };
+        let refund = unused.min(self.mft_balance_of(token_id.clone(), receiver_id.clone()).0);
+        if refund > 0 {
+            self.internal_transfer(&token_id, &receiver_id, &sender_id, refund);
+        }
+        U128(amount.0 - refund)
+    }
+
+    pub fn mft_balance_of(&self, token_id: String, account_id: AccountId) -> U128 {
+        U128(self.shares.get(&(token_id, account_id)).unwrap_or(0))
+    }
+}
+
+impl Contract {
+    fn internal_deposit(&mut self, token_id: &str, account_id: &AccountId, amount: u128) {
+        let key = (token_id.to_string(), account_id.clone());
+        let balance = self.shares.get(&key).unwrap_or(0);
+        self.shares.insert(&key, &(balance + amount));
+    }
+
+    fn internal_transfer(&mut self, token_id: &str, sender_id: &AccountId, receiver_id: &AccountId, amount: u128) {
+        assert_pool_token(token_id);
+        assert!(amount > 0, "The amount should be a positive number");
+        let key = (token_id.to_string(), sender_id.clone());
+        let balance = self.shares.get(&key).unwrap_or(0);
+        assert!(balance >= amount, "Not enough shares");
+        self.shares.insert(&key, &(balance - amount));
+        self.internal_deposit(token_id, receiver_id, amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    #[test]
+    fn shares_are_tracked_per_pool() {
+        testing_env!(VMContextBuilder::new()
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(1)
+            .build());
+        let mut contract = Contract::new();
+        contract.mint(":0".to_string(), accounts(0), U128(100));
+        contract.mft_transfer(":0".to_string(), accounts(1), U128(30), None);
+        assert_eq!(contract.mft_balance_of(":0".to_string(), accounts(0)).0, 70);
+        assert_eq!(contract.mft_balance_of(":0".to_string(), accounts(1)).0, 30);
+        assert_eq!(contract.mft_balance_of(":1".to_string(), accounts(1)).0, 0);
+    }
+}
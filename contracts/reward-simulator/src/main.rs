@@ -0,0 +1,367 @@
+//! Replays a farm schedule offline against the staking contract's own code. A scenario file
+//! holds the `save_editing_farm_info` arguments of one farm and a list of timestamped stakes,
+//! unstakes and claims. They run in order on a mocked blockchain, and the report lists each
+//! account's claimed and pending rewards, APR and share of the farm's reward budget.
+//!
+//! ```text
+//! reward-simulator scenario.json [--csv]
+//! ```
+
+use std::collections::BTreeMap;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use masterchef_staking::{Contract, FungibleTokenReceiver, MFTTokenReceiver};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Deserialize;
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{testing_env, AccountId, Gas};
+
+const CONTRACT_ID: &str = "masterchef.sim";
+const OWNER_ID: &str = "owner.sim";
+const FARM_ID: u16 = 0;
+const YEAR_MS: f64 = 365.0 * 24.0 * 3600.0 * 1000.0;
+
+/// arguments of `save_editing_farm_info`
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FarmConfig {
+    farm_type: u8,
+    token_id: AccountId,
+    token_decimal: u8,
+    pool_id: u64,
+    token_reward_rate: U128,
+    pool_reward_rate: U128,
+    reward_reward_rate: U128,
+    token_weight_rate: u16,
+    pool_weight_rate: u16,
+    max_token_vesting_duration: u64,
+    max_reward_vesting_duration: u64,
+    starting_at: u64,
+    ending_at: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde", tag = "action", rename_all = "snake_case")]
+enum Action {
+    /// `token_type` 0 stakes farm tokens, 1 LP shares of the farm's pool
+    Stake {
+        token_type: u8,
+        amount: U128,
+        #[serde(default)]
+        lock_duration: u64,
+    },
+    /// `token_type` 2 withdraws claimed rewards
+    Unstake { token_type: u8, amount: U128 },
+    Claim,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct Event {
+    /// ms
+    at: u64,
+    account: AccountId,
+    #[serde(flatten)]
+    action: Action,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct Scenario {
+    farm: FarmConfig,
+    /// farm tokens one LP share is worth, for the APR of LP stakes
+    #[serde(default = "default_lp_share_price")]
+    lp_share_price: f64,
+    /// ms, when pending rewards are read
+    until: u64,
+    events: Vec<Event>,
+}
+
+fn default_lp_share_price() -> f64 {
+    1.0
+}
+
+#[derive(Default)]
+struct AccountReport {
+    claimed: u128,
+    pending: u128,
+    /// principal in farm tokens integrated over time, token·ms
+    principal_ms: f64,
+    /// principal in farm tokens since the account's last event
+    principal: f64,
+    last_at: u64,
+}
+
+impl AccountReport {
+    fn total(&self) -> u128 {
+        self.claimed + self.pending
+    }
+
+    fn apr_bps(&self) -> Option<f64> {
+        if self.principal_ms == 0.0 {
+            None
+        } else {
+            Some(self.total() as f64 / (self.principal_ms / YEAR_MS) * 10000.0)
+        }
+    }
+}
+
+struct Report {
+    token_decimal: u8,
+    accounts: BTreeMap<AccountId, AccountReport>,
+    /// `None` for open-ended farms
+    budget: Option<u128>,
+    emitted: u128,
+}
+
+fn set_context(predecessor: &str, now: u64) {
+    testing_env!(VMContextBuilder::new()
+        .current_account_id(AccountId::new_unchecked(CONTRACT_ID.to_string()))
+        .predecessor_account_id(AccountId::new_unchecked(predecessor.to_string()))
+        .block_timestamp(now * 1_000_000)
+        .attached_deposit(1)
+        .prepaid_gas(Gas(300_000_000_000_000))
+        .build());
+}
+
+fn create_farm(farm: &FarmConfig) -> Contract {
+    set_context(OWNER_ID, 0);
+    let owner_id = AccountId::new_unchecked(OWNER_ID.to_string());
+    let mut contract = Contract::new(owner_id.clone());
+    contract.grant_role(masterchef_staking::Role::FarmManager, owner_id);
+    contract.save_editing_farm_info(
+        farm.farm_type,
+        farm.token_id.clone(),
+        farm.token_decimal,
+        farm.pool_id,
+        farm.token_reward_rate,
+        farm.pool_reward_rate,
+        farm.reward_reward_rate,
+        farm.token_weight_rate,
+        farm.pool_weight_rate,
+        farm.max_token_vesting_duration,
+        farm.max_reward_vesting_duration,
+        farm.starting_at,
+        farm.ending_at,
+    );
+    contract
+}
+
+fn apply(contract: &mut Contract, event: &Event) -> u128 {
+    let farm_info = contract.farm_infos.get(FARM_ID.into()).unwrap();
+    match &event.action {
+        Action::Stake {
+            token_type,
+            amount,
+            lock_duration,
+        } => {
+            let msg = format!(
+                "{{\"stake_type\":{},\"farm_id\":{},\"lock_duration\":{}}}",
+                token_type, FARM_ID, lock_duration
+            );
+            match token_type {
+                0 => {
+                    set_context(farm_info.token_id.as_str(), event.at);
+                    contract.ft_on_transfer(event.account.clone(), *amount, msg);
+                }
+                1 => {
+                    set_context(contract.ref_exchange_id.clone().as_str(), event.at);
+                    let token_id = format!(":{}", farm_info.pool_id);
+                    contract.mft_on_transfer(token_id, event.account.clone(), *amount, msg);
+                }
+                _ => panic!("Only token (0) and LP share (1) stakes are supported"),
+            }
+            0
+        }
+        Action::Unstake { token_type, amount } => {
+            set_context(event.account.as_str(), event.at);
+            contract.unstake(FARM_ID, *token_type, *amount);
+            0
+        }
+        Action::Claim => {
+            set_context(event.account.as_str(), event.at);
+            contract
+                .claim_all(Some(vec![FARM_ID]), None)
+                .claims
+                .iter()
+                .map(|summary| summary.token_reward.0 + summary.lp_reward.0 + summary.reward_reward.0)
+                .sum()
+        }
+    }
+}
+
+/// principal of the account in farm tokens
+fn principal_of(contract: &Contract, account_id: &AccountId, lp_share_price: f64) -> f64 {
+    let farm_info = contract.farm_infos.get(FARM_ID.into()).unwrap();
+    farm_info.stake_infos.get(account_id).map_or(0.0, |stake_info| {
+        stake_info.token_amount as f64 + stake_info.lp_share_amount as f64 * lp_share_price
+    })
+}
+
+fn simulate(scenario: &Scenario) -> Result<Report, String> {
+    let mut contract = create_farm(&scenario.farm);
+    let mut accounts: BTreeMap<AccountId, AccountReport> = BTreeMap::new();
+    let mut last_at = 0;
+
+    for (index, event) in scenario.events.iter().enumerate() {
+        if event.at < last_at || event.at > scenario.until {
+            return Err(format!(
+                "Event {} at {} is out of order, events must be sorted and end by {}",
+                index, event.at, scenario.until
+            ));
+        }
+        last_at = event.at;
+        let claimed = catch_unwind(AssertUnwindSafe(|| apply(&mut contract, event)))
+            .map_err(|_| format!("Event {} of {} at {} failed", index, event.account, event.at))?;
+
+        let report = accounts.entry(event.account.clone()).or_default();
+        report.principal_ms += report.principal * (event.at - report.last_at) as f64;
+        report.principal = principal_of(&contract, &event.account, scenario.lp_share_price);
+        report.last_at = event.at;
+        report.claimed += claimed;
+    }
+
+    set_context(OWNER_ID, scenario.until);
+    let farm_info = contract.farm_infos.get(FARM_ID.into()).unwrap();
+    for (account_id, report) in accounts.iter_mut() {
+        report.principal_ms += report.principal * (scenario.until - report.last_at) as f64;
+        if farm_info.stake_infos.get(account_id).is_some() {
+            report.pending = contract.get_token_claim_amount(FARM_ID, account_id.clone()).0
+                + contract.get_lp_claim_amount(FARM_ID, account_id.clone()).0
+                + contract.get_reward_claim_amount(FARM_ID, account_id.clone()).0;
+        }
+    }
+
+    let budget = if farm_info.ending_at == u64::MAX {
+        None
+    } else {
+        Some(farm_info.remaining_reward_budget() + farm_info.distributed_reward_amount)
+    };
+    let until = scenario.until.min(farm_info.ending_at);
+    let mut emitted = 0;
+    if farm_info.farm_type == 0 || farm_info.farm_type == 2 {
+        emitted += farm_info.emitted(farm_info.token_reward_rate, farm_info.starting_at, until);
+    }
+    if farm_info.farm_type == 1 || farm_info.farm_type == 2 {
+        emitted += farm_info.emitted(farm_info.pool_reward_rate, farm_info.starting_at, until);
+    }
+
+    Ok(Report {
+        token_decimal: farm_info.token_decimal,
+        accounts,
+        budget,
+        emitted,
+    })
+}
+
+/// `amount` in whole tokens with four decimals
+fn format_amount(amount: u128, decimals: u8) -> String {
+    let unit = 10u128.pow(u32::from(decimals));
+    let fraction = (amount % unit) * 10000 / unit;
+    format!("{}.{:04}", amount / unit, fraction)
+}
+
+fn budget_bps(amount: u128, budget: Option<u128>) -> Option<f64> {
+    budget.filter(|budget| *budget > 0).map(|budget| amount as f64 / budget as f64 * 10000.0)
+}
+
+fn format_bps(bps: Option<f64>) -> String {
+    bps.map_or("-".to_string(), |bps| format!("{:.2}%", bps / 100.0))
+}
+
+fn print_table(report: &Report) {
+    println!(
+        "{:<24} {:>20} {:>20} {:>20} {:>10} {:>10}",
+        "account", "claimed", "pending", "total", "APR", "budget"
+    );
+    let mut total = 0;
+    for (account_id, account) in &report.accounts {
+        total += account.total();
+        println!(
+            "{:<24} {:>20} {:>20} {:>20} {:>10} {:>10}",
+            account_id.as_str(),
+            format_amount(account.claimed, report.token_decimal),
+            format_amount(account.pending, report.token_decimal),
+            format_amount(account.total(), report.token_decimal),
+            format_bps(account.apr_bps()),
+            format_bps(budget_bps(account.total(), report.budget)),
+        );
+    }
+    println!();
+    println!("rewards     {}", format_amount(total, report.token_decimal));
+    println!("emitted     {}", format_amount(report.emitted, report.token_decimal));
+    match report.budget {
+        Some(budget) => println!(
+            "budget      {} ({} used)",
+            format_amount(budget, report.token_decimal),
+            format_bps(budget_bps(total, report.budget))
+        ),
+        None => println!("budget      unbounded"),
+    }
+}
+
+fn print_csv(report: &Report) {
+    println!("account,claimed,pending,total,apr_bps,budget_bps");
+    let bps = |bps: Option<f64>| bps.map_or(String::new(), |bps| format!("{:.0}", bps));
+    let mut total = 0;
+    for (account_id, account) in &report.accounts {
+        total += account.total();
+        println!(
+            "{},{},{},{},{},{}",
+            account_id,
+            account.claimed,
+            account.pending,
+            account.total(),
+            bps(account.apr_bps()),
+            bps(budget_bps(account.total(), report.budget)),
+        );
+    }
+    println!(",,,{},,{}", total, bps(budget_bps(total, report.budget)));
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let csv = args.iter().any(|arg| arg == "--csv");
+    let path = match args.iter().find(|arg| !arg.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: reward-simulator <scenario.json> [--csv]");
+            std::process::exit(2);
+        }
+    };
+
+    let result = std::fs::read_to_string(path)
+        .map_err(|err| format!("{}: {}", path, err))
+        .and_then(|json| near_sdk::serde_json::from_str::<Scenario>(&json).map_err(|err| format!("{}: {}", path, err)))
+        .and_then(|scenario| simulate(&scenario));
+    match result {
+        Ok(report) if csv => print_csv(&report),
+        Ok(report) => print_table(&report),
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example_scenario_stays_within_the_budget() {
+        let scenario: Scenario =
+            near_sdk::serde_json::from_str(include_str!("../scenario.json")).unwrap();
+        let report = simulate(&scenario).unwrap();
+        let total: u128 = report.accounts.values().map(AccountReport::total).sum();
+        assert!(total > 0);
+        assert!(total <= report.emitted);
+        assert!(report.emitted <= report.budget.unwrap());
+        // alice claimed once and pending rewards only count since then
+        let alice = &report.accounts[&AccountId::new_unchecked("alice.testnet".to_string())];
+        assert!(alice.claimed > 0 && alice.pending > 0);
+        // carol staked a third of the way in
+        let carol = &report.accounts[&AccountId::new_unchecked("carol.testnet".to_string())];
+        assert!(carol.apr_bps().unwrap() > 0.0);
+    }
+}